@@ -1,16 +1,36 @@
 use pyo3::prelude::*;
+use std::collections::{HashSet, VecDeque};
 use std::io::{BufRead, BufReader, Write};
 use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::str::FromStr;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::commands::{Command, CommandHandler};
 use crate::constants::{
     AudioFormatType, DocumentAddFileTransformation, DocumentPreparePaperSize,
     DocumentPrepareResolution, DocumentSaveType, FaxReceiveMode, FaxSendSpeed, PayloadType,
-    ToneType,
+    PayloadTypeMap, ToneType,
 };
-use crate::primitives::{Channels, ResourceId, SampleRate, ECM};
+use crate::events::{self, Event};
+use crate::fax::{
+    FaxLine, FaxReceiveHandler, FaxReceiveOptions, FaxReport, FaxSendJob, FaxSendOptions, ReceivedFax,
+};
+use crate::queue::FaxQueue;
+use crate::recording::CallRecording;
+use crate::relay::T38Relay;
+use crate::routing::RoutingGraph;
+use crate::primitives::{unix_time, Channels, FaxIdentifier, NetworkAddress, ResourceId, SampleRate, ECM};
+use crate::statistics::{RtpAlert, RtpMonitor, RtpStatistics, StatisticsError};
+use crate::trace::TraceRecorder;
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often long-running loops check for Python signals such as KeyboardInterrupt.
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Most unhandled lines kept for `take_unhandled`; older ones are dropped.
+const MAX_UNHANDLED_LINES: usize = 1000;
 
 pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
     let child_module = PyModule::new(parent_module.py(), "client")?;
@@ -36,6 +56,16 @@ struct GridborgClient {
     password: String,
     socket: Option<TcpStream>,
     reader: Option<BufReader<TcpStream>>,
+    /// Lines received on the connection, shared with the reader thread.
+    inbox: Option<Arc<Inbox>>,
+    /// Trace of the session being recorded, shared with the reader thread.
+    trace: Arc<Mutex<Option<TraceRecorder>>>,
+    /// Dynamic RTP payload codes used in this session.
+    #[pyo3(get, set)]
+    payload_types: PayloadTypeMap,
+    /// Audio routes set up in this session.
+    #[pyo3(get)]
+    routes: RoutingGraph,
     #[pyo3(get)]
     command_tag: u64,
 }
@@ -67,6 +97,10 @@ impl GridborgClient {
             password,
             socket: None,
             reader: None,
+            inbox: None,
+            trace: Arc::new(Mutex::new(None)),
+            payload_types: PayloadTypeMap::default(),
+            routes: RoutingGraph::new(),
             command_tag: 0,
         })
     }
@@ -80,6 +114,9 @@ impl GridborgClient {
 
                 let reader = BufReader::new(stream.try_clone()?);
                 self.socket = Some(stream);
+                let inbox = Arc::new(Inbox::default());
+                self.inbox = Some(inbox.clone());
+                let trace = self.trace.clone();
 
                 thread::spawn(move || {
                     let mut reader = reader;
@@ -90,8 +127,11 @@ impl GridborgClient {
                             break;
                         }
                         println!("Received: {}", line.trim());
+                        record(&trace, false, line.trim());
+                        inbox.deliver(line.trim().to_string());
                         line.clear();
                     }
+                    inbox.close();
                     println!("Connection closed.");
                 });
 
@@ -114,25 +154,272 @@ impl GridborgClient {
         }
     }
 
+    /// Record every line sent and received from now on to the trace file at `path`, for
+    /// replaying with `SessionTrace` and `ReplayServer`. Replaces any trace being recorded.
+    fn record_trace(&mut self, path: &str) -> PyResult<()> {
+        let recorder = TraceRecorder::create(Path::new(path))
+            .map_err(|e| pyo3::exceptions::PyIOError::new_err(format!("Failed to create trace: {e}")))?;
+        *self.trace.lock().unwrap_or_else(|e| e.into_inner()) = Some(recorder);
+        Ok(())
+    }
+
+    fn stop_trace(&mut self) {
+        *self.trace.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
     fn send_raw_command(&mut self, message: String) -> PyResult<u64> {
-        if let Some(ref mut stream) = self.socket {
-            let msg = format!("{} COMMANDTAG={}\n", message, self.command_tag);
-            let msg_bytes = msg.into_bytes();
-            stream.write_all(&msg_bytes).map_err(|e| {
-                pyo3::exceptions::PyIOError::new_err(format!("Failed to send message: {e}"))
-            })?;
-            let tag = self.command_tag;
-            self.command_tag += 1;
-            Ok(tag)
-        } else {
-            Err(pyo3::exceptions::PyRuntimeError::new_err(
-                "No active connection to send message",
-            ))
-        }
+        self.send_raw_commands(vec![message])
     }
 
     fn send_command(&mut self, command: Command) -> PyResult<u64> {
-        self.send_raw_command(command.into())
+        command.validate()?;
+        let mut commands = self.routes.prepare(&command)?;
+        commands.push(command);
+        self.send_routed_commands(commands)
+    }
+
+    /// Move the audio route from `source` to `old_sink` over to `new_sink`, keeping its
+    /// settings. Both commands are checked first and written to the server together.
+    fn reroute(&mut self, source: ResourceId, old_sink: ResourceId, new_sink: ResourceId) -> PyResult<()> {
+        let commands = self.routes.reroute(source, old_sink, new_sink)?;
+        self.send_routed_commands(commands)?;
+        Ok(())
+    }
+
+    /// Send several commands, such as the route changes of a `Conference`, in a single write.
+    /// All of them are checked, in order, before any is sent. Returns the tag of the last one.
+    fn send_commands(&mut self, commands: Vec<Command>) -> PyResult<u64> {
+        let mut routes = self.routes.clone();
+        let mut batch = Vec::new();
+        for command in commands {
+            command.validate()?;
+            for prepared in routes.prepare(&command)?.into_iter().chain([command]) {
+                routes.apply(&prepared);
+                batch.push(prepared);
+            }
+        }
+        self.send_routed_commands(batch)
+    }
+
+    /// Send `files` as a fax to `number` and wait for the outcome. Creates a front-end, a fax
+    /// and a document resource, runs the job on the events received and deletes the resources
+    /// when it ends. `on_progress` is called with each `FaxProgress`. A job that outlives
+    /// `options.job_timeout_ms` is aborted. Failed attempts are retried as `options.retry`
+    /// allows, on the same resources. On an error, including one raised by `on_progress` or
+    /// a KeyboardInterrupt, the call is cleared and the resources are deleted before it is raised.
+    #[pyo3(signature = (number, files, options=FaxSendOptions::default(), on_progress=None))]
+    fn send_fax(
+        &mut self,
+        py: Python<'_>,
+        number: &str,
+        files: Vec<String>,
+        options: FaxSendOptions,
+        on_progress: Option<PyObject>,
+    ) -> PyResult<FaxReport> {
+        let job_timeout = Duration::from_millis(options.job_timeout_ms);
+        let _listening = self.listen()?;
+        let mut created = Vec::new();
+        let mut job = None;
+        let result = (|| {
+            for command in [
+                Command::resource_create_frontend(None, None, None, None),
+                Command::resource_create_fax(),
+                Command::resource_create_document(),
+            ] {
+                created.push(self.create_resource(command)?);
+            }
+            let job = job.insert(FaxSendJob::new(number, files, options, created[0], created[1], created[2]));
+            self.run_fax_job(py, job, job_timeout, on_progress.as_ref())
+        })();
+        if let Err(err) = &result {
+            let cleanup = match &mut job {
+                Some(job) => job.release(&err.to_string()),
+                None => created.into_iter().rev().map(Command::resource_delete).collect(),
+            };
+            // the original error is the one worth raising
+            let _ = self.send_commands(cleanup);
+        }
+        result
+    }
+
+    /// Send the jobs of `queue` that are due now, one after the other, and record the outcome
+    /// of each attempt in the queue. `options` gives the settings not stored with the jobs and
+    /// `on_progress` is passed to `send_fax`. Returns the reports, after at most `limit` jobs
+    /// or once no job is due; call again later for jobs scheduled in the future.
+    #[pyo3(signature = (queue, options=FaxSendOptions::default(), on_progress=None, limit=None))]
+    fn process_fax_queue(
+        &mut self,
+        py: Python<'_>,
+        queue: &Bound<'_, FaxQueue>,
+        options: FaxSendOptions,
+        on_progress: Option<PyObject>,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<FaxReport>> {
+        let mut reports = Vec::new();
+        while limit.is_none_or(|limit| reports.len() < limit) {
+            let job = {
+                let mut queue = queue.borrow_mut();
+                let Some(id) = queue.next_due(unix_time()) else {
+                    break;
+                };
+                queue.begin(id, unix_time())?
+            };
+            let on_progress = on_progress.as_ref().map(|callback| callback.clone_ref(py));
+            match self.send_fax(py, job.number(), job.files(), job.options(&options), on_progress) {
+                Ok(report) => {
+                    queue.borrow_mut().finish(job.id(), &report, unix_time())?;
+                    reports.push(report);
+                }
+                Err(err) => {
+                    queue.borrow_mut().interrupt(job.id(), &err.to_string(), unix_time())?;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Answer fax calls and save the faxes received, as set out in `options`. Creates `lines`
+    /// accepting front-ends, each with its own fax and document resource, and calls
+    /// `callback(path, fax)` for every fax that ends, `path` being `None` if nothing was
+    /// saved. Returns after `max_faxes` faxes or `idle_timeout_ms` without any event on its
+    /// lines, clearing the calls still up and deleting the resources it created, also on an
+    /// error. Lines of other resources are left for `take_unhandled`.
+    #[pyo3(signature = (options=FaxReceiveOptions::default(), callback=None, lines=1, max_faxes=None, idle_timeout_ms=None))]
+    fn serve_fax(
+        &mut self,
+        py: Python<'_>,
+        options: FaxReceiveOptions,
+        callback: Option<PyObject>,
+        lines: usize,
+        max_faxes: Option<usize>,
+        idle_timeout_ms: Option<u64>,
+    ) -> PyResult<Vec<ReceivedFax>> {
+        let _listening = self.listen()?;
+        let mut handler = FaxReceiveHandler::new(options);
+        let mut received = Vec::new();
+        // resources of a line not complete yet, deleted with the lines if the rest fails
+        let mut partial = Vec::new();
+        let result = (|| {
+            for _ in 0..lines {
+                for command in [
+                    Command::resource_create_frontend(None, None, None, Some(true)),
+                    Command::resource_create_fax(),
+                    Command::resource_create_document(),
+                ] {
+                    partial.push(self.create_resource(command)?);
+                }
+                if let [call, fax, document] = partial[..] {
+                    handler.add_line(FaxLine { call, fax, document });
+                }
+                partial.clear();
+            }
+            let idle_timeout = idle_timeout_ms.map(Duration::from_millis);
+            let mut last_line = Instant::now();
+            while max_faxes.is_none_or(|max| received.len() < max) {
+                py.check_signals()?;
+                if idle_timeout.is_some_and(|timeout| last_line.elapsed() >= timeout) {
+                    break;
+                }
+                let Some(line) = self.next_line(Instant::now() + SIGNAL_POLL_INTERVAL)? else {
+                    continue;
+                };
+                match events::parse_event_with(&line, &self.payload_types) {
+                    Ok(event) if event_resource(&line).is_some_and(|id| handler.resources().contains(&id)) => {
+                        last_line = Instant::now();
+                        self.send_commands(handler.handle_event(&event))?;
+                    }
+                    _ => self.unhandled(line),
+                }
+                for fax in handler.take_received() {
+                    if let Some(callback) = &callback {
+                        callback.call1(py, (fax.path(), fax.clone()))?;
+                    }
+                    received.push(fax);
+                }
+            }
+            Ok(())
+        })();
+        let mut cleanup = handler.shutdown();
+        cleanup.extend(partial.into_iter().rev().map(Command::resource_delete));
+        let cleaned_up = self.send_commands(cleanup);
+        // an error from the loop is the one worth raising
+        result.and(cleaned_up).map(|_| received)
+    }
+
+    /// Record two calls to one stereo file at `path` on the server, `call_a` on the left
+    /// channel and `call_b` on the right. Creates the recorder, and with `warning_tone` a
+    /// player for the `RecorderWarningTone`, then starts recording. Pass the session's
+    /// events to the returned recording's `handle_event` and send the commands it returns.
+    /// If the recording cannot be started, the resources created for it are deleted before
+    /// the error is raised.
+    #[pyo3(signature = (call_a, call_b, path, warning_tone=false))]
+    fn record_call(
+        &mut self,
+        call_a: ResourceId,
+        call_b: ResourceId,
+        path: &str,
+        warning_tone: bool,
+    ) -> PyResult<CallRecording> {
+        let mut created = Vec::new();
+        let result = (|| {
+            created.push(self.create_resource(Command::resource_create_recorder())?);
+            if warning_tone {
+                created.push(self.create_resource(Command::resource_create_player())?);
+            }
+            let mut recording = CallRecording::new(call_a, call_b, created[0], created.get(1).copied(), path);
+            self.send_commands(recording.start())?;
+            Ok(recording)
+        })();
+        if result.is_err() {
+            let cleanup = created.into_iter().rev().map(Command::resource_delete).collect();
+            // the original error is the one worth raising
+            let _ = self.send_commands(cleanup);
+        }
+        result
+    }
+
+    /// Relay fax between two calls over T.38. Pass the session's events to the returned relay's
+    /// `handle_event` and send the commands it returns, so that either leg clearing clears the other.
+    fn t38_relay(&mut self, call_a: ResourceId, call_b: ResourceId) -> PyResult<T38Relay> {
+        let mut relay = T38Relay::new(call_a, call_b);
+        self.send_commands(relay.start())?;
+        Ok(relay)
+    }
+
+    /// Assign a dynamic RTP code (96-127) to a payload type for this session. RTP channels
+    /// started with that payload type announce the code, and events reporting it resolve to it.
+    fn map_payload_type(&mut self, code: u8, payload_type: PayloadType) -> PyResult<()> {
+        self.payload_types.insert(code, payload_type).map_err(|_| {
+            pyo3::exceptions::PyValueError::new_err(format!(
+                "{} is not a dynamic payload code (96-127)",
+                code
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// Parse an event line received from the server, using this session's payload type map.
+    fn parse_event(&self, line: &str) -> PyResult<Event> {
+        events::parse_event_with(line, &self.payload_types)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+
+    /// Wait for the server line carrying `COMMANDTAG=<tag>`, which may also have arrived
+    /// before. Other lines are left for `take_unhandled`. Raises TimeoutError if no reply
+    /// arrives in time.
+    fn wait_for_reply(&self, tag: u64) -> PyResult<String> {
+        self.reply(tag)?.ok_or_else(|| reply_timeout(&format!("command {}", tag)))
+    }
+
+    /// Lines received from the server that no method waiting for the server has used, oldest
+    /// first, such as events of resources created with the plain commands. The latest
+    /// `MAX_UNHANDLED_LINES` (1000) are kept.
+    fn take_unhandled(&self) -> Vec<String> {
+        match &self.inbox {
+            Some(inbox) => inbox.lock().unhandled.drain(..).collect(),
+            None => Vec::new(),
+        }
     }
 
     // Product Information Commands
@@ -431,9 +718,9 @@ impl GridborgClient {
     fn rtp_channel_start_receiving(
         &mut self,
         resource_id: ResourceId,
-        sender_control_address: Option<String>,
-        receiver_data_address: Option<String>,
-        receiver_control_address: Option<String>,
+        sender_control_address: Option<NetworkAddress>,
+        receiver_data_address: Option<NetworkAddress>,
+        receiver_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
@@ -457,10 +744,10 @@ impl GridborgClient {
     fn rtp_channel_start_sending(
         &mut self,
         resource_id: ResourceId,
-        receiver_data_address: String,
-        receiver_control_address: Option<String>,
-        sender_data_address: Option<String>,
-        sender_control_address: Option<String>,
+        receiver_data_address: NetworkAddress,
+        receiver_control_address: Option<NetworkAddress>,
+        sender_data_address: Option<NetworkAddress>,
+        sender_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
@@ -517,7 +804,7 @@ impl GridborgClient {
         document_resource_id: ResourceId,
         fax_mode: Option<FaxReceiveMode>,
         use_ecm: Option<ECM>,
-        csi: Option<String>,
+        csi: Option<FaxIdentifier>,
     ) -> PyResult<()> {
         CommandHandler::fax_receive(self, fax_resource_id, frontend_resource_id, document_resource_id, fax_mode, use_ecm, csi)
     }
@@ -530,7 +817,7 @@ impl GridborgClient {
         speed: Option<FaxSendSpeed>,
         use_ecm: Option<ECM>,
         header: Option<String>,
-        tsi: Option<String>,
+        tsi: Option<FaxIdentifier>,
     ) -> PyResult<()> {
         CommandHandler::fax_send(self, fax_resource_id, frontend_resource_id, document_resource_id, speed, use_ecm, header, tsi)
     }
@@ -623,10 +910,51 @@ impl GridborgClient {
     }
 
     // Miscellaneous Commands
-    fn get_rtp_statistics(&mut self, resource_id: ResourceId) -> PyResult<()> {
+    fn get_rtp_statistics(&mut self, resource_id: ResourceId) -> PyResult<RtpStatistics> {
         CommandHandler::get_rtp_statistics(self, resource_id)
     }
 
+    /// Fetch statistics of every channel in `resource_ids` once and check them with `monitor`.
+    fn poll_rtp_statistics(
+        &mut self,
+        monitor: &mut RtpMonitor,
+        resource_ids: Vec<ResourceId>,
+    ) -> PyResult<Vec<RtpAlert>> {
+        let mut alerts = Vec::new();
+        for resource_id in resource_ids {
+            let stats = CommandHandler::get_rtp_statistics(self, resource_id)?;
+            alerts.extend(monitor.update(stats));
+        }
+        Ok(alerts)
+    }
+
+    /// Poll `resource_ids` every `monitor.interval_ms`, calling `callback(alerts)` whenever
+    /// a threshold is exceeded. Runs `rounds` times, or until interrupted when `None`.
+    #[pyo3(signature = (monitor, resource_ids, callback, rounds=None))]
+    fn watch_rtp_statistics(
+        &mut self,
+        py: Python<'_>,
+        monitor: &mut RtpMonitor,
+        resource_ids: Vec<ResourceId>,
+        callback: PyObject,
+        rounds: Option<u32>,
+    ) -> PyResult<()> {
+        let mut round = 0;
+        while rounds.is_none_or(|rounds| round < rounds) {
+            if round > 0 {
+                let interval = Duration::from_millis(monitor.interval_ms());
+                py.allow_threads(|| thread::sleep(interval));
+                py.check_signals()?;
+            }
+            let alerts = self.poll_rtp_statistics(monitor, resource_ids.clone())?;
+            if !alerts.is_empty() {
+                callback.call1(py, (alerts,))?;
+            }
+            round += 1;
+        }
+        Ok(())
+    }
+
     // Not Commands
     fn print_details(&self) {
         println!(
@@ -997,16 +1325,16 @@ impl CommandHandler for GridborgClient {
     fn rtp_channel_start_receiving(
         &mut self,
         resource_id: ResourceId,
-        sender_control_address: Option<String>,
-        receiver_data_address: Option<String>,
-        receiver_control_address: Option<String>,
+        sender_control_address: Option<NetworkAddress>,
+        receiver_data_address: Option<NetworkAddress>,
+        receiver_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
         jitter_buffer_length_min: Option<u16>,
         jitter_buffer_length_max: Option<u16>,
     ) -> PyResult<()> {
-        self.send_command(Command::rtp_channel_start_receiving(
+        let command = Command::rtp_channel_start_receiving(
             resource_id,
             sender_control_address,
             receiver_data_address,
@@ -1016,22 +1344,23 @@ impl CommandHandler for GridborgClient {
             rtp_session_id,
             jitter_buffer_length_min,
             jitter_buffer_length_max,
-        ))?;
+        );
+        self.send_command(self.with_dynamic_code(command, payload_type))?;
         Ok(())
     }
 
     fn rtp_channel_start_sending(
         &mut self,
         resource_id: ResourceId,
-        receiver_data_address: String,
-        receiver_control_address: Option<String>,
-        sender_data_address: Option<String>,
-        sender_control_address: Option<String>,
+        receiver_data_address: NetworkAddress,
+        receiver_control_address: Option<NetworkAddress>,
+        sender_data_address: Option<NetworkAddress>,
+        sender_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
     ) -> PyResult<()> {
-        self.send_command(Command::rtp_channel_start_sending(
+        let command = Command::rtp_channel_start_sending(
             resource_id,
             receiver_data_address,
             receiver_control_address,
@@ -1040,7 +1369,8 @@ impl CommandHandler for GridborgClient {
             payload_type,
             rfc2833_payload_type,
             rtp_session_id,
-        ))?;
+        );
+        self.send_command(self.with_dynamic_code(command, payload_type))?;
         Ok(())
     }
 
@@ -1086,7 +1416,7 @@ impl CommandHandler for GridborgClient {
         document_resource_id: ResourceId,
         fax_mode: Option<FaxReceiveMode>,
         use_ecm: Option<ECM>,
-        csi: Option<String>,
+        csi: Option<FaxIdentifier>,
     ) -> PyResult<()> {
         self.send_command(Command::fax_receive(
             fax_resource_id,
@@ -1107,7 +1437,7 @@ impl CommandHandler for GridborgClient {
         speed: Option<FaxSendSpeed>,
         use_ecm: Option<ECM>,
         header: Option<String>,
-        tsi: Option<String>,
+        tsi: Option<FaxIdentifier>,
     ) -> PyResult<()> {
         self.send_command(Command::fax_send(
             fax_resource_id,
@@ -1252,8 +1582,319 @@ impl CommandHandler for GridborgClient {
     }
 
     // Miscellaneous Commands
-    fn get_rtp_statistics(&mut self, resource_id: ResourceId) -> PyResult<()> {
-        self.send_command(Command::get_rtp_statistics(resource_id))?;
-        Ok(())
+    fn get_rtp_statistics(&mut self, resource_id: ResourceId) -> PyResult<RtpStatistics> {
+        let _listening = self.listen()?;
+        let tag = self.send_command(Command::get_rtp_statistics(resource_id))?;
+        let reply = self
+            .reply(tag)?
+            .ok_or(StatisticsError::Timeout(REPLY_TIMEOUT.as_millis() as u64))?;
+        Ok(RtpStatistics::parse(resource_id, &reply)?)
+    }
+}
+
+impl GridborgClient {
+    fn with_dynamic_code(&self, command: Command, payload_type: Option<PayloadType>) -> Command {
+        match payload_type.and_then(|pt| self.payload_types.dynamic_code_for(&pt)) {
+            Some(code) => command.with_rtp_payload_type(code),
+            None => command,
+        }
+    }
+
+    /// Write several lines with consecutive command tags in a single write. Returns the tag
+    /// of the last line.
+    fn send_raw_commands(&mut self, messages: Vec<String>) -> PyResult<u64> {
+        if let Some(ref mut stream) = self.socket {
+            let lines: Vec<String> = (self.command_tag..)
+                .zip(&messages)
+                .map(|(tag, message)| format!("{} COMMANDTAG={}", message, tag))
+                .collect();
+            let msg: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+            // the trace stays locked during the write, so that replies cannot precede the lines in it
+            let mut trace = self.trace.lock().unwrap_or_else(|e| e.into_inner());
+            stream.write_all(msg.as_bytes()).map_err(|e| {
+                pyo3::exceptions::PyIOError::new_err(format!("Failed to send message: {e}"))
+            })?;
+            if let Some(recorder) = trace.as_mut() {
+                for line in &lines {
+                    let _ = recorder.record(true, line);
+                }
+            }
+            self.command_tag += messages.len() as u64;
+            Ok(self.command_tag - 1)
+        } else {
+            Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "No active connection to send message",
+            ))
+        }
+    }
+
+    fn inbox(&self) -> PyResult<&Arc<Inbox>> {
+        self.inbox.as_ref().ok_or_else(|| {
+            pyo3::exceptions::PyRuntimeError::new_err("No active connection to wait for a reply")
+        })
+    }
+
+    /// Start waiting for server lines: from now until the guard is dropped they are queued
+    /// for `next_line` and `wait_for_reply`, not left unhandled.
+    fn listen(&self) -> PyResult<Listening> {
+        let inbox = self.inbox()?.clone();
+        inbox.lock().listeners += 1;
+        Ok(Listening(inbox))
+    }
+
+    /// The reply to the command sent with `tag`, or `None` if it does not arrive within
+    /// `REPLY_TIMEOUT`.
+    fn reply(&self, tag: u64) -> PyResult<Option<String>> {
+        let _listening = self.listen()?;
+        let inbox = self.inbox()?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        let mut state = inbox.lock();
+        if let Some(line) = take_first(&mut state.unhandled, |line| is_reply_to(line, tag)) {
+            return Ok(Some(line));
+        }
+        loop {
+            if let Some(line) = take_first(&mut state.pending, |line| is_reply_to(line, tag)) {
+                return Ok(Some(line));
+            }
+            match inbox.wait(state, deadline)? {
+                Some(next) => state = next,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Next line from the server while listening, or `None` if none arrives before `deadline`.
+    fn next_line(&self, deadline: Instant) -> PyResult<Option<String>> {
+        let inbox = self.inbox()?;
+        let mut state = inbox.lock();
+        loop {
+            if let Some(line) = state.pending.pop_front() {
+                return Ok(Some(line));
+            }
+            match inbox.wait(state, deadline)? {
+                Some(next) => state = next,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Leave a line the current operation has no use for to `take_unhandled`.
+    fn unhandled(&self, line: String) {
+        if let Some(inbox) = &self.inbox {
+            inbox.lock().push_unhandled(line);
+        }
+    }
+
+    /// Send a `ResourceCreate*` command and return the id of the new resource, the first
+    /// argument of the reply.
+    fn create_resource(&mut self, command: Command) -> PyResult<ResourceId> {
+        let _listening = self.listen()?;
+        let name = command.to_string().split_whitespace().next().unwrap_or_default().to_string();
+        let tag = self.send_command(command)?;
+        let reply = self
+            .reply(tag)?
+            .ok_or_else(|| reply_timeout(&format!("{} (command {})", name, tag)))?;
+        reply
+            .split_whitespace()
+            .nth(1)
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| {
+                pyo3::exceptions::PyRuntimeError::new_err(format!("No resource id in reply: {}", reply))
+            })
+    }
+
+    /// Run a fax job until it reports, aborting it after `job_timeout`. Lines are waited for in
+    /// `SIGNAL_POLL_INTERVAL` slices, so that Python signals are handled; the caller listens.
+    /// An `Error` reply to a command of the job releases it.
+    fn run_fax_job(
+        &mut self,
+        py: Python<'_>,
+        job: &mut FaxSendJob,
+        job_timeout: Duration,
+        on_progress: Option<&PyObject>,
+    ) -> PyResult<FaxReport> {
+        let mut deadline = Instant::now() + job_timeout;
+        let mut commands = job.start();
+        let mut timed_out = false;
+        let mut sent = HashSet::new();
+        loop {
+            let first = self.command_tag;
+            self.send_commands(commands)?;
+            sent.extend(first..self.command_tag);
+            if let Some(callback) = on_progress {
+                for progress in job.take_progress() {
+                    callback.call1(py, (progress,))?;
+                }
+            }
+            if let Some(report) = job.report() {
+                return Ok(report);
+            }
+            py.check_signals()?;
+            commands = match self.next_line(deadline.min(Instant::now() + SIGNAL_POLL_INTERVAL))? {
+                Some(line) if reply_tag(&line).is_some_and(|tag| sent.remove(&tag)) => {
+                    match line.strip_prefix("Error ") {
+                        Some(error) => {
+                            let reason = error.split(" COMMANDTAG=").next().unwrap_or(error);
+                            job.release(&format!("server error: {}", reason.trim_matches('"')))
+                        }
+                        None => Vec::new(),
+                    }
+                }
+                Some(line) => match events::parse_event_with(&line, &self.payload_types) {
+                    Ok(event) if event_resource(&line).is_some_and(|id| job.resources().contains(&id)) => {
+                        job.handle_event(&event)
+                    }
+                    // replies to other commands and events of other resources
+                    _ => {
+                        self.unhandled(line);
+                        Vec::new()
+                    }
+                },
+                None if Instant::now() < deadline => Vec::new(),
+                // a fax in progress is aborted, and the server gets one reply timeout to confirm
+                None if !timed_out => {
+                    timed_out = true;
+                    deadline = Instant::now() + REPLY_TIMEOUT;
+                    job.time_out()
+                }
+                None => {
+                    return Err(pyo3::exceptions::PyTimeoutError::new_err(
+                        "Fax job did not end after FaxAbort",
+                    ))
+                }
+            };
+        }
+    }
+
+    /// Send commands already checked against the routing graph, then record them in it.
+    fn send_routed_commands(&mut self, commands: Vec<Command>) -> PyResult<u64> {
+        let tag = self.send_raw_commands(commands.iter().map(|c| c.to_string()).collect())?;
+        for command in &commands {
+            self.routes.apply(command);
+        }
+        Ok(tag)
+    }
+}
+
+/// Lines received on a connection, filled by its reader thread.
+///
+/// While a method listens for the server, lines are queued in `pending` for it; the others,
+/// and those a listening method has no use for, are kept in `unhandled`.
+#[derive(Default)]
+struct Inbox {
+    state: Mutex<InboxState>,
+    arrived: Condvar,
+}
+
+#[derive(Default)]
+struct InboxState {
+    /// Methods listening, counting nested ones such as `create_resource` within `send_fax`.
+    listeners: usize,
+    pending: VecDeque<String>,
+    unhandled: VecDeque<String>,
+    closed: bool,
+}
+
+impl InboxState {
+    fn push_unhandled(&mut self, line: String) {
+        if self.unhandled.len() == MAX_UNHANDLED_LINES {
+            self.unhandled.pop_front();
+        }
+        self.unhandled.push_back(line);
+    }
+}
+
+impl Inbox {
+    fn lock(&self) -> MutexGuard<'_, InboxState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn deliver(&self, line: String) {
+        let mut state = self.lock();
+        match state.listeners {
+            0 => state.push_unhandled(line),
+            _ => state.pending.push_back(line),
+        }
+        self.arrived.notify_all();
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.arrived.notify_all();
+    }
+
+    /// Wait for the next line to arrive; `None` once `deadline` has passed.
+    fn wait<'a>(
+        &'a self,
+        state: MutexGuard<'a, InboxState>,
+        deadline: Instant,
+    ) -> PyResult<Option<MutexGuard<'a, InboxState>>> {
+        if state.closed {
+            return Err(pyo3::exceptions::PyConnectionError::new_err(
+                "Connection closed while waiting for a reply",
+            ));
+        }
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if timeout.is_zero() {
+            return Ok(None);
+        }
+        let (state, _) = self.arrived.wait_timeout(state, timeout).unwrap_or_else(|e| e.into_inner());
+        Ok(Some(state))
+    }
+}
+
+/// Listening for server lines, from `GridborgClient::listen` until dropped. The lines the
+/// last listener leaves behind become unhandled.
+struct Listening(Arc<Inbox>);
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.listeners -= 1;
+        if state.listeners == 0 {
+            while let Some(line) = state.pending.pop_front() {
+                state.push_unhandled(line);
+            }
+        }
+    }
+}
+
+/// Remove and return the first line of `lines` that is `wanted`.
+fn take_first(lines: &mut VecDeque<String>, wanted: impl Fn(&str) -> bool) -> Option<String> {
+    let index = lines.iter().position(|line| wanted(line))?;
+    lines.remove(index)
+}
+
+/// The resource an event line is about; events start `E<name> <session> <resource>`.
+fn event_resource(line: &str) -> Option<ResourceId> {
+    line.split_whitespace().nth(2)?.parse().ok()
+}
+
+/// The error raised when no reply to `command` arrives within `REPLY_TIMEOUT`.
+fn reply_timeout(command: &str) -> PyErr {
+    pyo3::exceptions::PyTimeoutError::new_err(format!(
+        "No reply to {} within {} ms",
+        command,
+        REPLY_TIMEOUT.as_millis()
+    ))
+}
+
+/// The tag of the command a reply line answers.
+fn reply_tag(line: &str) -> Option<u64> {
+    line.split_whitespace()
+        .filter_map(|t| t.split_once('='))
+        .find(|(k, _)| k.eq_ignore_ascii_case("commandtag"))
+        .and_then(|(_, v)| v.parse().ok())
+}
+
+fn is_reply_to(line: &str, tag: u64) -> bool {
+    reply_tag(line) == Some(tag)
+}
+
+/// Add a line to the session trace, if one is being recorded. A trace is a debugging aid,
+/// so failing to write it does not interrupt the session.
+fn record(trace: &Mutex<Option<TraceRecorder>>, sent: bool, line: &str) {
+    if let Some(recorder) = trace.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        let _ = recorder.record(sent, line);
     }
 }
@@ -1,12 +1,20 @@
 use crate::constants::{
-    AudioFormatType, DocumentAddFileTransformation, DocumentPreparePaperSize,
-    DocumentPrepareResolution, DocumentSaveType, FaxReceiveMode, FaxSendSpeed, PayloadType,
-    ToneType,
+    AudioFormatType, ConstantWithDescription, DocumentAddFileTransformation,
+    DocumentPreparePaperSize, DocumentPrepareResolution, DocumentSaveType, FaxReceiveMode,
+    FaxSendSpeed, PayloadType, ToneType, ALL_DOCUMENT_ADD_FILE_TRANSFORMATIONS,
+    ALL_DOCUMENT_PREPARE_PAPER_SIZES, ALL_DOCUMENT_PREPARE_RESOLUTIONS, ALL_DOCUMENT_SAVE_TYPES,
+    ALL_FAX_RECEIVE_MODES, ALL_FAX_SEND_SPEEDS,
 };
-use crate::primitives::{Channels, ResourceId, SampleRate, ECM};
+use crate::events::tokenize;
+use crate::primitives::{Channels, FaxIdentifier, NetworkAddress, ResourceId, SampleRate, ECM};
+use crate::statistics::RtpStatistics;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::{PyModule, PyModuleMethods};
-use pyo3::{pyclass, pymethods, Bound, PyResult};
+use pyo3::{pyclass, pymethods, Bound, PyErr, PyResult};
+use std::collections::HashMap;
 use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
 
 pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
     let child_module = PyModule::new(parent_module.py(), "commands")?;
@@ -16,34 +24,168 @@ pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
     parent_module.add_submodule(&child_module)
 }
 
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CommandError {
+    #[error("{format} supports at most {max} channel(s), got {requested}")]
+    UnsupportedChannels {
+        format: &'static str,
+        max: u8,
+        requested: u8,
+    },
+    #[error("{format} does not support a sample rate of {sample_rate} Hz (supported: {supported:?})")]
+    UnsupportedSampleRate {
+        format: &'static str,
+        sample_rate: SampleRate,
+        supported: &'static [SampleRate],
+    },
+    #[error("file '{file_name}' does not match {format}: {expected}")]
+    FileExtensionMismatch {
+        format: &'static str,
+        file_name: String,
+        expected: &'static str,
+    },
+}
+
+impl From<CommandError> for PyErr {
+    fn from(err: CommandError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ParseCommandError {
+    #[error("empty command line")]
+    Empty,
+    #[error("unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("unexpected argument count for {0}")]
+    WrongArity(String),
+    #[error("bad value '{value}' for {command}")]
+    BadValue { command: String, value: String },
+    #[error("unknown option '{option}' for {command}")]
+    UnknownOption { command: String, option: String },
+    #[error("unterminated quote in '{0}'")]
+    UnterminatedQuote(String),
+}
+
+impl From<ParseCommandError> for PyErr {
+    fn from(err: ParseCommandError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// A string argument as written on the command line.
+///
+/// Values that are empty or contain whitespace, `"`, `=` or `#` are put in double quotes,
+/// with `"` and `\` escaped by a backslash and line breaks and tabs written as `\n`, `\r`
+/// and `\t`; other values are written as they are. `events::tokenize` reads them back.
+pub(crate) struct Quoted<'a>(pub(crate) &'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.0;
+        if !value.is_empty() && !value.chars().any(|c| c.is_whitespace() || matches!(c, '"' | '=' | '#')) {
+            return f.write_str(value);
+        }
+        f.write_str("\"")?;
+        for c in value.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Check the audio arguments of a play/record command against the capabilities of `audio_type`.
+fn validate_audio_format(
+    audio_type: Option<&AudioFormatType>,
+    file_name: Option<&str>,
+    sample_rate: Option<SampleRate>,
+    channels: Option<Channels>,
+) -> Result<(), CommandError> {
+    let Some(format) = audio_type else {
+        return Ok(());
+    };
+
+    if let Some(channels) = channels {
+        if !format.supports_channels(channels) {
+            return Err(CommandError::UnsupportedChannels {
+                format: format.name,
+                max: format.channels as u8,
+                requested: channels as u8,
+            });
+        }
+    }
+
+    if let Some(sample_rate) = sample_rate {
+        if !format.supports_sample_rate(sample_rate) {
+            return Err(CommandError::UnsupportedSampleRate {
+                format: format.name,
+                sample_rate,
+                supported: format.sample_rates,
+            });
+        }
+    }
+
+    if let Some(file_name) = file_name {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let (matches, expected) = match format.container() {
+            "WAV" => (extension.as_deref() == Some("wav"), "expected a .wav file"),
+            "VAP" => (extension.as_deref() == Some("vap"), "expected a .vap file"),
+            _ => (
+                !matches!(extension.as_deref(), Some("wav") | Some("vap")),
+                "headerless RAW data cannot be stored in a .wav or .vap file",
+            ),
+        };
+        if !matches {
+            return Err(CommandError::FileExtensionMismatch {
+                format: format.name,
+                file_name: file_name.to_string(),
+                expected,
+            });
+        }
+    }
+
+    Ok(())
+}
+
 // Product Information Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ProtocolVersion;
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct GetVersion;
 
 // Session Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Login {
-    username: String,
-    password: String,
+    pub(crate) username: String,
+    pub(crate) password: String,
     protocol_major_version: u8,
     protocol_minor_version: u8,
     protocol_revision: Option<u8>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Logout;
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Quit;
 
 // General Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreateFrontEnd {
     reg_incoming_ani: Option<String>,
     reg_incoming_dnis: Option<String>,
@@ -51,51 +193,51 @@ pub struct ResourceCreateFrontEnd {
     accepting: Option<bool>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreatePlayer;
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreateRecorder;
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreateTransportChannel {
     transport_type: String,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreateRtpChannel {
     in_band_dtmf_enabled: Option<bool>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreateSoundDevice {
     direction: String,
     device: Option<String>,
     buffers: Option<u8>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreateFax;
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreateDocument;
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceDelete {
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceGetStatus {
     resource_id: ResourceId,
 }
 
 // Front-end Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallMake {
-    resource_id: ResourceId,
-    address: String,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) address: String,
     timeout: Option<u32>, // Default: 30000 ms
     caller_number: Option<String>,
     caller_name: Option<String>,
@@ -103,42 +245,42 @@ pub struct CallMake {
     screen: Option<u8>,  // Default: 1
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallAnswer {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallClear {
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
     reason: Option<String>, // Optional reason string
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallTransferConsultation {
     resource_id1: ResourceId,
     resource_id2: ResourceId,
 }
 
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallTransferBlind {
     resource_id: ResourceId,
     address: String,
     use_h450: Option<u8>, // Default: 1
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallHold {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallRetrieve {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallSendDTMF {
     resource_id: ResourceId,
     dtmf_string: String,
@@ -147,24 +289,24 @@ pub struct CallSendDTMF {
     pause_duration: Option<u32>, // Default: 2000 ms
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallStopActivity {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallT38Relay {
     resource_id1: ResourceId,
     resource_id2: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallsSetAlertingType {
     resource_id: ResourceId,
     alerting_type: String,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallsSetAccepting {
     resource_id: ResourceId,
     accepting: bool,
@@ -172,7 +314,7 @@ pub struct CallsSetAccepting {
 
 // Player Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PlayFile {
     resource_id: ResourceId,
     file_name: String,
@@ -183,7 +325,7 @@ pub struct PlayFile {
     skip_bytes: Option<i64>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PlayStream {
     player_id: ResourceId,
     transport_channel_id: ResourceId,
@@ -192,7 +334,7 @@ pub struct PlayStream {
     buffer_optimum_size: Option<u32>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PlayTone {
     resource_id: ResourceId,
     frequency: Option<u16>,
@@ -202,14 +344,14 @@ pub struct PlayTone {
     duration: Option<u16>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PlayStop {
     resource_id: ResourceId,
 }
 
 // Recorder Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RecorderStartToFile {
     resource_id: ResourceId,
     file_name: String,
@@ -223,7 +365,7 @@ pub struct RecorderStartToFile {
     pause_if_empty: Option<bool>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RecorderStartToStream {
     recorder_id: ResourceId,
     transport_channel_id: ResourceId,
@@ -235,44 +377,48 @@ pub struct RecorderStartToStream {
     pause_if_empty: Option<bool>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RecorderStop {
     resource_id: ResourceId,
 }
 
 // RTP Channel Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelStartReceiving {
     resource_id: ResourceId,
-    sender_control_address: Option<String>,
-    receiver_data_address: Option<String>,
-    receiver_control_address: Option<String>,
+    sender_control_address: Option<NetworkAddress>,
+    receiver_data_address: Option<NetworkAddress>,
+    receiver_control_address: Option<NetworkAddress>,
     payload_type: Option<PayloadType>,
+    /// Dynamic RTP code of `payload_type`, set from the session's payload type map.
+    rtp_payload_type: Option<u8>,
     rfc2833_payload_type: Option<u8>,
     rtp_session_id: Option<u8>,
     jitter_buffer_length_min: Option<u16>,
     jitter_buffer_length_max: Option<u16>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelStartSending {
     resource_id: ResourceId,
-    receiver_data_address: String,
-    receiver_control_address: Option<String>,
-    sender_data_address: Option<String>,
-    sender_control_address: Option<String>,
+    receiver_data_address: NetworkAddress,
+    receiver_control_address: Option<NetworkAddress>,
+    sender_data_address: Option<NetworkAddress>,
+    sender_control_address: Option<NetworkAddress>,
     payload_type: Option<PayloadType>,
+    /// Dynamic RTP code of `payload_type`, set from the session's payload type map.
+    rtp_payload_type: Option<u8>,
     rfc2833_payload_type: Option<u8>,
     rtp_session_id: Option<u8>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelStop {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelSendDTMF {
     resource_id: ResourceId,
     dtmf_string: String,
@@ -283,61 +429,61 @@ pub struct RtpChannelSendDTMF {
 
 // Sound device Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SoundDeviceStart {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SoundDeviceStop {
     resource_id: ResourceId,
 }
 
 // Fax Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxReceive {
-    fax_resource_id: ResourceId,
+    pub(crate) fax_resource_id: ResourceId,
     frontend_resource_id: ResourceId,
     document_resource_id: ResourceId,
     fax_mode: Option<FaxReceiveMode>,
-    use_ecm: Option<ECM>,
-    csi: Option<String>,
+    pub(crate) use_ecm: Option<ECM>,
+    csi: Option<FaxIdentifier>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxSend {
-    fax_resource_id: ResourceId,
+    pub(crate) fax_resource_id: ResourceId,
     frontend_resource_id: ResourceId,
     document_resource_id: ResourceId,
-    speed: Option<FaxSendSpeed>,
-    use_ecm: Option<ECM>,
+    pub(crate) speed: Option<FaxSendSpeed>,
+    pub(crate) use_ecm: Option<ECM>,
     header: Option<String>,
-    tsi: Option<String>,
+    tsi: Option<FaxIdentifier>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxAbort {
     resource_id: ResourceId,
 }
 
 // Document Resource Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentAddFile {
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
     file_path: String,
     transformation: Option<DocumentAddFileTransformation>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentPrepare {
-    resource_id: ResourceId,
-    paper_size: Option<DocumentPreparePaperSize>,
-    resolution: Option<DocumentPrepareResolution>,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) paper_size: Option<DocumentPreparePaperSize>,
+    pub(crate) resolution: Option<DocumentPrepareResolution>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentSave {
     resource_id: ResourceId,
     file_path: String,
@@ -345,34 +491,34 @@ pub struct DocumentSave {
     document_type: Option<DocumentSaveType>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentClear {
     resource_id: ResourceId,
 }
 
 // Audio Routing and Audio Stream Monitoring Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AudioSend {
-    source_resource_id: ResourceId,
-    sink_resource_id: ResourceId,
-    source_channel: Option<u8>,
-    sink_channel: Option<u8>,
-    volume: Option<i16>,
-    auto_gain: Option<bool>,
-    auto_gain_resolution: Option<u16>,
-    auto_gain_rise_time: Option<u16>,
-    auto_gain_fall_time: Option<u16>,
-    auto_gain_kill_time: Option<u16>,
+    pub(crate) source_resource_id: ResourceId,
+    pub(crate) sink_resource_id: ResourceId,
+    pub(crate) source_channel: Option<u8>,
+    pub(crate) sink_channel: Option<u8>,
+    pub(crate) volume: Option<i16>,
+    pub(crate) auto_gain: Option<bool>,
+    pub(crate) auto_gain_resolution: Option<u16>,
+    pub(crate) auto_gain_rise_time: Option<u16>,
+    pub(crate) auto_gain_fall_time: Option<u16>,
+    pub(crate) auto_gain_kill_time: Option<u16>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AudioCancel {
-    source_resource_id: ResourceId,
-    sink_resource_id: ResourceId,
+    pub(crate) source_resource_id: ResourceId,
+    pub(crate) sink_resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AudioLevelNotificationSend {
     resource_id: ResourceId,
     resolution: Option<u16>,
@@ -383,30 +529,30 @@ pub struct AudioLevelNotificationSend {
     silence_timer: Option<u16>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AudioLevelNotificationCancel {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct InBandSignalingDetectionEnable {
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct InBandSignalingDetectionDisable {
     resource_id: ResourceId,
 }
 
 // Miscellaneous Commands
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct GetRtpStatistics {
     resource_id: ResourceId,
 }
 
 #[pyclass(str)]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Command {
     ProtocolVersion(ProtocolVersion),
     GetVersion(GetVersion),
@@ -813,9 +959,9 @@ impl Command {
     #[staticmethod]
     pub fn rtp_channel_start_receiving(
         resource_id: ResourceId,
-        sender_control_address: Option<String>,
-        receiver_data_address: Option<String>,
-        receiver_control_address: Option<String>,
+        sender_control_address: Option<NetworkAddress>,
+        receiver_data_address: Option<NetworkAddress>,
+        receiver_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
@@ -828,6 +974,7 @@ impl Command {
             receiver_data_address,
             receiver_control_address,
             payload_type,
+            rtp_payload_type: None,
             rfc2833_payload_type,
             rtp_session_id,
             jitter_buffer_length_min,
@@ -838,10 +985,10 @@ impl Command {
     #[staticmethod]
     pub fn rtp_channel_start_sending(
         resource_id: ResourceId,
-        receiver_data_address: String,
-        receiver_control_address: Option<String>,
-        sender_data_address: Option<String>,
-        sender_control_address: Option<String>,
+        receiver_data_address: NetworkAddress,
+        receiver_control_address: Option<NetworkAddress>,
+        sender_data_address: Option<NetworkAddress>,
+        sender_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
@@ -853,6 +1000,7 @@ impl Command {
             sender_data_address,
             sender_control_address,
             payload_type,
+            rtp_payload_type: None,
             rfc2833_payload_type,
             rtp_session_id,
         })
@@ -899,7 +1047,7 @@ impl Command {
         document_resource_id: ResourceId,
         fax_mode: Option<FaxReceiveMode>,
         use_ecm: Option<ECM>,
-        csi: Option<String>,
+        csi: Option<FaxIdentifier>,
     ) -> Self {
         Command::FaxReceive(FaxReceive {
             fax_resource_id,
@@ -919,7 +1067,7 @@ impl Command {
         speed: Option<FaxSendSpeed>,
         use_ecm: Option<ECM>,
         header: Option<String>,
-        tsi: Option<String>,
+        tsi: Option<FaxIdentifier>,
     ) -> Self {
         Command::FaxSend(FaxSend {
             fax_resource_id,
@@ -1061,6 +1209,17 @@ impl Command {
     pub fn get_rtp_statistics(resource_id: ResourceId) -> Self {
         Command::GetRtpStatistics(GetRtpStatistics { resource_id })
     }
+
+    /// Parse a command line, e.g. one read from a client by a proxy or a mock server.
+    #[staticmethod]
+    #[pyo3(name = "parse")]
+    fn py_parse(line: &str) -> PyResult<Self> {
+        Ok(parse_command(line)?)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
 }
 
 impl fmt::Display for Command {
@@ -1076,8 +1235,8 @@ impl fmt::Display for Command {
                     write!(
                         f,
                         "Login {} {} {} {} {}",
-                        login.username,
-                        login.password,
+                        Quoted(&login.username),
+                        Quoted(&login.password),
                         login.protocol_major_version,
                         login.protocol_minor_version,
                         revision
@@ -1086,8 +1245,8 @@ impl fmt::Display for Command {
                     write!(
                         f,
                         "Login {} {} {} {}",
-                        login.username,
-                        login.password,
+                        Quoted(&login.username),
+                        Quoted(&login.password),
                         login.protocol_major_version,
                         login.protocol_minor_version
                     )
@@ -1100,13 +1259,13 @@ impl fmt::Display for Command {
             Command::ResourceCreateFrontEnd(cmd) => {
                 write!(f, "ResourceCreateFrontEnd")?;
                 if let Some(ani) = &cmd.reg_incoming_ani {
-                    write!(f, " RegIncomingANI={}", ani)?;
+                    write!(f, " RegIncomingANI={}", Quoted(ani))?;
                 }
                 if let Some(dnis) = &cmd.reg_incoming_dnis {
-                    write!(f, " RegIncomingDNIS={}", dnis)?;
+                    write!(f, " RegIncomingDNIS={}", Quoted(dnis))?;
                 }
                 if let Some(rdn) = &cmd.reg_incoming_rdn {
-                    write!(f, " RegIncomingRDN={}", rdn)?;
+                    write!(f, " RegIncomingRDN={}", Quoted(rdn))?;
                 }
                 if let Some(accepting) = cmd.accepting {
                     write!(f, " Accepting={}", if accepting { 1 } else { 0 })?;
@@ -1116,7 +1275,7 @@ impl fmt::Display for Command {
             Command::ResourceCreatePlayer(_) => write!(f, "ResourceCreatePlayer"),
             Command::ResourceCreateRecorder(_) => write!(f, "ResourceCreateRecorder"),
             Command::ResourceCreateTransportChannel(cmd) => {
-                write!(f, "ResourceCreateTransportChannel {}", cmd.transport_type)
+                write!(f, "ResourceCreateTransportChannel {}", Quoted(&cmd.transport_type))
             }
             Command::ResourceCreateRtpChannel(cmd) => {
                 write!(f, "ResourceCreateRtpChannel")?;
@@ -1126,9 +1285,9 @@ impl fmt::Display for Command {
                 Ok(())
             }
             Command::ResourceCreateSoundDevice(cmd) => {
-                write!(f, "ResourceCreateSoundDevice Direction={}", cmd.direction)?;
+                write!(f, "ResourceCreateSoundDevice Direction={}", Quoted(&cmd.direction))?;
                 if let Some(device) = &cmd.device {
-                    write!(f, " Device={}", device)?;
+                    write!(f, " Device={}", Quoted(device))?;
                 }
                 if let Some(buffers) = cmd.buffers {
                     write!(f, " Buffers={}", buffers)?;
@@ -1142,15 +1301,15 @@ impl fmt::Display for Command {
 
             // Front-end Resource Commands
             Command::CallMake(cmd) => {
-                write!(f, "CallMake {} {}", cmd.resource_id, cmd.address)?;
+                write!(f, "CallMake {} {}", cmd.resource_id, Quoted(&cmd.address))?;
                 if let Some(timeout) = cmd.timeout {
                     write!(f, " TimeOut={}", timeout)?;
                 }
                 if let Some(ref num) = cmd.caller_number {
-                    write!(f, " CallerNumber={}", num)?;
+                    write!(f, " CallerNumber={}", Quoted(num))?;
                 }
                 if let Some(ref name) = cmd.caller_name {
-                    write!(f, " CallerName={}", name)?;
+                    write!(f, " CallerName={}", Quoted(name))?;
                 }
                 if let Some(privacy) = cmd.privacy {
                     write!(f, " Privacy={}", privacy)?;
@@ -1164,7 +1323,7 @@ impl fmt::Display for Command {
             Command::CallClear(cmd) => {
                 write!(f, "CallClear {}", cmd.resource_id)?;
                 if let Some(ref reason) = cmd.reason {
-                    write!(f, " Reason={}", reason)?;
+                    write!(f, " Reason={}", Quoted(reason))?;
                 }
                 Ok(())
             }
@@ -1176,7 +1335,7 @@ impl fmt::Display for Command {
                 )
             }
             Command::CallTransferBlind(cmd) => {
-                write!(f, "CallTransferBlind {} {}", cmd.resource_id, cmd.address)?;
+                write!(f, "CallTransferBlind {} {}", cmd.resource_id, Quoted(&cmd.address))?;
                 if let Some(use_h450) = cmd.use_h450 {
                     write!(f, " UseH450={}", use_h450)?;
                 }
@@ -1185,7 +1344,7 @@ impl fmt::Display for Command {
             Command::CallHold(cmd) => write!(f, "CallHold {}", cmd.resource_id),
             Command::CallRetrieve(cmd) => write!(f, "CallRetrieve {}", cmd.resource_id),
             Command::CallSendDTMF(cmd) => {
-                write!(f, "CallSendDTMF {} {}", cmd.resource_id, cmd.dtmf_string)?;
+                write!(f, "CallSendDTMF {} {}", cmd.resource_id, Quoted(&cmd.dtmf_string))?;
                 if let Some(duration) = cmd.duration {
                     write!(f, " Duration={}", duration)?;
                 }
@@ -1205,7 +1364,7 @@ impl fmt::Display for Command {
                 write!(
                     f,
                     "CallsSetAlertingType {} {}",
-                    cmd.resource_id, cmd.alerting_type
+                    cmd.resource_id, Quoted(&cmd.alerting_type)
                 )
             }
             Command::CallsSetAccepting(cmd) => {
@@ -1219,7 +1378,7 @@ impl fmt::Display for Command {
 
             // Player Resource Commands
             Command::PlayFile(cmd) => {
-                write!(f, "PlayFile {} {}", cmd.resource_id, cmd.file_name)?;
+                write!(f, "PlayFile {} {}", cmd.resource_id, Quoted(&cmd.file_name))?;
                 if let Some(audio_type) = &cmd.audio_type {
                     write!(f, " Type={}", audio_type.name)?;
                 }
@@ -1263,7 +1422,7 @@ impl fmt::Display for Command {
                     write!(f, " Frequency2={}", frequency2)?;
                 }
                 if let Some(tone) = &cmd.tone {
-                    write!(f, " Tone={}", tone.name)?;
+                    write!(f, " Tone={}", Quoted(tone.name))?;
                 }
                 if let Some(volume) = cmd.volume {
                     write!(f, " Volume={}", volume)?;
@@ -1282,7 +1441,7 @@ impl fmt::Display for Command {
                 write!(
                     f,
                     "RecorderStartToFile {} {}",
-                    cmd.resource_id, cmd.file_name
+                    cmd.resource_id, Quoted(&cmd.file_name)
                 )?;
                 if let Some(audio_type) = &cmd.audio_type {
                     write!(f, " Type={}", audio_type.name)?;
@@ -1355,6 +1514,9 @@ impl fmt::Display for Command {
                 if let Some(payload) = &cmd.payload_type {
                     write!(f, " PayloadType={}", payload.name)?;
                 }
+                if let Some(code) = cmd.rtp_payload_type {
+                    write!(f, " RtpPayloadType={}", code)?;
+                }
                 if let Some(rfc) = cmd.rfc2833_payload_type {
                     write!(f, " RFC2833PayloadType={}", rfc)?;
                 }
@@ -1387,6 +1549,9 @@ impl fmt::Display for Command {
                 if let Some(payload) = &cmd.payload_type {
                     write!(f, " PayloadType={}", payload.name)?;
                 }
+                if let Some(code) = cmd.rtp_payload_type {
+                    write!(f, " RtpPayloadType={}", code)?;
+                }
                 if let Some(rfc) = cmd.rfc2833_payload_type {
                     write!(f, " RFC2833PayloadType={}", rfc)?;
                 }
@@ -1402,7 +1567,7 @@ impl fmt::Display for Command {
                 write!(
                     f,
                     "RtpChannelSendDTMF {} {}",
-                    cmd.resource_id, cmd.dtmf_string
+                    cmd.resource_id, Quoted(&cmd.dtmf_string)
                 )?;
                 if let Some(duration) = cmd.duration {
                     write!(f, " Duration={}", duration)?;
@@ -1438,7 +1603,7 @@ impl fmt::Display for Command {
                     write!(f, " UseECM={}", *use_ecm as u16)?;
                 }
                 if let Some(csi) = &cmd.csi {
-                    write!(f, " CSI={}", csi)?;
+                    write!(f, " CSI={}", Quoted(csi.as_str()))?;
                 }
                 Ok(())
             }
@@ -1455,10 +1620,10 @@ impl fmt::Display for Command {
                     write!(f, " UseECM={}", *use_ecm as u16)?;
                 }
                 if let Some(header) = &cmd.header {
-                    write!(f, " Header={}", header)?;
+                    write!(f, " Header={}", Quoted(header))?;
                 }
                 if let Some(tsi) = &cmd.tsi {
-                    write!(f, " TSI={}", tsi)?;
+                    write!(f, " TSI={}", Quoted(tsi.as_str()))?;
                 }
                 Ok(())
             }
@@ -1468,7 +1633,7 @@ impl fmt::Display for Command {
 
             // Document Resource Commands
             Command::DocumentAddFile(cmd) => {
-                write!(f, "DocumentAddFile {} {}", cmd.resource_id, cmd.file_path)?;
+                write!(f, "DocumentAddFile {} {}", cmd.resource_id, Quoted(&cmd.file_path))?;
                 if let Some(transformation) = &cmd.transformation {
                     write!(f, " Transformation={}", transformation.name)?;
                 }
@@ -1485,7 +1650,7 @@ impl fmt::Display for Command {
                 Ok(())
             }
             Command::DocumentSave(cmd) => {
-                write!(f, "DocumentSave {} {}", cmd.resource_id, cmd.file_path)?;
+                write!(f, "DocumentSave {} {}", cmd.resource_id, Quoted(&cmd.file_path))?;
                 if let Some(multipage) = cmd.multipage {
                     write!(f, " Multipage={}", if multipage { 1 } else { 0 })?;
                 }
@@ -1581,12 +1746,507 @@ impl fmt::Display for Command {
     }
 }
 
+impl Command {
+    /// Attach the dynamic RTP code of the payload type to an `RtpChannelStart*` command;
+    /// other commands are returned unchanged.
+    pub fn with_rtp_payload_type(mut self, code: u8) -> Self {
+        match &mut self {
+            Command::RtpChannelStartReceiving(cmd) => cmd.rtp_payload_type = Some(code),
+            Command::RtpChannelStartSending(cmd) => cmd.rtp_payload_type = Some(code),
+            _ => {}
+        }
+        self
+    }
+
+    /// The resources the command acts on, in argument order; empty for session commands
+    /// and for the `ResourceCreate*` commands.
+    pub(crate) fn resource_ids(&self) -> Vec<ResourceId> {
+        match self {
+            Command::ProtocolVersion(_)
+            | Command::GetVersion(_)
+            | Command::Login(_)
+            | Command::Logout(_)
+            | Command::Quit(_)
+            | Command::ResourceCreateFrontEnd(_)
+            | Command::ResourceCreatePlayer(_)
+            | Command::ResourceCreateRecorder(_)
+            | Command::ResourceCreateTransportChannel(_)
+            | Command::ResourceCreateRtpChannel(_)
+            | Command::ResourceCreateSoundDevice(_)
+            | Command::ResourceCreateFax(_)
+            | Command::ResourceCreateDocument(_) => Vec::new(),
+            Command::ResourceDelete(cmd) => vec![cmd.resource_id],
+            Command::ResourceGetStatus(cmd) => vec![cmd.resource_id],
+            Command::CallMake(cmd) => vec![cmd.resource_id],
+            Command::CallAnswer(cmd) => vec![cmd.resource_id],
+            Command::CallClear(cmd) => vec![cmd.resource_id],
+            Command::CallTransferConsultation(cmd) => vec![cmd.resource_id1, cmd.resource_id2],
+            Command::CallTransferBlind(cmd) => vec![cmd.resource_id],
+            Command::CallHold(cmd) => vec![cmd.resource_id],
+            Command::CallRetrieve(cmd) => vec![cmd.resource_id],
+            Command::CallSendDTMF(cmd) => vec![cmd.resource_id],
+            Command::CallStopActivity(cmd) => vec![cmd.resource_id],
+            Command::CallT38Relay(cmd) => vec![cmd.resource_id1, cmd.resource_id2],
+            Command::CallsSetAlertingType(cmd) => vec![cmd.resource_id],
+            Command::CallsSetAccepting(cmd) => vec![cmd.resource_id],
+            Command::PlayFile(cmd) => vec![cmd.resource_id],
+            Command::PlayStream(cmd) => vec![cmd.player_id, cmd.transport_channel_id],
+            Command::PlayTone(cmd) => vec![cmd.resource_id],
+            Command::PlayStop(cmd) => vec![cmd.resource_id],
+            Command::RecorderStartToFile(cmd) => vec![cmd.resource_id],
+            Command::RecorderStartToStream(cmd) => vec![cmd.recorder_id, cmd.transport_channel_id],
+            Command::RecorderStop(cmd) => vec![cmd.resource_id],
+            Command::RtpChannelStartReceiving(cmd) => vec![cmd.resource_id],
+            Command::RtpChannelStartSending(cmd) => vec![cmd.resource_id],
+            Command::RtpChannelStop(cmd) => vec![cmd.resource_id],
+            Command::RtpChannelSendDTMF(cmd) => vec![cmd.resource_id],
+            Command::SoundDeviceStart(cmd) => vec![cmd.resource_id],
+            Command::SoundDeviceStop(cmd) => vec![cmd.resource_id],
+            Command::FaxReceive(cmd) => vec![cmd.fax_resource_id, cmd.frontend_resource_id, cmd.document_resource_id],
+            Command::FaxSend(cmd) => vec![cmd.fax_resource_id, cmd.frontend_resource_id, cmd.document_resource_id],
+            Command::FaxAbort(cmd) => vec![cmd.resource_id],
+            Command::DocumentAddFile(cmd) => vec![cmd.resource_id],
+            Command::DocumentPrepare(cmd) => vec![cmd.resource_id],
+            Command::DocumentSave(cmd) => vec![cmd.resource_id],
+            Command::DocumentClear(cmd) => vec![cmd.resource_id],
+            Command::AudioSend(cmd) => vec![cmd.source_resource_id, cmd.sink_resource_id],
+            Command::AudioCancel(cmd) => vec![cmd.source_resource_id, cmd.sink_resource_id],
+            Command::AudioLevelNotificationSend(cmd) => vec![cmd.resource_id],
+            Command::AudioLevelNotificationCancel(cmd) => vec![cmd.resource_id],
+            Command::InBandSignalingDetectionEnable(cmd) => vec![cmd.resource_id],
+            Command::InBandSignalingDetectionDisable(cmd) => vec![cmd.resource_id],
+            Command::GetRtpStatistics(cmd) => vec![cmd.resource_id],
+        }
+    }
+
+    /// Validate the command arguments client-side, before the command is sent to the server.
+    pub fn validate(&self) -> Result<(), CommandError> {
+        match self {
+            Command::PlayFile(cmd) => validate_audio_format(
+                cmd.audio_type.as_ref(),
+                Some(&cmd.file_name),
+                cmd.sample_rate,
+                cmd.channels,
+            ),
+            Command::PlayStream(cmd) => {
+                validate_audio_format(cmd.audio_type.as_ref(), None, cmd.sample_rate, None)
+            }
+            Command::RecorderStartToFile(cmd) => validate_audio_format(
+                cmd.audio_type.as_ref(),
+                Some(&cmd.file_name),
+                cmd.sample_rate,
+                cmd.channels,
+            ),
+            Command::RecorderStartToStream(cmd) => {
+                validate_audio_format(cmd.audio_type.as_ref(), None, cmd.sample_rate, None)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 impl From<Command> for String {
     fn from(val: Command) -> Self {
         val.to_string()
     }
 }
 
+/// The arguments of a command line: positional arguments are taken in order, then the
+/// remaining `Key=Value` options by (case-insensitive) key.
+struct CommandArgs {
+    name: String,
+    tokens: std::vec::IntoIter<String>,
+    options: Option<HashMap<String, String>>,
+}
+
+impl CommandArgs {
+    fn bad_value(&self, value: &str) -> ParseCommandError {
+        ParseCommandError::BadValue {
+            command: self.name.clone(),
+            value: value.to_string(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseCommandError> {
+        self.tokens
+            .next()
+            .ok_or_else(|| ParseCommandError::WrongArity(self.name.clone()))
+    }
+
+    fn pos<T: FromStr>(&mut self) -> Result<T, ParseCommandError> {
+        let value = self.string()?;
+        value.parse().map_err(|_| self.bad_value(&value))
+    }
+
+    /// A trailing positional argument that may be left out.
+    fn opt_pos<T: FromStr>(&mut self) -> Result<Option<T>, ParseCommandError> {
+        match self.tokens.as_slice().first() {
+            Some(token) if !token.contains('=') => self.pos().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn options(&mut self) -> Result<&mut HashMap<String, String>, ParseCommandError> {
+        if self.options.is_none() {
+            let mut options = HashMap::new();
+            for token in self.tokens.by_ref() {
+                let (key, value) = token
+                    .split_once('=')
+                    .ok_or_else(|| ParseCommandError::WrongArity(self.name.clone()))?;
+                options.insert(key.to_ascii_lowercase(), value.to_string());
+            }
+            self.options = Some(options);
+        }
+        Ok(self.options.as_mut().unwrap())
+    }
+
+    fn opt_string(&mut self, key: &str) -> Result<Option<String>, ParseCommandError> {
+        Ok(self.options()?.remove(&key.to_ascii_lowercase()))
+    }
+
+    fn opt<T: FromStr>(&mut self, key: &str) -> Result<Option<T>, ParseCommandError> {
+        self.opt_string(key)?
+            .map(|value| value.parse().map_err(|_| self.bad_value(&value)))
+            .transpose()
+    }
+
+    /// A `0`/`1` option.
+    fn opt_flag(&mut self, key: &str) -> Result<Option<bool>, ParseCommandError> {
+        self.opt_string(key)?
+            .map(|value| parse_flag(&value).ok_or_else(|| self.bad_value(&value)))
+            .transpose()
+    }
+
+    fn opt_channels(&mut self, key: &str) -> Result<Option<Channels>, ParseCommandError> {
+        self.opt::<u16>(key)?
+            .map(|value| Channels::try_from(value).map_err(|_| self.bad_value(&value.to_string())))
+            .transpose()
+    }
+
+    /// An option naming one of the constants of `table`.
+    fn opt_constant(
+        &mut self,
+        key: &str,
+        table: &[ConstantWithDescription],
+    ) -> Result<Option<ConstantWithDescription>, ParseCommandError> {
+        self.opt_string(key)?
+            .map(|value| {
+                table
+                    .iter()
+                    .find(|c| c.name.eq_ignore_ascii_case(&value))
+                    .copied()
+                    .ok_or_else(|| self.bad_value(&value))
+            })
+            .transpose()
+    }
+
+    /// Fail on arguments that no parameter of the command took.
+    fn finish(mut self) -> Result<(), ParseCommandError> {
+        match self.options()?.keys().next().cloned() {
+            Some(option) => Err(ParseCommandError::UnknownOption {
+                command: self.name,
+                option,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a command line as written by `Command`'s `Display`.
+pub fn parse_command(line: &str) -> Result<Command, ParseCommandError> {
+    line.parse()
+}
+
+impl FromStr for Command {
+    type Err = ParseCommandError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(line)
+            .map_err(|_| ParseCommandError::UnterminatedQuote(line.to_string()))?
+            .into_iter();
+        let name = tokens.next().ok_or(ParseCommandError::Empty)?;
+        let mut args = CommandArgs {
+            name,
+            tokens,
+            options: None,
+        };
+        let a = &mut args;
+
+        let command = match a.name.as_str() {
+            // Product Information Commands
+            "ProtocolVersion" => Command::ProtocolVersion(ProtocolVersion),
+            "GetVersion" => Command::GetVersion(GetVersion),
+
+            // Session Commands
+            "Login" => Command::Login(Login {
+                username: a.string()?,
+                password: a.string()?,
+                protocol_major_version: a.pos()?,
+                protocol_minor_version: a.pos()?,
+                protocol_revision: a.opt_pos()?,
+            }),
+            "Logout" => Command::Logout(Logout),
+            "Quit" => Command::Quit(Quit),
+
+            // General Resource Commands
+            "ResourceCreateFrontEnd" => Command::ResourceCreateFrontEnd(ResourceCreateFrontEnd {
+                reg_incoming_ani: a.opt_string("RegIncomingANI")?,
+                reg_incoming_dnis: a.opt_string("RegIncomingDNIS")?,
+                reg_incoming_rdn: a.opt_string("RegIncomingRDN")?,
+                accepting: a.opt_flag("Accepting")?,
+            }),
+            "ResourceCreatePlayer" => Command::ResourceCreatePlayer(ResourceCreatePlayer),
+            "ResourceCreateRecorder" => Command::ResourceCreateRecorder(ResourceCreateRecorder),
+            "ResourceCreateTransportChannel" => {
+                Command::ResourceCreateTransportChannel(ResourceCreateTransportChannel {
+                    transport_type: a.string()?,
+                })
+            }
+            "ResourceCreateRtpChannel" => Command::ResourceCreateRtpChannel(ResourceCreateRtpChannel {
+                in_band_dtmf_enabled: a.opt_flag("InBandDTMFEnabled")?,
+            }),
+            "ResourceCreateSoundDevice" => Command::ResourceCreateSoundDevice(ResourceCreateSoundDevice {
+                direction: a.opt_string("Direction")?.ok_or_else(|| ParseCommandError::WrongArity(a.name.clone()))?,
+                device: a.opt_string("Device")?,
+                buffers: a.opt("Buffers")?,
+            }),
+            "ResourceCreateFax" => Command::ResourceCreateFax(ResourceCreateFax),
+            "ResourceCreateDocument" => Command::ResourceCreateDocument(ResourceCreateDocument),
+            "ResourceDelete" => Command::ResourceDelete(ResourceDelete { resource_id: a.pos()? }),
+            "ResourceGetStatus" => Command::ResourceGetStatus(ResourceGetStatus { resource_id: a.pos()? }),
+
+            // Front-end Resource Commands
+            "CallMake" => Command::CallMake(CallMake {
+                resource_id: a.pos()?,
+                address: a.string()?,
+                timeout: a.opt("TimeOut")?,
+                caller_number: a.opt_string("CallerNumber")?,
+                caller_name: a.opt_string("CallerName")?,
+                privacy: a.opt("Privacy")?,
+                screen: a.opt("Screen")?,
+            }),
+            "CallAnswer" => Command::CallAnswer(CallAnswer { resource_id: a.pos()? }),
+            "CallClear" => Command::CallClear(CallClear {
+                resource_id: a.pos()?,
+                reason: a.opt_string("Reason")?,
+            }),
+            "CallTransferConsultation" => Command::CallTransferConsultation(CallTransferConsultation {
+                resource_id1: a.pos()?,
+                resource_id2: a.pos()?,
+            }),
+            "CallTransferBlind" => Command::CallTransferBlind(CallTransferBlind {
+                resource_id: a.pos()?,
+                address: a.string()?,
+                use_h450: a.opt("UseH450")?,
+            }),
+            "CallHold" => Command::CallHold(CallHold { resource_id: a.pos()? }),
+            "CallRetrieve" => Command::CallRetrieve(CallRetrieve { resource_id: a.pos()? }),
+            "CallSendDTMF" => Command::CallSendDTMF(CallSendDTMF {
+                resource_id: a.pos()?,
+                dtmf_string: a.string()?,
+                duration: a.opt("Duration")?,
+                delay: a.opt("Delay")?,
+                pause_duration: a.opt("PauseDuration")?,
+            }),
+            "CallStopActivity" => Command::CallStopActivity(CallStopActivity { resource_id: a.pos()? }),
+            "CallT38Relay" => Command::CallT38Relay(CallT38Relay {
+                resource_id1: a.pos()?,
+                resource_id2: a.pos()?,
+            }),
+            "CallsSetAlertingType" => Command::CallsSetAlertingType(CallsSetAlertingType {
+                resource_id: a.pos()?,
+                alerting_type: a.string()?,
+            }),
+            "CallsSetAccepting" => Command::CallsSetAccepting(CallsSetAccepting {
+                resource_id: a.pos()?,
+                accepting: {
+                    let value = a.string()?;
+                    parse_flag(&value).ok_or_else(|| a.bad_value(&value))?
+                },
+            }),
+
+            // Player Resource Commands
+            "PlayFile" => Command::PlayFile(PlayFile {
+                resource_id: a.pos()?,
+                file_name: a.string()?,
+                audio_type: a.opt("Type")?,
+                sample_rate: a.opt("SampleRate")?,
+                channels: a.opt_channels("Channels")?,
+                index: a.opt("Index")?,
+                skip_bytes: a.opt("SkipBytes")?,
+            }),
+            "PlayStream" => Command::PlayStream(PlayStream {
+                player_id: a.pos()?,
+                transport_channel_id: a.pos()?,
+                audio_type: a.opt("Type")?,
+                sample_rate: a.opt("SampleRate")?,
+                buffer_optimum_size: a.opt("BufferOptimumSize")?,
+            }),
+            "PlayTone" => Command::PlayTone(PlayTone {
+                resource_id: a.pos()?,
+                frequency: a.opt("Frequency")?,
+                frequency2: a.opt("Frequency2")?,
+                tone: a.opt("Tone")?,
+                volume: a.opt("Volume")?,
+                duration: a.opt("Duration")?,
+            }),
+            "PlayStop" => Command::PlayStop(PlayStop { resource_id: a.pos()? }),
+
+            // Recorder Resource Commands
+            "RecorderStartToFile" => Command::RecorderStartToFile(RecorderStartToFile {
+                resource_id: a.pos()?,
+                file_name: a.string()?,
+                audio_type: a.opt("Type")?,
+                sample_rate: a.opt("SampleRate")?,
+                channels: a.opt_channels("Channels")?,
+                file_offset: a.opt("FileOffset")?,
+                max_duration: a.opt("MaxDuration")?,
+                max_silence: a.opt("MaxSilence")?,
+                voice_trigger: a.opt_flag("VoiceTrigger")?,
+                pause_if_empty: a.opt_flag("PauseIfEmpty")?,
+            }),
+            "RecorderStartToStream" => Command::RecorderStartToStream(RecorderStartToStream {
+                recorder_id: a.pos()?,
+                transport_channel_id: a.pos()?,
+                audio_type: a.opt("Type")?,
+                sample_rate: a.opt("SampleRate")?,
+                max_duration: a.opt("MaxDuration")?,
+                max_silence: a.opt("MaxSilence")?,
+                voice_trigger: a.opt_flag("VoiceTrigger")?,
+                pause_if_empty: a.opt_flag("PauseIfEmpty")?,
+            }),
+            "RecorderStop" => Command::RecorderStop(RecorderStop { resource_id: a.pos()? }),
+
+            // RTP Channel Resource Commands
+            "RtpChannelStartReceiving" => Command::RtpChannelStartReceiving(RtpChannelStartReceiving {
+                resource_id: a.pos()?,
+                sender_control_address: a.opt("SenderControlAddress")?,
+                receiver_data_address: a.opt("ReceiverDataAddress")?,
+                receiver_control_address: a.opt("ReceiverControlAddress")?,
+                payload_type: a.opt("PayloadType")?,
+                rtp_payload_type: a.opt("RtpPayloadType")?,
+                rfc2833_payload_type: a.opt("RFC2833PayloadType")?,
+                rtp_session_id: a.opt("RtpSessionId")?,
+                jitter_buffer_length_min: a.opt("JitterBufferLengthMin")?,
+                jitter_buffer_length_max: a.opt("JitterBufferLengthMax")?,
+            }),
+            "RtpChannelStartSending" => Command::RtpChannelStartSending(RtpChannelStartSending {
+                resource_id: a.pos()?,
+                receiver_data_address: a.pos()?,
+                receiver_control_address: a.opt("ReceiverControlAddress")?,
+                sender_data_address: a.opt("SenderDataAddress")?,
+                sender_control_address: a.opt("SenderControlAddress")?,
+                payload_type: a.opt("PayloadType")?,
+                rtp_payload_type: a.opt("RtpPayloadType")?,
+                rfc2833_payload_type: a.opt("RFC2833PayloadType")?,
+                rtp_session_id: a.opt("RtpSessionId")?,
+            }),
+            "RtpChannelStop" => Command::RtpChannelStop(RtpChannelStop { resource_id: a.pos()? }),
+            "RtpChannelSendDTMF" => Command::RtpChannelSendDTMF(RtpChannelSendDTMF {
+                resource_id: a.pos()?,
+                dtmf_string: a.string()?,
+                duration: a.opt("Duration")?,
+                delay: a.opt("Delay")?,
+                pause_duration: a.opt("PauseDuration")?,
+            }),
+
+            // Sound device Resource Commands
+            "SoundDeviceStart" => Command::SoundDeviceStart(SoundDeviceStart { resource_id: a.pos()? }),
+            "SoundDeviceStop" => Command::SoundDeviceStop(SoundDeviceStop { resource_id: a.pos()? }),
+
+            // Fax Resource Commands
+            "FaxReceive" => Command::FaxReceive(FaxReceive {
+                fax_resource_id: a.pos()?,
+                frontend_resource_id: a.pos()?,
+                document_resource_id: a.pos()?,
+                fax_mode: a.opt_constant("FaxMode", ALL_FAX_RECEIVE_MODES)?,
+                use_ecm: a.opt("UseECM")?,
+                csi: a.opt("CSI")?,
+            }),
+            "FaxSend" => Command::FaxSend(FaxSend {
+                fax_resource_id: a.pos()?,
+                frontend_resource_id: a.pos()?,
+                document_resource_id: a.pos()?,
+                speed: a.opt_constant("Speed", ALL_FAX_SEND_SPEEDS)?,
+                use_ecm: a.opt("UseECM")?,
+                header: a.opt_string("Header")?,
+                tsi: a.opt("TSI")?,
+            }),
+            "FaxAbort" => Command::FaxAbort(FaxAbort { resource_id: a.pos()? }),
+
+            // Document Resource Commands
+            "DocumentAddFile" => Command::DocumentAddFile(DocumentAddFile {
+                resource_id: a.pos()?,
+                file_path: a.string()?,
+                transformation: a.opt_constant("Transformation", ALL_DOCUMENT_ADD_FILE_TRANSFORMATIONS)?,
+            }),
+            "DocumentPrepare" => Command::DocumentPrepare(DocumentPrepare {
+                resource_id: a.pos()?,
+                paper_size: a.opt_constant("PaperSize", ALL_DOCUMENT_PREPARE_PAPER_SIZES)?,
+                resolution: a.opt_constant("Resolution", ALL_DOCUMENT_PREPARE_RESOLUTIONS)?,
+            }),
+            "DocumentSave" => Command::DocumentSave(DocumentSave {
+                resource_id: a.pos()?,
+                file_path: a.string()?,
+                multipage: a.opt_flag("Multipage")?,
+                document_type: a.opt_constant("DocumentType", ALL_DOCUMENT_SAVE_TYPES)?,
+            }),
+            "DocumentClear" => Command::DocumentClear(DocumentClear { resource_id: a.pos()? }),
+
+            // Audio Routing and Audio Stream Monitoring Commands
+            "AudioSend" => Command::AudioSend(AudioSend {
+                source_resource_id: a.pos()?,
+                sink_resource_id: a.pos()?,
+                source_channel: a.opt("SourceChannel")?,
+                sink_channel: a.opt("SinkChannel")?,
+                volume: a.opt("Volume")?,
+                auto_gain: a.opt_flag("AutoGain")?,
+                auto_gain_resolution: a.opt("AutoGainResolution")?,
+                auto_gain_rise_time: a.opt("AutoGainRiseTime")?,
+                auto_gain_fall_time: a.opt("AutoGainFallTime")?,
+                auto_gain_kill_time: a.opt("AutoGainKillTime")?,
+            }),
+            "AudioCancel" => Command::AudioCancel(AudioCancel {
+                source_resource_id: a.pos()?,
+                sink_resource_id: a.pos()?,
+            }),
+            "AudioLevelNotificationSend" => Command::AudioLevelNotificationSend(AudioLevelNotificationSend {
+                resource_id: a.pos()?,
+                resolution: a.opt("Resolution")?,
+                voice_dead_band: a.opt("VoiceDeadBand")?,
+                silence_dead_band: a.opt("SilenceDeadBand")?,
+                adaptive_period: a.opt("AdaptivePeriod")?,
+                voice_timer: a.opt("VoiceTimer")?,
+                silence_timer: a.opt("SilenceTimer")?,
+            }),
+            "AudioLevelNotificationCancel" => {
+                Command::AudioLevelNotificationCancel(AudioLevelNotificationCancel { resource_id: a.pos()? })
+            }
+            "InBandSignalingDetectionEnable" => {
+                Command::InBandSignalingDetectionEnable(InBandSignalingDetectionEnable { resource_id: a.pos()? })
+            }
+            "InBandSignalingDetectionDisable" => {
+                Command::InBandSignalingDetectionDisable(InBandSignalingDetectionDisable { resource_id: a.pos()? })
+            }
+
+            // Miscellaneous Commands
+            "GetRtpStatistics" => Command::GetRtpStatistics(GetRtpStatistics { resource_id: a.pos()? }),
+
+            _ => return Err(ParseCommandError::UnknownCommand(args.name)),
+        };
+        args.finish()?;
+        Ok(command)
+    }
+}
+
 pub trait CommandHandler: Send + Sync {
     // Product Information Commands
     fn get_version(&mut self) -> PyResult<()>;
@@ -1724,9 +2384,9 @@ pub trait CommandHandler: Send + Sync {
     fn rtp_channel_start_receiving(
         &mut self,
         resource_id: ResourceId,
-        sender_control_address: Option<String>,
-        receiver_data_address: Option<String>,
-        receiver_control_address: Option<String>,
+        sender_control_address: Option<NetworkAddress>,
+        receiver_data_address: Option<NetworkAddress>,
+        receiver_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
@@ -1736,10 +2396,10 @@ pub trait CommandHandler: Send + Sync {
     fn rtp_channel_start_sending(
         &mut self,
         resource_id: ResourceId,
-        receiver_data_address: String,
-        receiver_control_address: Option<String>,
-        sender_data_address: Option<String>,
-        sender_control_address: Option<String>,
+        receiver_data_address: NetworkAddress,
+        receiver_control_address: Option<NetworkAddress>,
+        sender_data_address: Option<NetworkAddress>,
+        sender_control_address: Option<NetworkAddress>,
         payload_type: Option<PayloadType>,
         rfc2833_payload_type: Option<u8>,
         rtp_session_id: Option<u8>,
@@ -1766,7 +2426,7 @@ pub trait CommandHandler: Send + Sync {
         document_resource_id: ResourceId,
         fax_mode: Option<FaxReceiveMode>,
         use_ecm: Option<ECM>,
-        csi: Option<String>,
+        csi: Option<FaxIdentifier>,
     ) -> PyResult<()>;
     fn fax_send(
         &mut self,
@@ -1776,7 +2436,7 @@ pub trait CommandHandler: Send + Sync {
         speed: Option<FaxSendSpeed>,
         use_ecm: Option<ECM>,
         header: Option<String>,
-        tsi: Option<String>,
+        tsi: Option<FaxIdentifier>,
     ) -> PyResult<()>;
     fn fax_abort(&mut self, resource_id: ResourceId) -> PyResult<()>;
 
@@ -1832,5 +2492,236 @@ pub trait CommandHandler: Send + Sync {
     fn in_band_signaling_detection_disable(&mut self, resource_id: ResourceId) -> PyResult<()>;
     
     // Miscellaneous Commands
-    fn get_rtp_statistics(&mut self, resource_id: ResourceId) -> PyResult<()>;
+    fn get_rtp_statistics(&mut self, resource_id: ResourceId) -> PyResult<RtpStatistics>;
+}
+
+/// Helpers for the tests of modules that build commands.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::Command;
+
+    /// The lines `commands` are sent as, without command tags.
+    pub(crate) fn lines(commands: &[Command]) -> Vec<String> {
+        commands.iter().map(|c| c.to_string()).collect()
+    }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{RAW_G729, RAW_PCM16, VAP_ALAW, WAV_ALAW};
+
+    #[test]
+    fn validate_play_file_accepts_matching_format() {
+        let cmd = Command::play_file(
+            1,
+            "prompt.wav".to_string(),
+            Some(WAV_ALAW),
+            Some(8000),
+            Some(Channels::Stereo),
+            None,
+            None,
+        );
+        assert_eq!(cmd.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_play_file_rejects_stereo_g729() {
+        let cmd = Command::play_file(
+            1,
+            "prompt.g729".to_string(),
+            Some(RAW_G729),
+            None,
+            Some(Channels::Stereo),
+            None,
+            None,
+        );
+        assert_eq!(
+            cmd.validate(),
+            Err(CommandError::UnsupportedChannels {
+                format: "RAW_G729",
+                max: 1,
+                requested: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn validate_recorder_rejects_unsupported_sample_rate() {
+        let cmd = Command::recorder_start_to_file(
+            1,
+            "out.raw".to_string(),
+            Some(RAW_G729),
+            Some(16000),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert!(matches!(
+            cmd.validate(),
+            Err(CommandError::UnsupportedSampleRate { sample_rate: 16000, .. })
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_mismatched_file_extension() {
+        let wav_as_vap = Command::play_file(1, "a.vap".into(), Some(WAV_ALAW), None, None, None, None);
+        assert!(matches!(
+            wav_as_vap.validate(),
+            Err(CommandError::FileExtensionMismatch { .. })
+        ));
+
+        let raw_as_wav = Command::play_file(1, "a.WAV".into(), Some(RAW_PCM16), None, None, None, None);
+        assert!(matches!(
+            raw_as_wav.validate(),
+            Err(CommandError::FileExtensionMismatch { .. })
+        ));
+
+        let vap = Command::play_file(1, "menu.vap".into(), Some(VAP_ALAW), None, None, Some(2), None);
+        assert_eq!(vap.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_stream_checks_sample_rate_only() {
+        let cmd = Command::play_stream(1, 2, Some(RAW_PCM16), Some(44100), None);
+        assert_eq!(cmd.validate(), Ok(()));
+
+        let cmd = Command::recorder_start_to_stream(1, 2, Some(RAW_PCM16), Some(12345), None, None, None, None);
+        assert!(cmd.validate().is_err());
+    }
+
+    #[test]
+    fn rtp_start_with_dynamic_payload_code() {
+        use crate::constants::PayloadType_SPEEX_8K;
+
+        let cmd = Command::rtp_channel_start_sending(
+            2,
+            "10.0.0.1:5004".parse().unwrap(),
+            None,
+            None,
+            None,
+            Some(PayloadType_SPEEX_8K),
+            Some(101),
+            None,
+        )
+        .with_rtp_payload_type(97);
+        assert_eq!(
+            cmd.to_string(),
+            "RtpChannelStartSending 2 10.0.0.1:5004 PayloadType=SpeexIETFNarrow-8k RtpPayloadType=97 RFC2833PayloadType=101"
+        );
+        assert_eq!(
+            Command::rtp_channel_stop(2).with_rtp_payload_type(97).to_string(),
+            "RtpChannelStop 2"
+        );
+    }
+
+    #[test]
+    fn quotes_string_arguments() {
+        let cmd = Command::call_make(1, "sip:bob@example.com".to_string(), None, None, Some("Jane Doe".to_string()), None, None);
+        assert_eq!(
+            cmd.to_string(),
+            "CallMake 1 sip:bob@example.com TimeOut=30000 CallerName=\"Jane Doe\" Privacy=0 Screen=1"
+        );
+        assert_eq!(
+            Command::call_clear(1, Some("say \"bye\"\n".to_string())).to_string(),
+            "CallClear 1 Reason=\"say \\\"bye\\\"\\n\""
+        );
+        assert_eq!(Quoted("C:\\fax\\out.tif").to_string(), "C:\\fax\\out.tif");
+        assert_eq!(Quoted("C:\\my fax.tif").to_string(), "\"C:\\\\my fax.tif\"");
+        assert_eq!(Quoted("a=b#c").to_string(), "\"a=b#c\"");
+        assert_eq!(Quoted("").to_string(), "\"\"");
+    }
+
+    #[test]
+    fn parse_round_trips_every_command() {
+        let lines = [
+            "ProtocolVersion",
+            "GetVersion",
+            "Login admin \"pass word\" 2 3",
+            "Login admin secret 2 3 1",
+            "Logout",
+            "Quit",
+            "ResourceCreateFrontEnd RegIncomingANI=100 RegIncomingDNIS=200 RegIncomingRDN=300 Accepting=1",
+            "ResourceCreatePlayer",
+            "ResourceCreateRecorder",
+            "ResourceCreateTransportChannel TCP",
+            "ResourceCreateRtpChannel InBandDTMFEnabled=0",
+            "ResourceCreateSoundDevice Direction=Output Device=\"Speakers (USB)\" Buffers=4",
+            "ResourceCreateFax",
+            "ResourceCreateDocument",
+            "ResourceDelete 3",
+            "ResourceGetStatus 3",
+            "CallMake 1 sip:bob@example.com TimeOut=30000 CallerNumber=100 CallerName=\"Jane Doe\" Privacy=0 Screen=1",
+            "CallAnswer 1",
+            "CallClear 1 Reason=\"user hung up\"",
+            "CallTransferConsultation 1 2",
+            "CallTransferBlind 1 200 UseH450=1",
+            "CallHold 1",
+            "CallRetrieve 1",
+            "CallSendDTMF 1 \"123#\" Duration=300 Delay=200 PauseDuration=2000",
+            "CallStopActivity 1",
+            "CallT38Relay 1 2",
+            "CallsSetAlertingType 1 Silent",
+            "CallsSetAccepting 1 0",
+            "PlayFile 2 \"prompts/hello world.wav\" Type=WAV_ALAW SampleRate=8000 Channels=1 Index=2 SkipBytes=-44",
+            "PlayStream 2 4 Type=RAW_PCM16 SampleRate=16000 BufferOptimumSize=4096",
+            "PlayTone 2 Frequency=440 Frequency2=480 Tone=\"Line is busy\" Volume=5 Duration=1000",
+            "PlayStop 2",
+            "RecorderStartToFile 3 out.wav Type=WAV_ALAW SampleRate=8000 Channels=2 FileOffset=0 MaxDuration=60000 MaxSilence=5000 VoiceTrigger=1 PauseIfEmpty=0",
+            "RecorderStartToStream 3 4 Type=RAW_PCM16 SampleRate=8000 MaxDuration=1 MaxSilence=2 VoiceTrigger=0 PauseIfEmpty=1",
+            "RecorderStop 3",
+            "RtpChannelStartReceiving 5 SenderControlAddress=10.0.0.1:4001 ReceiverDataAddress=10.0.0.2:5000 ReceiverControlAddress=10.0.0.2:5001 PayloadType=PCM-16 RtpPayloadType=97 RFC2833PayloadType=101 RtpSessionId=1 JitterBufferLengthMin=20 JitterBufferLengthMax=200",
+            "RtpChannelStartSending 5 [::1]:5000 ReceiverControlAddress=[::1]:5001 PayloadType=G.711-ALaw-64k RtpSessionId=2",
+            "RtpChannelStop 5",
+            "RtpChannelSendDTMF 5 42 Duration=100",
+            "SoundDeviceStart 6",
+            "SoundDeviceStop 6",
+            "FaxReceive 2 1 3 FaxMode=V17 UseECM=64 CSI=\"+1 555 0100\"",
+            "FaxSend 2 1 3 Speed=V29At9600 UseECM=256 Header=\"{date} {time} Page {page}\" TSI=5550100",
+            "FaxAbort 2",
+            "DocumentAddFile 3 cover.tif Transformation=ScaleToFit",
+            "DocumentPrepare 3 PaperSize=Letter Resolution=High",
+            "DocumentSave 3 C:\\fax\\in.tif Multipage=1 DocumentType=TIFF",
+            "DocumentClear 3",
+            "AudioSend 1 7 SourceChannel=1 SinkChannel=2 Volume=-3 AutoGain=1 AutoGainResolution=10 AutoGainRiseTime=20 AutoGainFallTime=30 AutoGainKillTime=40",
+            "AudioCancel 1 7",
+            "AudioLevelNotificationSend 1 Resolution=1 VoiceDeadBand=2 SilenceDeadBand=3 AdaptivePeriod=4 VoiceTimer=5 SilenceTimer=6",
+            "AudioLevelNotificationCancel 1",
+            "InBandSignalingDetectionEnable 1",
+            "InBandSignalingDetectionDisable 1",
+            "GetRtpStatistics 5",
+        ];
+        for line in lines {
+            let cmd = parse_command(line).unwrap_or_else(|e| panic!("{}: {}", line, e));
+            assert_eq!(cmd.to_string(), line);
+            assert_eq!(parse_command(&cmd.to_string()).unwrap(), cmd);
+        }
+    }
+
+    #[test]
+    fn parse_matches_constructed_commands() {
+        let cmd = Command::call_make(1, "200".to_string(), None, None, Some("Jane \"JD\" Doe".to_string()), None, None);
+        assert_eq!(cmd.to_string().parse::<Command>().unwrap(), cmd);
+        let cmd = Command::recorder_start_to_file(7, "calls/1.wav".to_string(), None, None, Some(Channels::Stereo), None, None, None, None, None);
+        assert_eq!(parse_command(&cmd.to_string()).unwrap(), cmd);
+        assert_eq!(parse_command("callclear 1 reason=x").unwrap_err(), ParseCommandError::UnknownCommand("callclear".into()));
+        assert_eq!(parse_command("CallClear 1 reason=x").unwrap(), Command::call_clear(1, Some("x".into())));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lines() {
+        assert_eq!(parse_command("  ").unwrap_err(), ParseCommandError::Empty);
+        assert_eq!(parse_command("Dial 1").unwrap_err(), ParseCommandError::UnknownCommand("Dial".into()));
+        assert_eq!(parse_command("CallMake 1").unwrap_err(), ParseCommandError::WrongArity("CallMake".into()));
+        assert_eq!(parse_command("CallAnswer 1 2").unwrap_err(), ParseCommandError::WrongArity("CallAnswer".into()));
+        assert!(matches!(parse_command("CallAnswer x"), Err(ParseCommandError::BadValue { value, .. }) if value == "x"));
+        assert!(matches!(parse_command("FaxSend 2 1 3 UseECM=5"), Err(ParseCommandError::BadValue { .. })));
+        assert!(matches!(parse_command("FaxSend 2 1 3 Speed=A4"), Err(ParseCommandError::BadValue { .. })));
+        assert!(matches!(parse_command("CallMake 1 200 Foo=1"), Err(ParseCommandError::UnknownOption { option, .. }) if option == "foo"));
+        assert!(matches!(parse_command("CallClear 1 Reason=\"x"), Err(ParseCommandError::UnterminatedQuote(_))));
+    }
+}
+
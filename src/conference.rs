@@ -0,0 +1,291 @@
+use crate::commands::Command;
+use crate::primitives::ResourceId;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "conference")?;
+
+    child_module.add_class::<Conference>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ConferenceError {
+    #[error("resource {0} is already in the conference")]
+    AlreadyJoined(ResourceId),
+    #[error("resource {0} is not in the conference")]
+    NotParticipant(ResourceId),
+}
+
+impl From<ConferenceError> for PyErr {
+    fn from(err: ConferenceError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Participant {
+    muted: bool,
+    volume: Option<i16>,
+}
+
+/// Route volume keyed by source and sink.
+type Routes = BTreeMap<(ResourceId, ResourceId), Option<i16>>;
+
+/// A multi-party call: every participant is routed with `AudioSend` to every other one,
+/// and optionally to a recorder.
+///
+/// The conference only plans routes. Each change returns the `AudioCancel` and `AudioSend`
+/// commands that bring the server from the old set of routes to the new one, to be sent
+/// in order (e.g. with `GridborgClient.send_commands`). Nothing is returned before `start`.
+#[pyclass]
+#[derive(Clone, Debug, Default)]
+pub struct Conference {
+    participants: BTreeMap<ResourceId, Participant>,
+    #[pyo3(get)]
+    recorder: Option<ResourceId>,
+    #[pyo3(get)]
+    started: bool,
+}
+
+impl Conference {
+    pub fn new(calls: &[ResourceId], recorder: Option<ResourceId>) -> Result<Self, ConferenceError> {
+        let mut conference = Conference {
+            recorder,
+            ..Default::default()
+        };
+        for &call in calls {
+            conference.join(call)?;
+        }
+        Ok(conference)
+    }
+
+    pub fn participants(&self) -> Vec<ResourceId> {
+        self.participants.keys().copied().collect()
+    }
+
+    /// Routes of the full mesh: each unmuted participant to every other one and to the
+    /// recorder, at that participant's volume.
+    fn routes(&self) -> Routes {
+        let mut routes = Routes::new();
+        if !self.started {
+            return routes;
+        }
+        for (&source, participant) in self.participants.iter().filter(|(_, p)| !p.muted) {
+            let sinks = self.participants.keys().copied().chain(self.recorder);
+            for sink in sinks.filter(|&sink| sink != source) {
+                routes.insert((source, sink), participant.volume);
+            }
+        }
+        routes
+    }
+
+    /// Apply a change and return the commands taking the old routes to the new ones.
+    ///
+    /// Routes whose volume changes are cancelled and sent again, since a second
+    /// `AudioSend` between the same resources is refused by the routing graph.
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> Vec<Command> {
+        let before = self.routes();
+        change(self);
+        let after = self.routes();
+
+        let cancels = before
+            .iter()
+            .filter(|(link, volume)| after.get(link) != Some(volume))
+            .map(|(&(source, sink), _)| Command::audio_cancel(source, sink));
+        let sends = after
+            .iter()
+            .filter(|(link, volume)| before.get(link) != Some(volume))
+            .map(|(&(source, sink), &volume)| {
+                Command::audio_send(source, sink, None, None, volume, None, None, None, None, None)
+            });
+        cancels.chain(sends).collect()
+    }
+
+    fn participant(&mut self, call: ResourceId) -> Result<&mut Participant, ConferenceError> {
+        self.participants
+            .get_mut(&call)
+            .ok_or(ConferenceError::NotParticipant(call))
+    }
+
+    /// Set up the full mesh.
+    pub fn start(&mut self) -> Vec<Command> {
+        self.update(|conference| conference.started = true)
+    }
+
+    /// Cancel every route of the conference.
+    pub fn stop(&mut self) -> Vec<Command> {
+        self.update(|conference| conference.started = false)
+    }
+
+    pub fn join(&mut self, call: ResourceId) -> Result<Vec<Command>, ConferenceError> {
+        if self.participants.contains_key(&call) || self.recorder == Some(call) {
+            return Err(ConferenceError::AlreadyJoined(call));
+        }
+        Ok(self.update(|conference| {
+            conference.participants.insert(call, Participant::default());
+        }))
+    }
+
+    pub fn leave(&mut self, call: ResourceId) -> Result<Vec<Command>, ConferenceError> {
+        self.participant(call)?;
+        Ok(self.update(|conference| {
+            conference.participants.remove(&call);
+        }))
+    }
+
+    /// Stop or resume sending a participant's audio to the others; they keep hearing the conference.
+    pub fn set_muted(&mut self, call: ResourceId, muted: bool) -> Result<Vec<Command>, ConferenceError> {
+        self.participant(call)?;
+        Ok(self.update(|conference| {
+            if let Some(participant) = conference.participants.get_mut(&call) {
+                participant.muted = muted;
+            }
+        }))
+    }
+
+    /// Volume at which the others hear a participant, as in `AudioSend`; `None` for the server default.
+    pub fn set_volume(&mut self, call: ResourceId, volume: Option<i16>) -> Result<Vec<Command>, ConferenceError> {
+        self.participant(call)?;
+        Ok(self.update(|conference| {
+            if let Some(participant) = conference.participants.get_mut(&call) {
+                participant.volume = volume;
+            }
+        }))
+    }
+
+    /// Add, replace or (with `None`) remove the recorder leg.
+    pub fn set_recorder(&mut self, recorder: Option<ResourceId>) -> Result<Vec<Command>, ConferenceError> {
+        if let Some(recorder) = recorder.filter(|r| self.participants.contains_key(r)) {
+            return Err(ConferenceError::AlreadyJoined(recorder));
+        }
+        Ok(self.update(|conference| conference.recorder = recorder))
+    }
+}
+
+#[pymethods]
+impl Conference {
+    #[new]
+    #[pyo3(signature = (calls, recorder=None))]
+    fn py_new(calls: Vec<ResourceId>, recorder: Option<ResourceId>) -> PyResult<Self> {
+        Ok(Conference::new(&calls, recorder)?)
+    }
+
+    #[getter(participants)]
+    fn py_participants(&self) -> Vec<ResourceId> {
+        self.participants()
+    }
+
+    fn is_muted(&self, call: ResourceId) -> PyResult<bool> {
+        Ok(self
+            .participants
+            .get(&call)
+            .ok_or(ConferenceError::NotParticipant(call))?
+            .muted)
+    }
+
+    #[pyo3(name = "start")]
+    fn py_start(&mut self) -> Vec<Command> {
+        self.start()
+    }
+
+    #[pyo3(name = "stop")]
+    fn py_stop(&mut self) -> Vec<Command> {
+        self.stop()
+    }
+
+    #[pyo3(name = "join")]
+    fn py_join(&mut self, call: ResourceId) -> PyResult<Vec<Command>> {
+        Ok(self.join(call)?)
+    }
+
+    #[pyo3(name = "leave")]
+    fn py_leave(&mut self, call: ResourceId) -> PyResult<Vec<Command>> {
+        Ok(self.leave(call)?)
+    }
+
+    fn mute(&mut self, call: ResourceId) -> PyResult<Vec<Command>> {
+        Ok(self.set_muted(call, true)?)
+    }
+
+    fn unmute(&mut self, call: ResourceId) -> PyResult<Vec<Command>> {
+        Ok(self.set_muted(call, false)?)
+    }
+
+    #[pyo3(name = "set_volume")]
+    #[pyo3(signature = (call, volume=None))]
+    fn py_set_volume(&mut self, call: ResourceId, volume: Option<i16>) -> PyResult<Vec<Command>> {
+        Ok(self.set_volume(call, volume)?)
+    }
+
+    #[pyo3(name = "set_recorder")]
+    #[pyo3(signature = (recorder=None))]
+    fn py_set_recorder(&mut self, recorder: Option<ResourceId>) -> PyResult<Vec<Command>> {
+        Ok(self.set_recorder(recorder)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::testing::lines;
+    use crate::routing::RoutingGraph;
+
+    /// Apply commands to a routing graph the way the client does, failing on any refused one.
+    fn apply(graph: &mut RoutingGraph, commands: &[Command]) {
+        for command in commands {
+            assert!(graph.prepare(command).unwrap().is_empty(), "{command}");
+            graph.apply(command);
+        }
+    }
+
+    #[test]
+    fn start_builds_full_mesh_with_recorder() {
+        let mut conference = Conference::new(&[3, 1, 2], Some(9)).unwrap();
+        let commands = conference.start();
+        assert_eq!(commands.len(), 9);
+        let mut graph = RoutingGraph::new();
+        apply(&mut graph, &commands);
+        assert!(!graph.is_routed(1, 1));
+        assert!(graph.is_routed(3, 9) && !graph.is_routed(9, 3));
+
+        apply(&mut graph, &conference.stop());
+        assert!(graph.links().is_empty());
+    }
+
+    #[test]
+    fn join_leave_and_mute() {
+        let mut conference = Conference::new(&[1, 2], None).unwrap();
+        assert!(conference.join(3).unwrap().is_empty(), "not started yet");
+        let mut graph = RoutingGraph::new();
+        apply(&mut graph, &conference.start());
+
+        apply(&mut graph, &conference.set_muted(2, true).unwrap());
+        assert_eq!(graph.links_of(2), [(1, 2), (3, 2)]);
+
+        let commands = conference.join(4).unwrap();
+        assert_eq!(lines(&commands), ["AudioSend 1 4", "AudioSend 3 4", "AudioSend 4 1", "AudioSend 4 2", "AudioSend 4 3"]);
+        apply(&mut graph, &commands);
+
+        apply(&mut graph, &conference.leave(1).unwrap());
+        assert!(graph.links_of(1).is_empty());
+        assert_eq!(conference.join(3).err(), Some(ConferenceError::AlreadyJoined(3)));
+        assert_eq!(conference.leave(1).err(), Some(ConferenceError::NotParticipant(1)));
+    }
+
+    #[test]
+    fn volume_change_resends_routes() {
+        let mut conference = Conference::new(&[1, 2], Some(5)).unwrap();
+        conference.start();
+        assert_eq!(
+            lines(&conference.set_volume(1, Some(-6)).unwrap()),
+            ["AudioCancel 1 2", "AudioCancel 1 5", "AudioSend 1 2 Volume=-6", "AudioSend 1 5 Volume=-6"]
+        );
+        assert!(conference.set_volume(1, Some(-6)).unwrap().is_empty());
+        assert_eq!(lines(&conference.set_recorder(None).unwrap()), ["AudioCancel 1 5", "AudioCancel 2 5"]);
+        assert_eq!(conference.set_recorder(Some(2)).err(), Some(ConferenceError::AlreadyJoined(2)));
+    }
+}
@@ -1,8 +1,11 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 use crate::primitives::{Channels, SampleRate};
 use crate::{audio_formats, constant_set, payload_types, play_tones};
 use paste::paste;
-use pyo3::pyclass;
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pymethods, PyResult};
 
 #[pyclass]
 #[derive(Clone)]
@@ -22,6 +25,7 @@ enum ResourceType {
 pub struct AudioFormatType {
     pub name: &'static str,
     pub channels: Channels,
+    pub sample_rates: &'static [SampleRate],
 }
 
 #[pyclass]
@@ -59,43 +63,48 @@ pub struct ToneType {
     pub off_ms: Option<u16>,
 }
 
+const RATES_8K: &[SampleRate] = &[8000];
+const RATES_16K: &[SampleRate] = &[16000];
+const RATES_LINEAR: &[SampleRate] = &[8000, 11025, 16000, 22050, 32000, 44100, 48000];
+const RATES_ADPCM: &[SampleRate] = &[6000, 8000, 11025, 22050, 44100];
+
 audio_formats! {
-    //  codec          wav raw vap  channels
-    (ALAW        ,   Y , Y , Y , Channels::from_u8(2)),
-    (MULAW       ,   Y , Y , Y , Channels::from_u8(2)),
-    (PCM16       ,   Y , Y , N , Channels::from_u8(2)),
-    (PCM8        ,   Y , Y , N , Channels::from_u8(2)),
-    (PCM_S8      ,   N , Y , N , Channels::from_u8(1)),
-    (Linear_16_Mono_16kHz , N , Y , N , Channels::from_u8(1)),
-    (G726_40K    ,   N , Y , N , Channels::from_u8(1)),
-    (G726_32K    ,   N , Y , N , Channels::from_u8(1)),
-    (G726_24K    ,   N , Y , N , Channels::from_u8(1)),
-    (G726_16K    ,   N , Y , N , Channels::from_u8(1)),
-    (GSM610      ,   Y , Y , N , Channels::from_u8(1)),
-    (MS_GSM      ,   Y , Y , N , Channels::from_u8(1)),
-    (ILBC_13K3   ,   N , Y , N , Channels::from_u8(1)),
-    (ILBC_15K2   ,   N , Y , N , Channels::from_u8(1)),
-    (LPC10       ,   N , Y , N , Channels::from_u8(1)),
-    (SpeexNB_5_95K , N , Y , N , Channels::from_u8(1)),
-    (SpeexNB_8K  ,   N , Y , N , Channels::from_u8(1)),
-    (SpeexNB_11K ,   N , Y , N , Channels::from_u8(1)),
-    (SpeexNB_15K ,   N , Y , N , Channels::from_u8(1)),
-    (SpeexNB_18_2K , N , Y , N , Channels::from_u8(1)),
-    (SpeexNB_24_6K , N , Y , N , Channels::from_u8(1)),
-    (SpeexWB_20_6K , N , Y , N , Channels::from_u8(1)),
-    (SpeexWNarrow_8K , N , Y , N , Channels::from_u8(1)),
-    (IMA_ADPCM   ,   Y , Y , N , Channels::from_u8(2)),
-    (MSADPCM4    ,   Y , Y , N , Channels::from_u8(2)),
-    (ADPCM4      ,   Y , Y , Y , Channels::from_u8(2)),
-    (G728        ,   N , Y , N , Channels::from_u8(1)),
-    (G729        ,   N , Y , N , Channels::from_u8(1)),
-    (G729A       ,   N , Y , N , Channels::from_u8(1)),
-    (G729B       ,   N , Y , N , Channels::from_u8(1)),
-    (G729AB      ,   N , Y , N , Channels::from_u8(1)),
-    (G7231       ,   N , Y , N , Channels::from_u8(1)),
-    (G7231_5_3K  ,   N , Y , N , Channels::from_u8(1)),
-    (G7231A_6_3K ,   N , Y , N , Channels::from_u8(1)),
-    (G7231A_5_3K ,   N , Y , N , Channels::from_u8(1)),
+    //  codec          wav raw vap  channels               sample rates
+    (ALAW        ,   Y , Y , Y , Channels::from_u8(2) , RATES_LINEAR),
+    (MULAW       ,   Y , Y , Y , Channels::from_u8(2) , RATES_LINEAR),
+    (PCM16       ,   Y , Y , N , Channels::from_u8(2) , RATES_LINEAR),
+    (PCM8        ,   Y , Y , N , Channels::from_u8(2) , RATES_LINEAR),
+    (PCM_S8      ,   N , Y , N , Channels::from_u8(1) , RATES_LINEAR),
+    (Linear_16_Mono_16kHz , N , Y , N , Channels::from_u8(1) , RATES_16K),
+    (G726_40K    ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G726_32K    ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G726_24K    ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G726_16K    ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (GSM610      ,   Y , Y , N , Channels::from_u8(1) , RATES_8K),
+    (MS_GSM      ,   Y , Y , N , Channels::from_u8(1) , RATES_8K),
+    (ILBC_13K3   ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (ILBC_15K2   ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (LPC10       ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (SpeexNB_5_95K , N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (SpeexNB_8K  ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (SpeexNB_11K ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (SpeexNB_15K ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (SpeexNB_18_2K , N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (SpeexNB_24_6K , N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (SpeexWB_20_6K , N , Y , N , Channels::from_u8(1) , RATES_16K),
+    (SpeexWNarrow_8K , N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (IMA_ADPCM   ,   Y , Y , N , Channels::from_u8(2) , RATES_ADPCM),
+    (MSADPCM4    ,   Y , Y , N , Channels::from_u8(2) , RATES_ADPCM),
+    (ADPCM4      ,   Y , Y , Y , Channels::from_u8(2) , RATES_ADPCM),
+    (G728        ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G729        ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G729A       ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G729B       ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G729AB      ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G7231       ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G7231_5_3K  ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G7231A_6_3K ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
+    (G7231A_5_3K ,   N , Y , N , Channels::from_u8(1) , RATES_8K),
 }
 
 payload_types! {
@@ -327,6 +336,18 @@ impl FromStr for ConstantWithDescription {
     }
 }
 
+impl FromStr for AudioFormatType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL_AUDIO_FORMATS
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(())
+    }
+}
+
 impl FromStr for PayloadType {
     type Err = ();
 
@@ -339,6 +360,18 @@ impl FromStr for PayloadType {
     }
 }
 
+impl FromStr for ToneType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL_PLAY_TONES
+            .iter()
+            .find(|t| t.name.eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(())
+    }
+}
+
 impl PayloadType {
     pub fn from_code(code: u8) -> Option<Self> {
         ALL_PAYLOAD_TYPES
@@ -348,6 +381,127 @@ impl PayloadType {
     }
 }
 
+/// RTP payload codes reserved for dynamic assignment (RFC 3551).
+pub const DYNAMIC_PAYLOAD_CODES: RangeInclusive<u8> = 96..=127;
+
+/// Dynamic RTP payload codes assigned to payload types for one session.
+///
+/// Lookups fall back to the static codes of `ALL_PAYLOAD_TYPES` outside 96-127.
+#[pyclass]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PayloadTypeMap {
+    dynamic: HashMap<u8, PayloadType>,
+}
+
+impl PayloadTypeMap {
+    /// Assign `code` to `payload_type`, replacing any previous assignment of the code.
+    /// Fails for codes outside the dynamic range.
+    pub fn insert(&mut self, code: u8, payload_type: PayloadType) -> Result<Option<PayloadType>, ()> {
+        if !DYNAMIC_PAYLOAD_CODES.contains(&code) {
+            return Err(());
+        }
+        Ok(self.dynamic.insert(code, payload_type))
+    }
+
+    pub fn lookup(&self, code: u8) -> Option<PayloadType> {
+        if DYNAMIC_PAYLOAD_CODES.contains(&code) {
+            self.dynamic.get(&code).copied()
+        } else {
+            PayloadType::from_code(code)
+        }
+    }
+
+    /// The static code of `payload_type`, or the lowest dynamic code assigned to it.
+    pub fn code_for(&self, payload_type: &PayloadType) -> Option<u8> {
+        payload_type.type_code.or_else(|| {
+            self.dynamic
+                .iter()
+                .filter(|(_, pt)| *pt == payload_type)
+                .map(|(code, _)| *code)
+                .min()
+        })
+    }
+
+    pub fn dynamic_code_for(&self, payload_type: &PayloadType) -> Option<u8> {
+        self.code_for(payload_type)
+            .filter(|code| DYNAMIC_PAYLOAD_CODES.contains(code))
+    }
+}
+
+#[pymethods]
+impl PayloadTypeMap {
+    #[new]
+    fn py_new() -> Self {
+        PayloadTypeMap::default()
+    }
+
+    #[pyo3(name = "insert")]
+    fn py_insert(&mut self, code: u8, payload_type: PayloadType) -> PyResult<Option<PayloadType>> {
+        self.insert(code, payload_type).map_err(|_| {
+            PyValueError::new_err(format!("{} is not a dynamic payload code (96-127)", code))
+        })
+    }
+
+    #[pyo3(name = "lookup")]
+    fn py_lookup(&self, code: u8) -> Option<PayloadType> {
+        self.lookup(code)
+    }
+
+    #[pyo3(name = "code_for")]
+    fn py_code_for(&self, payload_type: PayloadType) -> Option<u8> {
+        self.code_for(&payload_type)
+    }
+
+    fn __len__(&self) -> usize {
+        self.dynamic.len()
+    }
+}
+
+impl ToneType {
+    /// The DTMF tone for a keypad character (`0`-`9`, `*`, `#`, `A`-`D`).
+    pub fn from_dtmf_key(key: char) -> Option<Self> {
+        match key.to_ascii_uppercase() {
+            '1' => Some(PlayTone_DTMF_1),
+            '2' => Some(PlayTone_DTMF_2),
+            '3' => Some(PlayTone_DTMF_3),
+            '4' => Some(PlayTone_DTMF_4),
+            '5' => Some(PlayTone_DTMF_5),
+            '6' => Some(PlayTone_DTMF_6),
+            '7' => Some(PlayTone_DTMF_7),
+            '8' => Some(PlayTone_DTMF_8),
+            '9' => Some(PlayTone_DTMF_9),
+            '0' => Some(PlayTone_DTMF_0),
+            '*' => Some(PlayTone_DTMF_STAR),
+            '#' => Some(PlayTone_DTMF_HASH),
+            'A' => Some(PlayTone_DTMF_A),
+            'B' => Some(PlayTone_DTMF_B),
+            'C' => Some(PlayTone_DTMF_C),
+            'D' => Some(PlayTone_DTMF_D),
+            _ => None,
+        }
+    }
+}
+
+impl AudioFormatType {
+    /// File container of the format: `"WAV"`, `"RAW"` or `"VAP"`.
+    pub fn container(&self) -> &'static str {
+        self.name.split_once('_').map_or(self.name, |(container, _)| container)
+    }
+
+    /// Codec part of the format name, e.g. `"G729"` for `RAW_G729`.
+    pub fn codec(&self) -> &'static str {
+        self.name.split_once('_').map_or(self.name, |(_, codec)| codec)
+    }
+
+    pub fn supports_channels(&self, channels: Channels) -> bool {
+        channels as u8 <= self.channels as u8
+    }
+
+    pub fn supports_sample_rate(&self, sample_rate: SampleRate) -> bool {
+        self.sample_rates.contains(&sample_rate)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -369,6 +523,46 @@ mod tests {
         assert!(ALL_PAYLOAD_TYPES.contains(&PayloadType_G711_ULAW_64K));
     }
 
+    #[test]
+    fn payload_type_map_dynamic_codes() {
+        let mut map = PayloadTypeMap::default();
+        assert_eq!(map.lookup(97), None);
+        assert_eq!(map.lookup(0), Some(PayloadType_G711_ALAW_64K));
+
+        assert_eq!(map.insert(97, PayloadType_SPEEX_8K), Ok(None));
+        assert_eq!(map.insert(98, PayloadType_ILBC_13K3), Ok(None));
+        assert_eq!(map.insert(96, PayloadType_SPEEX_8K), Ok(None));
+        assert_eq!(map.insert(8, PayloadType_SPEEX_8K), Err(()));
+        assert_eq!(map.insert(128, PayloadType_SPEEX_8K), Err(()));
+
+        assert_eq!(map.lookup(97), Some(PayloadType_SPEEX_8K));
+        assert_eq!(map.code_for(&PayloadType_SPEEX_8K), Some(96));
+        assert_eq!(map.dynamic_code_for(&PayloadType_ILBC_13K3), Some(98));
+        assert_eq!(map.code_for(&PayloadType_G711_ALAW_64K), Some(0));
+        assert_eq!(map.dynamic_code_for(&PayloadType_G711_ALAW_64K), None);
+        assert_eq!(map.code_for(&PayloadType_G726_32K), None);
+    }
+
+    #[test]
+    fn audio_format_constants_individual() {
+        assert_eq!(WAV_ALAW.name, "WAV_ALAW");
+        assert_eq!(WAV_ALAW.container(), "WAV");
+        assert_eq!(WAV_ALAW.codec(), "ALAW");
+        assert_eq!(RAW_G729.channels, Channels::Mono);
+        assert!(RAW_G729.supports_sample_rate(8000));
+        assert!(!RAW_G729.supports_sample_rate(16000));
+        assert!(RAW_PCM16.supports_channels(Channels::Stereo));
+        assert!(!RAW_G729.supports_channels(Channels::Stereo));
+    }
+
+    #[test]
+    fn audio_format_constants_all() {
+        assert!(ALL_AUDIO_FORMATS.contains(&WAV_ALAW));
+        assert!(ALL_AUDIO_FORMATS.contains(&VAP_ADPCM4));
+        assert!(!ALL_AUDIO_FORMATS.iter().any(|f| f.name == "WAV_G729"));
+        assert_eq!("raw_g729".parse::<AudioFormatType>(), Ok(RAW_G729));
+    }
+
     #[test]
     fn call_end_reason_constants_individual() {
         assert_eq!(CallEndReason_EndedByLocalUser.name, "EndedByLocalUser");
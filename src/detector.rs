@@ -0,0 +1,345 @@
+use crate::constants::{
+    AudioFormatType, PlayTone_BusyTone, PlayTone_CallWaitingTone, PlayTone_ReorderTone,
+    PlayTone_RingBackTone, ToneType,
+};
+use crate::primitives::{ResourceId, SampleRate};
+use crate::tones;
+use pyo3::prelude::*;
+use std::f64::consts::PI;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "detector")?;
+
+    child_module.add_class::<ToneDetector>()?;
+    child_module.add_class::<DetectedTone>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+const DTMF_ROWS: [u16; 4] = [697, 770, 852, 941];
+const DTMF_COLUMNS: [u16; 4] = [1209, 1336, 1477, 1633];
+const DTMF_KEYS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Call-progress tones recognized by their frequencies and on-time.
+const CALL_PROGRESS_TONES: [ToneType; 4] = [
+    PlayTone_BusyTone,
+    PlayTone_RingBackTone,
+    PlayTone_ReorderTone,
+    PlayTone_CallWaitingTone,
+];
+const CALL_PROGRESS_FREQUENCIES: [u16; 3] = [440, 480, 620];
+
+/// Analysis block length; 25.6 ms is 205 samples at 8 kHz, the classic DTMF block.
+const BLOCK_US: u64 = 25_600;
+/// Blocks quieter than this RMS value (about -50 dBm0) are treated as silence.
+const MIN_RMS: f64 = 100.0;
+/// Share of the block energy a single component of a dual tone must hold.
+const MIN_COMPONENT: f64 = 0.25;
+/// Share of the block energy the detected tone must hold in total.
+const MIN_TOTAL: f64 = 0.7;
+/// Maximum level difference between the DTMF row and column tones (8 dB).
+const MAX_TWIST: f64 = 6.3;
+/// A DTMF key must be present for at least this many consecutive blocks (~50 ms).
+const MIN_DTMF_BLOCKS: u32 = 2;
+
+/// A tone found by `ToneDetector`, shaped like `CallKeyPress`.
+///
+/// `key` is the DTMF character, or the `ToneType` name for call-progress tones.
+/// `timestamp_ms` is the start of the tone relative to the first sample analysed.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectedTone {
+    #[pyo3(get)]
+    resource_id: ResourceId,
+    #[pyo3(get)]
+    key: String,
+    #[pyo3(get)]
+    duration: Option<u16>,
+    #[pyo3(get)]
+    timestamp_ms: u64,
+    #[pyo3(get)]
+    tone: ToneType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Signal {
+    Dtmf(char),
+    Progress(u16, u16),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    signal: Signal,
+    start_block: u64,
+    blocks: u32,
+}
+
+struct Goertzel {
+    coefficient: f64,
+}
+
+impl Goertzel {
+    fn new(frequency: u16, sample_rate: SampleRate) -> Self {
+        let omega = 2.0 * PI * f64::from(frequency) / f64::from(sample_rate);
+        Goertzel {
+            coefficient: 2.0 * omega.cos(),
+        }
+    }
+
+    fn power(&self, block: &[f64]) -> f64 {
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for &x in block {
+            let s = x + self.coefficient * s1 - s2;
+            s2 = s1;
+            s1 = s;
+        }
+        s1 * s1 + s2 * s2 - self.coefficient * s1 * s2
+    }
+}
+
+/// Goertzel-based detector for DTMF digits and call-progress tones in linear PCM.
+///
+/// Audio may be fed in frames of any size; results are reported once a tone ends,
+/// so call `flush` at the end of the stream to report a tone still sounding.
+#[pyclass]
+pub struct ToneDetector {
+    #[pyo3(get)]
+    resource_id: ResourceId,
+    #[pyo3(get)]
+    sample_rate: SampleRate,
+    block_len: usize,
+    filters: Vec<Goertzel>,
+    pending: Vec<f64>,
+    blocks_seen: u64,
+    current: Option<Segment>,
+}
+
+impl ToneDetector {
+    pub fn new(resource_id: ResourceId, sample_rate: SampleRate) -> Self {
+        let filters = DTMF_ROWS
+            .iter()
+            .chain(DTMF_COLUMNS.iter())
+            .chain(CALL_PROGRESS_FREQUENCIES.iter())
+            .map(|&f| Goertzel::new(f, sample_rate))
+            .collect();
+        let block_len = (u64::from(sample_rate) * BLOCK_US / 1_000_000) as usize;
+        ToneDetector {
+            resource_id,
+            sample_rate,
+            block_len,
+            filters,
+            pending: Vec::with_capacity(block_len),
+            blocks_seen: 0,
+            current: None,
+        }
+    }
+
+    /// Analyse a frame of linear samples, returning the tones that ended within it.
+    pub fn process(&mut self, samples: &[i16]) -> Vec<DetectedTone> {
+        let mut detected = Vec::new();
+        for &sample in samples {
+            self.pending.push(f64::from(sample));
+            if self.pending.len() == self.block_len {
+                let signal = self.classify();
+                self.pending.clear();
+                self.advance(signal, &mut detected);
+            }
+        }
+        detected
+    }
+
+    /// Report a tone that is still sounding and reset the segment state.
+    pub fn flush(&mut self) -> Vec<DetectedTone> {
+        self.pending.clear();
+        self.current.take().and_then(|s| self.report(s)).into_iter().collect()
+    }
+
+    fn blocks_to_ms(&self, blocks: u64) -> u64 {
+        blocks * self.block_len as u64 * 1000 / u64::from(self.sample_rate)
+    }
+
+    fn advance(&mut self, signal: Option<Signal>, detected: &mut Vec<DetectedTone>) {
+        match (self.current.as_mut(), signal) {
+            (Some(segment), Some(signal)) if segment.signal == signal => segment.blocks += 1,
+            _ => {
+                if let Some(tone) = self.current.take().and_then(|s| self.report(s)) {
+                    detected.push(tone);
+                }
+                self.current = signal.map(|signal| Segment {
+                    signal,
+                    start_block: self.blocks_seen,
+                    blocks: 1,
+                });
+            }
+        }
+        self.blocks_seen += 1;
+    }
+
+    fn report(&self, segment: Segment) -> Option<DetectedTone> {
+        let duration_ms = self.blocks_to_ms(u64::from(segment.blocks));
+        let tone = match segment.signal {
+            Signal::Dtmf(key) if segment.blocks >= MIN_DTMF_BLOCKS => ToneType::from_dtmf_key(key)?,
+            Signal::Dtmf(_) => return None,
+            Signal::Progress(f1, f2) => {
+                // allow 20% deviation plus the block granularity on either edge
+                let block_ms = self.blocks_to_ms(1);
+                CALL_PROGRESS_TONES.into_iter().find(|tone| {
+                    let on_ms = u64::from(tone.on_ms.unwrap_or(0));
+                    tone.f1 == f1 && tone.f2 == f2 && duration_ms.abs_diff(on_ms) <= on_ms / 5 + 2 * block_ms
+                })?
+            }
+        };
+        let key = match segment.signal {
+            Signal::Dtmf(key) => key.to_string(),
+            Signal::Progress(..) => tone.name.to_string(),
+        };
+        Some(DetectedTone {
+            resource_id: self.resource_id,
+            key,
+            duration: Some(duration_ms.min(u64::from(u16::MAX)) as u16),
+            timestamp_ms: self.blocks_to_ms(segment.start_block),
+            tone,
+        })
+    }
+
+    fn classify(&self) -> Option<Signal> {
+        let block = &self.pending;
+        let energy: f64 = block.iter().map(|x| x * x).sum();
+        if (energy / block.len() as f64).sqrt() < MIN_RMS {
+            return None;
+        }
+        // a pure sine holding all the block energy has a relative power of 1
+        let scale = energy * block.len() as f64 / 2.0;
+        let powers: Vec<f64> = self.filters.iter().map(|f| f.power(block) / scale).collect();
+        let (rows, rest) = powers.split_at(DTMF_ROWS.len());
+        let (columns, progress) = rest.split_at(DTMF_COLUMNS.len());
+
+        let (row, row_power) = strongest(rows);
+        let (column, column_power) = strongest(columns);
+        if row_power >= MIN_COMPONENT
+            && column_power >= MIN_COMPONENT
+            && row_power + column_power >= MIN_TOTAL
+            && row_power.max(column_power) / row_power.min(column_power) <= MAX_TWIST
+        {
+            return Some(Signal::Dtmf(DTMF_KEYS[row][column]));
+        }
+
+        let power_at = |frequency: u16| {
+            CALL_PROGRESS_FREQUENCIES
+                .iter()
+                .position(|&f| f == frequency)
+                .map_or(0.0, |i| progress[i])
+        };
+        CALL_PROGRESS_TONES.iter().find_map(|tone| {
+            let (p1, p2) = (power_at(tone.f1), if tone.f2 == 0 { 0.0 } else { power_at(tone.f2) });
+            let matches = if tone.f2 == 0 {
+                p1 >= MIN_TOTAL
+            } else {
+                p1 >= MIN_COMPONENT && p2 >= MIN_COMPONENT && p1 + p2 >= MIN_TOTAL
+            };
+            matches.then_some(Signal::Progress(tone.f1, tone.f2))
+        })
+    }
+}
+
+fn strongest(powers: &[f64]) -> (usize, f64) {
+    powers
+        .iter()
+        .copied()
+        .enumerate()
+        .fold((0, 0.0), |best, (i, p)| if p > best.1 { (i, p) } else { best })
+}
+
+#[pymethods]
+impl ToneDetector {
+    #[new]
+    #[pyo3(signature = (resource_id=0, sample_rate=8000))]
+    fn py_new(resource_id: ResourceId, sample_rate: SampleRate) -> Self {
+        ToneDetector::new(resource_id, sample_rate)
+    }
+
+    /// Analyse a frame of PCM16, A-law or µ-law audio, e.g. from a recorder stream.
+    #[pyo3(name = "process")]
+    fn py_process(&mut self, data: &[u8], audio_type: AudioFormatType) -> PyResult<Vec<DetectedTone>> {
+        let samples = tones::decode(data, &audio_type)?;
+        Ok(self.process(&samples))
+    }
+
+    #[pyo3(name = "flush")]
+    fn py_flush(&mut self) -> Vec<DetectedTone> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::PlayTone_DialTone;
+    use crate::tones::ToneGenerator;
+
+    fn detect(samples: &[i16]) -> Vec<DetectedTone> {
+        let mut detector = ToneDetector::new(7, 8000);
+        // feed in odd-sized frames to exercise the block buffering
+        let mut detected: Vec<_> = samples.chunks(160 + 3).flat_map(|f| detector.process(f)).collect();
+        detected.extend(detector.flush());
+        detected
+    }
+
+    #[test]
+    fn detects_dtmf_digits_with_timestamps() {
+        let generator = ToneGenerator::new(8000, -10.0);
+        let samples = generator.dtmf("159#D", 100, 100, 0).unwrap();
+        let detected = detect(&samples);
+
+        let keys: String = detected.iter().map(|t| t.key.as_str()).collect();
+        assert_eq!(keys, "159#D");
+        for (i, tone) in detected.iter().enumerate() {
+            assert_eq!(tone.resource_id, 7);
+            let start = i as u64 * 200;
+            assert!(tone.timestamp_ms.abs_diff(start) <= 26, "{tone:?}");
+            assert!(tone.duration.unwrap().abs_diff(100) <= 26, "{tone:?}");
+        }
+    }
+
+    #[test]
+    fn ignores_short_bursts_and_silence() {
+        let generator = ToneGenerator::new(8000, -10.0);
+        assert!(detect(&generator.dtmf("5", 20, 0, 0).unwrap()).is_empty());
+        assert!(detect(&[0; 8000]).is_empty());
+
+        let quiet = ToneGenerator::new(8000, -60.0);
+        assert!(detect(&quiet.dtmf("5", 200, 0, 0).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn detects_call_progress_tones() {
+        let generator = ToneGenerator::new(8000, -13.0);
+        for tone in CALL_PROGRESS_TONES {
+            let period = u32::from(tone.on_ms.unwrap() + tone.off_ms.unwrap());
+            let detected = detect(&generator.tone(&tone, period * 2).unwrap());
+            assert_eq!(detected.len(), 2, "{}: {detected:?}", tone.name);
+            assert!(detected.iter().all(|t| t.tone == tone && t.key == tone.name));
+            assert!(detected[1].timestamp_ms.abs_diff(u64::from(period)) <= 26);
+        }
+
+        // continuous dial tone is not one of the cadenced tones
+        assert!(detect(&generator.tone(&PlayTone_DialTone, 3000).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn decodes_g711_frames() {
+        let generator = ToneGenerator::new(8000, -10.0);
+        let samples = generator.dtmf("42", 80, 80, 0).unwrap();
+        let data = tones::encode(&samples, &crate::constants::RAW_ALAW).unwrap();
+
+        let mut detector = ToneDetector::new(0, 8000);
+        let mut detected = detector.process(&tones::decode(&data, &crate::constants::RAW_ALAW).unwrap());
+        detected.extend(detector.flush());
+        assert_eq!(detected.iter().map(|t| t.key.as_str()).collect::<String>(), "42");
+    }
+}
@@ -1,11 +1,12 @@
 use crate::constants::{
     DocumentPreparePaperSize, DocumentPrepareResolution, EStreamBufferStateNotification,
-    FaxSendSpeed, PayloadType, RecorderStopReason,
+    FaxSendSpeed, PayloadType, PayloadTypeMap, RecorderStopReason,
 };
-use crate::primitives::{ResourceId, SessionId, ECM};
+use crate::primitives::{NetworkAddress, ResourceId, SessionId, ECM};
 use pyo3::pyclass;
+use crate::commands::Quoted;
 use serde::de::Visitor;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::{collections::HashMap, fmt, str::FromStr};
 
 #[derive(thiserror::Error, Debug)]
@@ -16,6 +17,10 @@ pub enum ParseEventError {
     WrongArity(String),
     #[error("bad integer value in '{0}'")]
     BadInt(String),
+    #[error("bad network address '{0}'")]
+    BadAddress(String),
+    #[error("unterminated quote in '{0}'")]
+    UnterminatedQuote(String),
     #[error("other: {0}")]
     Other(&'static str),
 }
@@ -29,6 +34,13 @@ fn parse_pos<T: FromStr>(tokens: &[&str], idx: usize, ev: &str) -> Result<T, Par
         .map_err(|_| ParseEventError::BadInt(tokens[idx].to_owned()))
 }
 
+/// Parse an optional `ip:port` option value.
+fn parse_address_opt(opts: &HashMap<String, String>, key: &str) -> Result<Option<NetworkAddress>, ParseEventError> {
+    opts.get(key)
+        .map(|v| v.parse().map_err(|_| ParseEventError::BadAddress(v.clone())))
+        .transpose()
+}
+
 /// Collect leftover tokens → HashMap<name, value>
 fn parse_opts(tokens: &[&str], start: usize) -> HashMap<String, String> {
     tokens[start..]
@@ -42,29 +54,29 @@ fn parse_opts(tokens: &[&str], start: usize) -> HashMap<String, String> {
 
 // Session, Resource and Notification Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SessionCreated {
     session_id: SessionId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SessionDeleted {
     session_id: SessionId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceCreated {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ResourceDeleted {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AudioLevelNotification {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -72,7 +84,7 @@ pub struct AudioLevelNotification {
     energy_level: u8,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct StreamBufferStateNotification {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -81,19 +93,19 @@ pub struct StreamBufferStateNotification {
 
 // Front-end Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallIncoming {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
     call_identifier: String,
-    ani: Option<String>,
-    dnis: Option<String>,
+    pub(crate) ani: Option<String>,
+    pub(crate) dnis: Option<String>,
     rdn: Option<String>,
     remote_name: Option<String>,
     remote_address: Option<String>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallOutgoing {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -101,42 +113,42 @@ pub struct CallOutgoing {
     call_identifier: String,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallRemoteAlerting {
     session_id: SessionId,
     resource_id: ResourceId,
     user: Option<String>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallConnectionEstablished {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallConnectionFailed {
     session_id: SessionId,
-    resource_id: ResourceId,
-    reason: String,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) reason: String,
     protocol_specific_reason: Option<String>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallCleared {
     session_id: SessionId,
-    resource_id: ResourceId,
-    reason: String,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) reason: String,
     protocol_specific_reason: Option<String>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallSendDTMFFinished {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CallKeyPress {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -146,19 +158,19 @@ pub struct CallKeyPress {
 
 // Player Resource Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PlayerStarted {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PlayerStopped {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PlayerError {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -167,27 +179,27 @@ pub struct PlayerError {
 
 // Recorder Resource Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RecorderStarted {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RecorderStopped {
     session_id: SessionId,
-    resource_id: ResourceId,
-    reason: RecorderStopReason,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) reason: RecorderStopReason,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RecorderError {
     session_id: SessionId,
-    resource_id: ResourceId,
-    error_text: String,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) error_text: String,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RecorderVoiceTrigger {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -195,30 +207,30 @@ pub struct RecorderVoiceTrigger {
 
 // RTP Channel Resource Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelStartedReceiving {
     session_id: SessionId,
     resource_id: ResourceId,
-    receiver_data_address: String,
-    receiver_control_address: Option<String>,
+    receiver_data_address: NetworkAddress,
+    receiver_control_address: Option<NetworkAddress>,
     rtp_payload_type: Option<PayloadType>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelStartedSending {
     session_id: SessionId,
     resource_id: ResourceId,
-    sender_control_address: Option<String>,
+    sender_control_address: Option<NetworkAddress>,
     rtp_payload_type: Option<PayloadType>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelSendDTMFFinished {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelReceivedDTMF {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -226,7 +238,7 @@ pub struct RtpChannelReceivedDTMF {
     duration: Option<u16>,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RtpChannelStopped {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -234,19 +246,19 @@ pub struct RtpChannelStopped {
 
 // Sound Device Resource Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SoundDeviceStarted {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SoundDeviceStopped {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SoundDeviceError {
     session_id: SessionId,
     resource_id: ResourceId,
@@ -254,107 +266,107 @@ pub struct SoundDeviceError {
 
 // Fax Resource Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ModeChangeT38 {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ModeChangeT38Refused {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxIncoming {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FacsimilePageStarted {
     session_id: SessionId,
-    resource_id: ResourceId,
-    speed: FaxSendSpeed,
-    paper_size: DocumentPreparePaperSize,
-    resolution: DocumentPrepareResolution,
-    ecm: ECM,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) speed: FaxSendSpeed,
+    pub(crate) paper_size: DocumentPreparePaperSize,
+    pub(crate) resolution: DocumentPrepareResolution,
+    pub(crate) ecm: ECM,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FacsimilePageReceived {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FacsimilePageSent {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxOperationsStarted {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxOperationFailed {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxOperationFinished {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FaxOperationAborted {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 
 // Document Resource Events
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentPrepared {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentNotPrepared {
     session_id: SessionId,
-    resource_id: ResourceId,
-    reason: String,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) reason: String,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentSaved {
     session_id: SessionId,
-    resource_id: ResourceId,
+    pub(crate) resource_id: ResourceId,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentNotSaved {
     session_id: SessionId,
-    resource_id: ResourceId,
-    reason: String,
+    pub(crate) resource_id: ResourceId,
+    pub(crate) reason: String,
 }
 #[pyclass]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DocumentCleared {
     session_id: SessionId,
     resource_id: ResourceId,
 }
 
-#[pyclass]
-#[derive(Clone)]
-enum Event {
+#[pyclass(str)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
     // Session, Resource and Notification Events
     SessionCreated(SessionCreated),
     SessionDeleted(SessionDeleted),
@@ -434,13 +446,263 @@ impl<'de> Deserialize<'de> for Event {
     }
 }
 
-fn parse_event(line: &str) -> Result<Event, ParseEventError> {
-    let mut line = line.split('#').next().unwrap_or("").trim();
-    if line.is_empty() {
-        return Err(ParseEventError::Other("empty line"));
+impl Serialize for Event {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// An event written as a protocol line, with the RTP payload codes of `payload_types`.
+struct EventLine<'a>(&'a Event, &'a PayloadTypeMap);
+
+/// Write the event name and the session and resource ids shared by most events.
+fn write_ids(f: &mut fmt::Formatter, name: &str, session_id: SessionId, resource_id: ResourceId) -> fmt::Result {
+    write!(f, "{} {} {}", name, session_id, resource_id)
+}
+
+impl fmt::Display for EventLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let EventLine(event, payload_types) = self;
+        let payload_code = |payload_type: &Option<PayloadType>| {
+            payload_type.as_ref().and_then(|pt| payload_types.code_for(pt))
+        };
+        match event {
+            // Session, Resource and Notification Events
+            Event::SessionCreated(e) => write!(f, "ESessionCreated {}", e.session_id),
+            Event::SessionDeleted(e) => write!(f, "ESessionDeleted {}", e.session_id),
+            Event::ResourceCreated(e) => write_ids(f, "EResourceCreated", e.session_id, e.resource_id),
+            Event::ResourceDeleted(e) => write_ids(f, "EResourceDeleted", e.session_id, e.resource_id),
+            Event::AudioLevelNotification(e) => {
+                write_ids(f, "EAudioLevelNotification", e.session_id, e.resource_id)?;
+                write!(f, " {} {}", e.in_talk, e.energy_level)
+            }
+            Event::StreamBufferStateNotification(e) => {
+                write_ids(f, "EStreamBufferStateNotification", e.session_id, e.resource_id)?;
+                write!(f, " {}", e.state.name)
+            }
+
+            // Front-end Events
+            Event::CallIncoming(e) => {
+                write_ids(f, "ECallIncoming", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.call_identifier))?;
+                if let Some(ani) = &e.ani {
+                    write!(f, " ANI={}", Quoted(ani))?;
+                }
+                if let Some(dnis) = &e.dnis {
+                    write!(f, " DNIS={}", Quoted(dnis))?;
+                }
+                if let Some(rdn) = &e.rdn {
+                    write!(f, " RDN={}", Quoted(rdn))?;
+                }
+                if let Some(remote_name) = &e.remote_name {
+                    write!(f, " RemoteName={}", Quoted(remote_name))?;
+                }
+                if let Some(remote_address) = &e.remote_address {
+                    write!(f, " RemoteAddress={}", Quoted(remote_address))?;
+                }
+                Ok(())
+            }
+            Event::CallOutgoing(e) => {
+                write_ids(f, "ECallOutgoing", e.session_id, e.resource_id)?;
+                write!(f, " {} {}", Quoted(&e.address), Quoted(&e.call_identifier))
+            }
+            Event::CallRemoteAlerting(e) => {
+                write_ids(f, "ECallRemoteAlerting", e.session_id, e.resource_id)?;
+                if let Some(user) = &e.user {
+                    write!(f, " User={}", Quoted(user))?;
+                }
+                Ok(())
+            }
+            Event::CallConnectionEstablished(e) => {
+                write_ids(f, "ECallConnectionEstablished", e.session_id, e.resource_id)
+            }
+            Event::CallConnectionFailed(e) => {
+                write_ids(f, "ECallConnectionFailed", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.reason))?;
+                if let Some(reason) = &e.protocol_specific_reason {
+                    write!(f, " ProtocolSpecificReason={}", Quoted(reason))?;
+                }
+                Ok(())
+            }
+            Event::CallCleared(e) => {
+                write_ids(f, "ECallCleared", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.reason))?;
+                if let Some(reason) = &e.protocol_specific_reason {
+                    write!(f, " ProtocolSpecificReason={}", Quoted(reason))?;
+                }
+                Ok(())
+            }
+            Event::CallSendDTMFFinished(e) => write_ids(f, "ECallSendDTMFFinished", e.session_id, e.resource_id),
+            Event::CallKeyPress(e) => {
+                write_ids(f, "ECallKeyPress", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.key))?;
+                if let Some(duration) = e.duration {
+                    write!(f, " Duration={}", duration)?;
+                }
+                Ok(())
+            }
+
+            // Player Resource Events
+            Event::PlayerStarted(e) => write_ids(f, "EPlayerStarted", e.session_id, e.resource_id),
+            Event::PlayerStopped(e) => write_ids(f, "EPlayerStopped", e.session_id, e.resource_id),
+            Event::PlayerError(e) => {
+                write_ids(f, "EPlayerError", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.error_text))
+            }
+
+            // Recorder Resource Events
+            Event::RecorderStarted(e) => write_ids(f, "ERecorderStarted", e.session_id, e.resource_id),
+            Event::RecorderStopped(e) => {
+                write_ids(f, "ERecorderStopped", e.session_id, e.resource_id)?;
+                write!(f, " {}", e.reason.name)
+            }
+            Event::RecorderError(e) => {
+                write_ids(f, "ERecorderError", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.error_text))
+            }
+            Event::RecorderVoiceTrigger(e) => write_ids(f, "ERecorderVoiceTrigger", e.session_id, e.resource_id),
+
+            // RTP Channel Resource Events
+            Event::RtpChannelStartedReceiving(e) => {
+                write_ids(f, "ERtpChannelStartedReceiving", e.session_id, e.resource_id)?;
+                write!(f, " {}", e.receiver_data_address)?;
+                if let Some(addr) = &e.receiver_control_address {
+                    write!(f, " ReceiverControlAddress={}", addr)?;
+                }
+                if let Some(code) = payload_code(&e.rtp_payload_type) {
+                    write!(f, " RtpPayloadType={}", code)?;
+                }
+                Ok(())
+            }
+            Event::RtpChannelStartedSending(e) => {
+                write_ids(f, "ERtpChannelStartedSending", e.session_id, e.resource_id)?;
+                if let Some(addr) = &e.sender_control_address {
+                    write!(f, " SenderControlAddress={}", addr)?;
+                }
+                if let Some(code) = payload_code(&e.rtp_payload_type) {
+                    write!(f, " RtpPayloadType={}", code)?;
+                }
+                Ok(())
+            }
+            Event::RtpChannelSendDTMFFinished(e) => {
+                write_ids(f, "ERtpChannelSendDTMFFinished", e.session_id, e.resource_id)
+            }
+            Event::RtpChannelReceivedDTMF(e) => {
+                write_ids(f, "ERtpChannelReceivedDTMF", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.key))?;
+                if let Some(duration) = e.duration {
+                    write!(f, " Duration={}", duration)?;
+                }
+                Ok(())
+            }
+            Event::RtpChannelStopped(e) => write_ids(f, "ERtpChannelStopped", e.session_id, e.resource_id),
+
+            // Sound Device Resource Events
+            Event::SoundDeviceStarted(e) => write_ids(f, "ESoundDeviceStarted", e.session_id, e.resource_id),
+            Event::SoundDeviceStopped(e) => write_ids(f, "ESoundDeviceStopped", e.session_id, e.resource_id),
+            Event::SoundDeviceError(e) => write_ids(f, "ESoundDeviceError", e.session_id, e.resource_id),
+
+            // Fax Resource Events
+            Event::ModeChangeT38(e) => write_ids(f, "EModeChangeT38", e.session_id, e.resource_id),
+            Event::ModeChangeT38Refused(e) => write_ids(f, "EModeChangeT38Refused", e.session_id, e.resource_id),
+            Event::FaxIncoming(e) => write_ids(f, "EFaxIncoming", e.session_id, e.resource_id),
+            Event::FacsimilePageStarted(e) => {
+                write_ids(f, "EFacsimilePageStarted", e.session_id, e.resource_id)?;
+                write!(
+                    f,
+                    " {} {} {} {}",
+                    e.speed.name, e.paper_size.name, e.resolution.name, e.ecm as u16
+                )
+            }
+            Event::FacsimilePageReceived(e) => write_ids(f, "EFacsimilePageReceived", e.session_id, e.resource_id),
+            Event::FacsimilePageSent(e) => write_ids(f, "EFacsimilePageSent", e.session_id, e.resource_id),
+            Event::FaxOperationsStarted(e) => write_ids(f, "EFaxOperationsStarted", e.session_id, e.resource_id),
+            Event::FaxOperationFailed(e) => write_ids(f, "EFaxOperationFailed", e.session_id, e.resource_id),
+            Event::FaxOperationFinished(e) => write_ids(f, "EFaxOperationFinished", e.session_id, e.resource_id),
+            Event::FaxOperationAborted(e) => write_ids(f, "EFaxOperationAborted", e.session_id, e.resource_id),
+
+            // Document Resource Events
+            Event::DocumentPrepared(e) => write_ids(f, "EDocumentPrepared", e.session_id, e.resource_id),
+            Event::DocumentNotPrepared(e) => {
+                write_ids(f, "EDocumentNotPrepared", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.reason))
+            }
+            Event::DocumentSaved(e) => write_ids(f, "EDocumentSaved", e.session_id, e.resource_id),
+            Event::DocumentNotSaved(e) => {
+                write_ids(f, "EDocumentNotSaved", e.session_id, e.resource_id)?;
+                write!(f, " {}", Quoted(&e.reason))
+            }
+            Event::DocumentCleared(e) => write_ids(f, "EDocumentCleared", e.session_id, e.resource_id),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        EventLine(self, &PayloadTypeMap::default()).fmt(f)
+    }
+}
+
+impl Event {
+    /// Write the event as a protocol line, giving dynamic RTP payload types the codes
+    /// assigned in `payload_types`; the reverse of `parse_event_with`.
+    pub fn to_line_with(&self, payload_types: &PayloadTypeMap) -> String {
+        EventLine(self, payload_types).to_string()
+    }
+}
+
+/// Split a protocol line into tokens, the reverse of the quoting of `Command` arguments.
+///
+/// Whitespace separates tokens except inside double quotes, where `\"`, `\\`, `\n`, `\r`
+/// and `\t` are escapes. Quotes may open anywhere in a token, as in `CallerName="Jane Doe"`,
+/// and `""` is an empty token. Outside quotes a backslash is an ordinary character and `#`
+/// starts a comment running to the end of the line.
+pub fn tokenize(line: &str) -> Result<Vec<String>, ParseEventError> {
+    let mut tokens = Vec::new();
+    let mut token: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => break,
+            c if c.is_whitespace() => tokens.extend(token.take()),
+            '"' => {
+                let token = token.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => token.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('r') => '\r',
+                            Some('t') => '\t',
+                            Some(c) => c,
+                            None => return Err(ParseEventError::UnterminatedQuote(line.to_string())),
+                        }),
+                        Some(c) => token.push(c),
+                        None => return Err(ParseEventError::UnterminatedQuote(line.to_string())),
+                    }
+                }
+            }
+            c => token.get_or_insert_with(String::new).push(c),
+        }
     }
+    tokens.extend(token);
+    Ok(tokens)
+}
 
-    let tokens: Vec<&str> = line.split_whitespace().collect();
+pub fn parse_event(line: &str) -> Result<Event, ParseEventError> {
+    parse_event_with(line, &PayloadTypeMap::default())
+}
+
+/// Parse an event line, resolving dynamic RTP payload codes with the session's `payload_types`.
+pub fn parse_event_with(line: &str, payload_types: &PayloadTypeMap) -> Result<Event, ParseEventError> {
+    let tokens = tokenize(line)?;
+    if tokens.is_empty() {
+        return Err(ParseEventError::Other("empty line"));
+    }
+    let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
     let name = tokens[0];
 
     match name {
@@ -665,13 +927,16 @@ fn parse_event(line: &str) -> Result<Event, ParseEventError> {
         "ERtpChannelStartedReceiving" => {
             let session_id = parse_pos::<SessionId>(&tokens, 1, name)?;
             let resource_id = parse_pos::<ResourceId>(&tokens, 2, name)?;
-            let receiver_data_address = tokens.get(3).ok_or(ParseEventError::WrongArity(name.into()))?.to_string();
+            let receiver_data_address = tokens.get(3).ok_or(ParseEventError::WrongArity(name.into()))?;
+            let receiver_data_address = receiver_data_address
+                .parse()
+                .map_err(|_| ParseEventError::BadAddress(receiver_data_address.to_string()))?;
             let opts = parse_opts(&tokens, 4);
-            let receiver_control_address = opts.get("receivercontroladdress").cloned();
+            let receiver_control_address = parse_address_opt(&opts, "receivercontroladdress")?;
             let rtp_payload_type = opts
                 .get("rtppayloadtype")
                 .and_then(|v| v.parse::<u8>().ok())
-                .and_then(PayloadType::from_code);
+                .and_then(|code| payload_types.lookup(code));
 
             Ok(Event::RtpChannelStartedReceiving(RtpChannelStartedReceiving {
                 session_id,
@@ -685,11 +950,11 @@ fn parse_event(line: &str) -> Result<Event, ParseEventError> {
             let session_id = parse_pos::<SessionId>(&tokens, 1, name)?;
             let resource_id = parse_pos::<ResourceId>(&tokens, 2, name)?;
             let opts = parse_opts(&tokens, 3);
-            let sender_control_address = opts.get("sendercontroladdress").cloned();
+            let sender_control_address = parse_address_opt(&opts, "sendercontroladdress")?;
             let rtp_payload_type = opts
                 .get("rtppayloadtype")
                 .and_then(|v| v.parse::<u8>().ok())
-                .and_then(PayloadType::from_code);
+                .and_then(|code| payload_types.lookup(code));
             Ok(Event::RtpChannelStartedSending(RtpChannelStartedSending {
                 session_id,
                 resource_id,
@@ -1219,13 +1484,13 @@ mod tests {
 
     #[test]
     fn parse_rtp_channel_started_receiving() {
-        let line = "ERtpChannelStartedReceiving 1 2 receiver.data";
+        let line = "ERtpChannelStartedReceiving 1 2 10.0.0.5:40000";
         let ev: Event = serde_plain::from_str(line).unwrap();
         match ev {
             Event::RtpChannelStartedReceiving(rcsr) => {
                 assert_eq!(rcsr.session_id, 1);
                 assert_eq!(rcsr.resource_id, 2);
-                assert_eq!(rcsr.receiver_data_address, "receiver.data");
+                assert_eq!(rcsr.receiver_data_address.to_string(), "10.0.0.5:40000");
                 assert_eq!(rcsr.receiver_control_address, None);
                 assert_eq!(rcsr.rtp_payload_type, None);
             }
@@ -1235,14 +1500,14 @@ mod tests {
 
     #[test]
     fn parse_rtp_channel_started_receiving_with_options() {
-        let line = "ERtpChannelStartedReceiving 1 2 receiver.data ReceiverControlAddress=ctrl RtpPayloadType=8";
+        let line = "ERtpChannelStartedReceiving 1 2 10.0.0.5:40000 ReceiverControlAddress=10.0.0.5:40001 RtpPayloadType=8";
         let ev: Event = serde_plain::from_str(line).unwrap();
         match ev {
             Event::RtpChannelStartedReceiving(rcsr) => {
                 assert_eq!(rcsr.session_id, 1);
                 assert_eq!(rcsr.resource_id, 2);
-                assert_eq!(rcsr.receiver_data_address, "receiver.data");
-                assert_eq!(rcsr.receiver_control_address.as_deref(), Some("ctrl"));
+                assert_eq!(rcsr.receiver_data_address.to_string(), "10.0.0.5:40000");
+                assert_eq!(rcsr.receiver_control_address, "10.0.0.5:40001".parse().ok());
                 assert_eq!(rcsr.rtp_payload_type.is_some(), true);
             }
             _ => panic!("wrong variant"),
@@ -1264,15 +1529,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_rtp_channel_started_receiving_bad_address() {
+        let line = "ERtpChannelStartedReceiving 1 2 receiver.data";
+        assert!(matches!(parse_event(line), Err(ParseEventError::BadAddress(a)) if a == "receiver.data"));
+        let line = "ERtpChannelStartedReceiving 1 2 10.0.0.5:40000 ReceiverControlAddress=10.0.0.5";
+        assert!(matches!(parse_event(line), Err(ParseEventError::BadAddress(_))));
+    }
+
+    #[test]
+    fn parse_rtp_channel_started_with_dynamic_payload_type() {
+        use crate::constants::PayloadType_SPEEX_8K;
+
+        let line = "ERtpChannelStartedSending 1 2 RtpPayloadType=97";
+        let mut payload_types = PayloadTypeMap::default();
+        match parse_event_with(line, &payload_types).unwrap() {
+            Event::RtpChannelStartedSending(rcss) => assert_eq!(rcss.rtp_payload_type, None),
+            _ => panic!("wrong variant"),
+        }
+
+        payload_types.insert(97, PayloadType_SPEEX_8K).unwrap();
+        match parse_event_with(line, &payload_types).unwrap() {
+            Event::RtpChannelStartedSending(rcss) => {
+                assert_eq!(rcss.rtp_payload_type, Some(PayloadType_SPEEX_8K))
+            }
+            _ => panic!("wrong variant"),
+        }
+    }
+
     #[test]
     fn parse_rtp_channel_started_sending_with_options() {
-        let line = "ERtpChannelStartedSending 1 2 SenderControlAddress=ctrl RtpPayloadType=8";
+        let line = "ERtpChannelStartedSending 1 2 SenderControlAddress=[2001:db8::7]:6001 RtpPayloadType=8";
         let ev: Event = serde_plain::from_str(line).unwrap();
         match ev {
             Event::RtpChannelStartedSending(rcss) => {
                 assert_eq!(rcss.session_id, 1);
                 assert_eq!(rcss.resource_id, 2);
-                assert_eq!(rcss.sender_control_address.as_deref(), Some("ctrl"));
+                assert_eq!(rcss.sender_control_address.map(|a| a.to_string()).as_deref(), Some("[2001:db8::7]:6001"));
                 assert_eq!(rcss.rtp_payload_type.is_some(), true);
             }
             _ => panic!("wrong variant"),
@@ -1585,4 +1878,96 @@ mod tests {
         let result = serde_plain::from_str::<Event>(line);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn tokenize_quoted_values() {
+        let tokens = tokenize(r#"ECallCleared 1 2 "Ended by \"remote\"" X="a b\\c\td" "" # comment"#).unwrap();
+        assert_eq!(tokens, ["ECallCleared", "1", "2", "Ended by \"remote\"", "X=a b\\c\td", ""]);
+        assert_eq!(tokenize(r"a\b c").unwrap(), ["a\\b", "c"]);
+        assert_eq!(tokenize("  # only a comment").unwrap(), Vec::<String>::new());
+        assert!(matches!(tokenize("ECallCleared 1 2 \"open"), Err(ParseEventError::UnterminatedQuote(_))));
+    }
+
+    #[test]
+    fn parse_quoted_reason_round_trip() {
+        let reason = "Rejected: \"busy\" # here";
+        let line = crate::commands::Command::call_clear(2, Some(reason.to_string()))
+            .to_string()
+            .replace("CallClear", "ECallCleared 1")
+            .replace("Reason=", "");
+        match parse_event(&line).unwrap() {
+            Event::CallCleared(e) => assert_eq!(e.reason, reason),
+            _ => panic!("wrong variant"),
+        }
+    }
+
+    #[test]
+    fn display_round_trips_every_event() {
+        let lines = [
+            "ESessionCreated 1",
+            "ESessionDeleted 1",
+            "EResourceCreated 1 2",
+            "EResourceDeleted 1 2",
+            "EAudioLevelNotification 1 2 true 42",
+            "EStreamBufferStateNotification 1 2 Underrun",
+            "ECallIncoming 1 2 call-7 ANI=100 DNIS=200 RDN=300 RemoteName=\"Jane Doe\" RemoteAddress=sip:jane@example.com",
+            "ECallIncoming 1 2 call-8",
+            "ECallOutgoing 1 2 sip:bob@example.com call-9",
+            "ECallRemoteAlerting 1 2 User=bob",
+            "ECallConnectionEstablished 1 2",
+            "ECallConnectionFailed 1 2 NoAnswer ProtocolSpecificReason=\"480 Temporarily Unavailable\"",
+            "ECallCleared 1 2 EndedByRemoteUser",
+            "ECallSendDTMFFinished 1 2",
+            "ECallKeyPress 1 2 \"#\" Duration=120",
+            "EPlayerStarted 1 2",
+            "EPlayerStopped 1 2",
+            "EPlayerError 1 2 \"file not found\"",
+            "ERecorderStarted 1 2",
+            "ERecorderStopped 1 2 ExplicitRequest",
+            "ERecorderError 1 2 DiskFull",
+            "ERecorderVoiceTrigger 1 2",
+            "ERtpChannelStartedReceiving 1 2 10.0.0.2:5000 ReceiverControlAddress=10.0.0.2:5001 RtpPayloadType=8",
+            "ERtpChannelStartedSending 1 2 SenderControlAddress=[::1]:6001 RtpPayloadType=0",
+            "ERtpChannelSendDTMFFinished 1 2",
+            "ERtpChannelReceivedDTMF 1 2 5 Duration=80",
+            "ERtpChannelStopped 1 2",
+            "ESoundDeviceStarted 1 2",
+            "ESoundDeviceStopped 1 2",
+            "ESoundDeviceError 1 2",
+            "EModeChangeT38 1 2",
+            "EModeChangeT38Refused 1 2",
+            "EFaxIncoming 1 2",
+            "EFacsimilePageStarted 1 2 V27At2400 A4 High 256",
+            "EFacsimilePageReceived 1 2",
+            "EFacsimilePageSent 1 2",
+            "EFaxOperationsStarted 1 2",
+            "EFaxOperationFailed 1 2",
+            "EFaxOperationFinished 1 2",
+            "EFaxOperationAborted 1 2",
+            "EDocumentPrepared 1 2",
+            "EDocumentNotPrepared 1 2 \"unsupported image\"",
+            "EDocumentSaved 1 2",
+            "EDocumentNotSaved 1 2 AccessDenied",
+            "EDocumentCleared 1 2",
+        ];
+        for line in lines {
+            let event = parse_event(line).unwrap_or_else(|e| panic!("{}: {}", line, e));
+            assert_eq!(event.to_string(), line);
+            assert_eq!(parse_event(&event.to_string()).unwrap(), event);
+            assert_eq!(serde_plain::to_string(&event).unwrap(), line);
+        }
+    }
+
+    #[test]
+    fn display_uses_session_payload_codes() {
+        use crate::constants::PayloadType_SPEEX_8K;
+
+        let mut payload_types = PayloadTypeMap::default();
+        payload_types.insert(97, PayloadType_SPEEX_8K).unwrap();
+        let line = "ERtpChannelStartedSending 1 2 RtpPayloadType=97";
+        let event = parse_event_with(line, &payload_types).unwrap();
+        assert_eq!(event.to_line_with(&payload_types), line);
+        assert_eq!(event.to_string(), "ERtpChannelStartedSending 1 2");
+    }
 }
+
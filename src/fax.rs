@@ -0,0 +1,1639 @@
+use crate::commands::Command;
+use crate::constants::{
+    DocumentPreparePaperSize, DocumentPrepareResolution, DocumentSaveType, DocumentSaveType_TIFF, FaxReceiveMode,
+    FaxSendSpeed, FaxSendSpeed_V27At4800, FaxSendSpeed_V29At9600,
+};
+use crate::events::Event;
+use crate::primitives::{unix_time, FaxIdentifier, ResourceId, ECM};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::time::Instant;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "fax")?;
+
+    child_module.add_class::<FaxRetryStep>()?;
+    child_module.add_class::<FaxRetryPolicy>()?;
+    child_module.add_class::<HeaderValues>()?;
+    child_module.add_class::<HeaderTemplate>()?;
+    child_module.add_class::<FaxSendOptions>()?;
+    child_module.add_class::<FaxFailure>()?;
+    child_module.add_class::<FaxProgress>()?;
+    child_module.add_class::<FaxReport>()?;
+    child_module.add_class::<FaxSendJob>()?;
+    child_module.add_class::<FaxReceiveOptions>()?;
+    child_module.add_class::<FaxPage>()?;
+    child_module.add_class::<ReceivedFax>()?;
+    child_module.add_class::<FaxReceiveHandler>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+const DEFAULT_JOB_TIMEOUT_MS: u64 = 15 * 60 * 1000;
+
+/// Failure kinds retried by default: a lower speed or other ECM setting is likely to help
+/// with those, but not with a busy line or a bad document.
+const DEFAULT_RETRY_ON: [&str; 2] = ["negotiation", "transmission"];
+
+/// How one retry differs from the attempt before it: `speed` replaces the `FaxSendSpeed`
+/// (`None` keeps it) and `toggle_ecm` switches ECM off if it was on, or on if it was off.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxRetryStep {
+    #[pyo3(get, set)]
+    pub speed: Option<FaxSendSpeed>,
+    #[pyo3(get, set)]
+    pub toggle_ecm: bool,
+}
+
+#[pymethods]
+impl FaxRetryStep {
+    #[new]
+    #[pyo3(signature = (speed=None, toggle_ecm=false))]
+    fn py_new(speed: Option<FaxSendSpeed>, toggle_ecm: bool) -> Self {
+        FaxRetryStep { speed, toggle_ecm }
+    }
+}
+
+/// When and how a failed fax is sent again, one `steps` entry per retry.
+///
+/// Only failures whose kind (see `FaxFailure`) is in `retry_on` are retried. The server
+/// cannot start a document part-way, so a retry sends the whole document again, unless
+/// `single_page_files` says each file is one page: the files already sent are then left
+/// out and the retry resumes at the failed page.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxRetryPolicy {
+    #[pyo3(get, set)]
+    pub steps: Vec<FaxRetryStep>,
+    #[pyo3(get, set)]
+    pub retry_on: Vec<String>,
+    #[pyo3(get, set)]
+    pub single_page_files: bool,
+}
+
+impl Default for FaxRetryPolicy {
+    /// Down from V.17 to V.29 at 9600, then V.27 at 4800, then the same with ECM toggled.
+    fn default() -> Self {
+        let step = |speed, toggle_ecm| FaxRetryStep {
+            speed: Some(speed),
+            toggle_ecm,
+        };
+        FaxRetryPolicy {
+            steps: vec![
+                step(FaxSendSpeed_V29At9600, false),
+                step(FaxSendSpeed_V27At4800, false),
+                step(FaxSendSpeed_V27At4800, true),
+            ],
+            retry_on: DEFAULT_RETRY_ON.map(String::from).to_vec(),
+            single_page_files: false,
+        }
+    }
+}
+
+#[pymethods]
+impl FaxRetryPolicy {
+    #[new]
+    #[pyo3(signature = (steps=None, retry_on=None, single_page_files=false))]
+    fn py_new(steps: Option<Vec<FaxRetryStep>>, retry_on: Option<Vec<String>>, single_page_files: bool) -> Self {
+        let default = FaxRetryPolicy::default();
+        FaxRetryPolicy {
+            steps: steps.unwrap_or(default.steps),
+            retry_on: retry_on.unwrap_or(default.retry_on),
+            single_page_files,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum HeaderError {
+    #[error("unknown header placeholder {{{0}}}")]
+    UnknownPlaceholder(String),
+    #[error("unmatched brace at position {0} of the header template")]
+    UnmatchedBrace(usize),
+    #[error("header contains a control character")]
+    ControlCharacter,
+}
+
+impl From<HeaderError> for PyErr {
+    fn from(err: HeaderError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+const HEADER_PLACEHOLDERS: [&str; 6] = ["date", "time", "sender", "number", "page", "pages"];
+
+#[derive(Clone, Debug, PartialEq)]
+enum HeaderPart {
+    Text(String),
+    Placeholder(&'static str),
+}
+
+/// Values filled into a `HeaderTemplate`; `time` is in seconds since the Unix epoch.
+#[pyclass]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderValues {
+    #[pyo3(get, set)]
+    pub time: u64,
+    #[pyo3(get, set)]
+    pub sender: Option<String>,
+    #[pyo3(get, set)]
+    pub number: Option<String>,
+    #[pyo3(get, set)]
+    pub page: Option<u32>,
+    #[pyo3(get, set)]
+    pub pages: Option<u32>,
+}
+
+#[pymethods]
+impl HeaderValues {
+    #[new]
+    #[pyo3(signature = (time=None, sender=None, number=None, page=None, pages=None))]
+    fn py_new(
+        time: Option<u64>,
+        sender: Option<String>,
+        number: Option<String>,
+        page: Option<u32>,
+        pages: Option<u32>,
+    ) -> Self {
+        HeaderValues {
+            time: time.unwrap_or_else(unix_time),
+            sender,
+            number,
+            page,
+            pages,
+        }
+    }
+}
+
+/// A fax header with placeholders, rendered just before `FaxSend`.
+///
+/// `{date}` and `{time}` are the UTC date (`YYYY-MM-DD`) and time (`HH:MM`) of sending,
+/// `{sender}` the TSI or else the caller number, `{number}` the number dialled, `{page}`
+/// the first page sent by the attempt and `{pages}` the page count, known only for
+/// single-page files. Unknown values render as `?`, and `{{` and `}}` stand for braces.
+/// The header goes out once per `FaxSend`, so `{page}` is not advanced page by page.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderTemplate {
+    #[pyo3(get)]
+    template: String,
+    parts: Vec<HeaderPart>,
+}
+
+impl HeaderTemplate {
+    pub fn parse(template: &str) -> Result<Self, HeaderError> {
+        if template.chars().any(char::is_control) {
+            return Err(HeaderError::ControlCharacter);
+        }
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.char_indices().peekable();
+        while let Some((at, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => text.push('{'),
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => text.push('}'),
+                '{' => {
+                    let rest = &template[at + 1..];
+                    let name = &rest[..rest.find('}').ok_or(HeaderError::UnmatchedBrace(at))?];
+                    let placeholder = HEADER_PLACEHOLDERS
+                        .into_iter()
+                        .find(|&p| p == name)
+                        .ok_or_else(|| HeaderError::UnknownPlaceholder(name.to_string()))?;
+                    parts.push(HeaderPart::Text(std::mem::take(&mut text)));
+                    parts.push(HeaderPart::Placeholder(placeholder));
+                    // skip the name and closing brace
+                    chars.nth(name.chars().count());
+                }
+                '}' => return Err(HeaderError::UnmatchedBrace(at)),
+                c => text.push(c),
+            }
+        }
+        parts.push(HeaderPart::Text(text));
+        parts.retain(|part| *part != HeaderPart::Text(String::new()));
+        Ok(HeaderTemplate {
+            template: template.to_string(),
+            parts,
+        })
+    }
+
+    /// The template as it was given.
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    pub fn render(&self, values: &HeaderValues) -> String {
+        let (year, month, day, hour, minute, _) = utc_fields(values.time);
+        let known = |value: Option<String>| value.unwrap_or_else(|| "?".to_string());
+        self.parts
+            .iter()
+            .map(|part| match *part {
+                HeaderPart::Text(ref text) => text.clone(),
+                HeaderPart::Placeholder("date") => format!("{:04}-{:02}-{:02}", year, month, day),
+                HeaderPart::Placeholder("time") => format!("{:02}:{:02}", hour, minute),
+                HeaderPart::Placeholder("sender") => known(values.sender.clone()),
+                HeaderPart::Placeholder("number") => known(values.number.clone()),
+                HeaderPart::Placeholder("page") => known(values.page.map(|p| p.to_string())),
+                HeaderPart::Placeholder(_) => known(values.pages.map(|p| p.to_string())),
+            })
+            .collect()
+    }
+}
+
+#[pymethods]
+impl HeaderTemplate {
+    #[new]
+    fn py_new(template: &str) -> PyResult<Self> {
+        Ok(HeaderTemplate::parse(template)?)
+    }
+
+    #[pyo3(name = "render")]
+    fn py_render(&self, values: HeaderValues) -> String {
+        self.render(&values)
+    }
+}
+
+/// Settings of a fax send job; those left as `None` are omitted from the commands.
+///
+/// `header` is given as a template string and kept as the `HeaderTemplate` it parses to,
+/// rendered at each `FaxSend`. `call_timeout` is the
+/// `CallMake` timeout in ms, `job_timeout_ms` bounds the whole job, retries included. Without `retry` a failed fax is not sent again.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxSendOptions {
+    #[pyo3(get, set)]
+    pub speed: Option<FaxSendSpeed>,
+    #[pyo3(get, set)]
+    pub ecm: Option<ECM>,
+    #[pyo3(get, set)]
+    pub header: Option<HeaderTemplate>,
+    #[pyo3(get, set)]
+    pub tsi: Option<FaxIdentifier>,
+    #[pyo3(get, set)]
+    pub paper_size: Option<DocumentPreparePaperSize>,
+    #[pyo3(get, set)]
+    pub resolution: Option<DocumentPrepareResolution>,
+    #[pyo3(get, set)]
+    pub caller_number: Option<String>,
+    #[pyo3(get, set)]
+    pub call_timeout: Option<u32>,
+    #[pyo3(get, set)]
+    pub job_timeout_ms: u64,
+    #[pyo3(get, set)]
+    pub retry: Option<FaxRetryPolicy>,
+}
+
+impl Default for FaxSendOptions {
+    fn default() -> Self {
+        FaxSendOptions {
+            speed: None,
+            ecm: None,
+            header: None,
+            tsi: None,
+            paper_size: None,
+            resolution: None,
+            caller_number: None,
+            call_timeout: None,
+            job_timeout_ms: DEFAULT_JOB_TIMEOUT_MS,
+            retry: None,
+        }
+    }
+}
+
+#[pymethods]
+impl FaxSendOptions {
+    // one argument per option, so that Python can give each by keyword
+    #[allow(clippy::too_many_arguments)]
+    #[new]
+    #[pyo3(signature = (speed=None, ecm=None, header=None, tsi=None, paper_size=None, resolution=None, caller_number=None, call_timeout=None, job_timeout_ms=DEFAULT_JOB_TIMEOUT_MS, retry=None))]
+    fn py_new(
+        speed: Option<FaxSendSpeed>,
+        ecm: Option<ECM>,
+        header: Option<String>,
+        tsi: Option<FaxIdentifier>,
+        paper_size: Option<DocumentPreparePaperSize>,
+        resolution: Option<DocumentPrepareResolution>,
+        caller_number: Option<String>,
+        call_timeout: Option<u32>,
+        job_timeout_ms: u64,
+        retry: Option<FaxRetryPolicy>,
+    ) -> PyResult<Self> {
+        Ok(FaxSendOptions {
+            speed,
+            ecm,
+            header: header.as_deref().map(HeaderTemplate::parse).transpose()?,
+            tsi,
+            paper_size,
+            resolution,
+            caller_number,
+            call_timeout,
+            job_timeout_ms,
+            retry,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    Preparing,
+    Dialing,
+    Connected,
+    Sending,
+    Retrying,
+    Completed,
+    Failed,
+}
+
+impl State {
+    fn name(self) -> &'static str {
+        match self {
+            State::Idle => "idle",
+            State::Preparing => "preparing",
+            State::Dialing => "dialing",
+            State::Connected => "connected",
+            State::Sending => "sending",
+            State::Retrying => "retrying",
+            State::Completed => "completed",
+            State::Failed => "failed",
+        }
+    }
+}
+
+/// Where a fax send job is, reported on every step.
+///
+/// `page` is the page being sent (from 1), `pages_sent` those confirmed so far. Speed,
+/// paper size, resolution and ECM are those negotiated for the current page.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxProgress {
+    #[pyo3(get)]
+    state: &'static str,
+    #[pyo3(get)]
+    page: u32,
+    #[pyo3(get)]
+    pages_sent: u32,
+    #[pyo3(get)]
+    speed: Option<FaxSendSpeed>,
+    #[pyo3(get)]
+    paper_size: Option<DocumentPreparePaperSize>,
+    #[pyo3(get)]
+    resolution: Option<DocumentPrepareResolution>,
+    #[pyo3(get)]
+    ecm: Option<ECM>,
+}
+
+/// Why an attempt of a fax send job failed, with what was known at the time.
+///
+/// `kind` is one of `document`, `busy`, `no_answer`, `call_failed`, `hangup`, `negotiation`
+/// (the fax failed before any page started), `transmission` (it failed during a page),
+/// `aborted` or `timeout`; `state` is where the job was. `page` is the page being sent, and
+/// speed, paper size, resolution and ECM are those of its `EFacsimilePageStarted`.
+/// `send_speed` and `send_ecm` are what the attempt asked for in `FaxSend`.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxFailure {
+    #[pyo3(get)]
+    attempt: u32,
+    #[pyo3(get)]
+    pub(crate) kind: &'static str,
+    #[pyo3(get)]
+    state: &'static str,
+    #[pyo3(get)]
+    reason: String,
+    #[pyo3(get)]
+    page: Option<u32>,
+    #[pyo3(get)]
+    pages_sent: u32,
+    #[pyo3(get)]
+    speed: Option<FaxSendSpeed>,
+    #[pyo3(get)]
+    paper_size: Option<DocumentPreparePaperSize>,
+    #[pyo3(get)]
+    resolution: Option<DocumentPrepareResolution>,
+    #[pyo3(get)]
+    ecm: Option<ECM>,
+    #[pyo3(get)]
+    send_speed: Option<FaxSendSpeed>,
+    #[pyo3(get)]
+    send_ecm: Option<ECM>,
+}
+
+/// The kind of failure for a call that could not be made.
+fn call_failure_kind(reason: &str) -> &'static str {
+    if reason.contains("Busy") {
+        "busy"
+    } else if reason.contains("NoAnswer") {
+        "no_answer"
+    } else {
+        "call_failed"
+    }
+}
+
+/// Outcome of a fax send job.
+///
+/// `failures` holds one entry per failed attempt; after a failed job the last one is why.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxReport {
+    #[pyo3(get)]
+    number: String,
+    #[pyo3(get)]
+    pub(crate) success: bool,
+    #[pyo3(get)]
+    pub(crate) pages_sent: u32,
+    #[pyo3(get)]
+    pub(crate) failure: Option<String>,
+    #[pyo3(get)]
+    speed: Option<FaxSendSpeed>,
+    #[pyo3(get)]
+    resolution: Option<DocumentPrepareResolution>,
+    #[pyo3(get)]
+    ecm: Option<ECM>,
+    #[pyo3(get)]
+    duration_ms: u64,
+    #[pyo3(get)]
+    attempts: u32,
+    #[pyo3(get)]
+    pub(crate) failures: Vec<FaxFailure>,
+}
+
+/// Sending files as a fax over a new call.
+///
+/// The job drives the front-end, fax and document resources it is given: `start` adds the
+/// files and prepares the document, then each `handle_event` returns the next commands
+/// (`CallMake` once prepared, `FaxSend` once connected) and records progress, until the
+/// fax finishes or fails and the call is cleared and the resources deleted.
+///
+/// A failure allowed by `options.retry` clears the call and dials again with the settings
+/// of the next retry step instead, re-preparing the document first if it resumes part-way.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct FaxSendJob {
+    #[pyo3(get)]
+    number: String,
+    #[pyo3(get)]
+    files: Vec<String>,
+    #[pyo3(get)]
+    options: FaxSendOptions,
+    #[pyo3(get)]
+    call: ResourceId,
+    #[pyo3(get)]
+    fax: ResourceId,
+    #[pyo3(get)]
+    document: ResourceId,
+    state: State,
+    progress: FaxProgress,
+    pending: Vec<FaxProgress>,
+    failure: Option<String>,
+    call_up: bool,
+    started: Option<Instant>,
+    duration_ms: u64,
+    attempt: u32,
+    speed: Option<FaxSendSpeed>,
+    ecm: Option<ECM>,
+    page_started: bool,
+    /// Index of the first file in the document, and of the one the next attempt starts at;
+    /// with single-page files this is also the number of pages already sent.
+    prepared_from: usize,
+    resume_from: usize,
+    aborted: Option<&'static str>,
+    failures: Vec<FaxFailure>,
+}
+
+impl FaxSendJob {
+    pub fn new(
+        number: &str,
+        files: Vec<String>,
+        options: FaxSendOptions,
+        call: ResourceId,
+        fax: ResourceId,
+        document: ResourceId,
+    ) -> Self {
+        FaxSendJob {
+            number: number.to_string(),
+            files,
+            speed: options.speed,
+            ecm: options.ecm,
+            options,
+            call,
+            fax,
+            document,
+            state: State::Idle,
+            progress: FaxProgress {
+                state: State::Idle.name(),
+                page: 0,
+                pages_sent: 0,
+                speed: None,
+                paper_size: None,
+                resolution: None,
+                ecm: None,
+            },
+            pending: Vec::new(),
+            failure: None,
+            call_up: false,
+            started: None,
+            duration_ms: 0,
+            attempt: 1,
+            page_started: false,
+            prepared_from: 0,
+            resume_from: 0,
+            aborted: None,
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Completed | State::Failed)
+    }
+
+    pub fn state(&self) -> &'static str {
+        self.state.name()
+    }
+
+    /// The front-end, fax and document resource of the job.
+    pub fn resources(&self) -> [ResourceId; 3] {
+        [self.call, self.fax, self.document]
+    }
+
+    pub fn progress(&self) -> &FaxProgress {
+        &self.progress
+    }
+
+    /// Progress reported since the previous call, oldest first.
+    pub fn take_progress(&mut self) -> Vec<FaxProgress> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// The final report, once the job has finished.
+    pub fn report(&self) -> Option<FaxReport> {
+        self.is_finished().then(|| FaxReport {
+            number: self.number.clone(),
+            success: self.state == State::Completed,
+            pages_sent: self.progress.pages_sent,
+            failure: self.failure.clone(),
+            speed: self.progress.speed,
+            resolution: self.progress.resolution,
+            ecm: self.progress.ecm,
+            duration_ms: self.duration_ms,
+            attempts: self.attempt,
+            failures: self.failures.clone(),
+        })
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+        self.progress.state = state.name();
+        self.pending.push(self.progress.clone());
+    }
+
+    pub fn start(&mut self) -> Vec<Command> {
+        if self.state != State::Idle {
+            return Vec::new();
+        }
+        self.started = Some(Instant::now());
+        self.prepare()
+    }
+
+    /// Add the files from `resume_from` on to the document and prepare it, clearing what a
+    /// previous attempt left in it.
+    fn prepare(&mut self) -> Vec<Command> {
+        let mut commands = Vec::new();
+        if self.state != State::Idle {
+            commands.push(Command::document_clear(self.document));
+        }
+        self.set_state(State::Preparing);
+        self.prepared_from = self.resume_from;
+        commands.extend(
+            self.files[self.resume_from..]
+                .iter()
+                .map(|file| Command::document_add_file(self.document, file.clone(), None)),
+        );
+        commands.push(Command::document_prepare(
+            self.document,
+            self.options.paper_size,
+            self.options.resolution,
+        ));
+        commands
+    }
+
+    /// The header template rendered for the attempt about to start.
+    fn header(&self, time: u64) -> Option<String> {
+        let template = self.options.header.as_ref()?;
+        let single_page_files = self.options.retry.as_ref().is_some_and(|retry| retry.single_page_files);
+        Some(template.render(&HeaderValues {
+            time,
+            sender: self.options.tsi.as_ref().map(ToString::to_string).or(self.options.caller_number.clone()),
+            number: Some(self.number.clone()),
+            page: Some(self.prepared_from as u32 + 1),
+            pages: single_page_files.then_some(self.files.len() as u32),
+        }))
+    }
+
+    fn dial(&mut self) -> Vec<Command> {
+        self.set_state(State::Dialing);
+        vec![Command::call_make(
+            self.call,
+            self.number.clone(),
+            self.options.call_timeout,
+            self.options.caller_number.clone(),
+            None,
+            None,
+            None,
+        )]
+    }
+
+    /// Follow an event from the server, returning the commands it calls for.
+    pub fn handle_event(&mut self, event: &Event) -> Vec<Command> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        match event {
+            Event::DocumentPrepared(e) if e.resource_id == self.document && self.state == State::Preparing => {
+                self.dial()
+            }
+            Event::DocumentNotPrepared(e) if e.resource_id == self.document => {
+                self.fail("document", format!("document not prepared: {}", e.reason))
+            }
+            Event::CallConnectionEstablished(e) if e.resource_id == self.call && self.state == State::Dialing => {
+                self.call_up = true;
+                self.set_state(State::Connected);
+                vec![Command::fax_send(
+                    self.fax,
+                    self.call,
+                    self.document,
+                    self.speed,
+                    self.ecm,
+                    self.header(unix_time()),
+                    self.options.tsi.clone(),
+                )]
+            }
+            Event::CallConnectionFailed(e) if e.resource_id == self.call => {
+                self.fail(call_failure_kind(&e.reason), format!("call failed: {}", e.reason))
+            }
+            Event::CallCleared(e) if e.resource_id == self.call && self.state == State::Retrying => self.redial(),
+            Event::CallCleared(e) if e.resource_id == self.call => {
+                self.call_up = false;
+                self.fail("hangup", format!("call cleared: {}", e.reason))
+            }
+            Event::FaxOperationsStarted(e) if e.resource_id == self.fax => {
+                self.set_state(State::Sending);
+                Vec::new()
+            }
+            Event::FacsimilePageStarted(e) if e.resource_id == self.fax => {
+                self.page_started = true;
+                self.progress.page = self.progress.pages_sent + 1;
+                self.progress.speed = Some(e.speed);
+                self.progress.paper_size = Some(e.paper_size);
+                self.progress.resolution = Some(e.resolution);
+                self.progress.ecm = Some(e.ecm);
+                self.set_state(State::Sending);
+                Vec::new()
+            }
+            Event::FacsimilePageSent(e) if e.resource_id == self.fax => {
+                self.progress.pages_sent += 1;
+                self.set_state(State::Sending);
+                Vec::new()
+            }
+            Event::FaxOperationFinished(e) if e.resource_id == self.fax => self.finish(State::Completed),
+            Event::FaxOperationFailed(e) if e.resource_id == self.fax => {
+                let kind = if self.page_started { "transmission" } else { "negotiation" };
+                self.fail(kind, "fax operation failed".into())
+            }
+            Event::FaxOperationAborted(e) if e.resource_id == self.fax => {
+                self.fail(self.aborted.unwrap_or("aborted"), "fax operation aborted".into())
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Give up on the job. A fax in progress is aborted and finishes on `FaxOperationAborted`;
+    /// otherwise the job fails at once. An aborted job is not retried.
+    pub fn abort(&mut self, reason: &str) -> Vec<Command> {
+        self.stop("aborted", reason)
+    }
+
+    /// Abort the job because it has run out of time.
+    pub fn time_out(&mut self) -> Vec<Command> {
+        self.stop("timeout", "job timed out")
+    }
+
+    /// End the job at once, without waiting for the server, when it cannot go on: the call is
+    /// cleared if it may be up and the resources are deleted. Nothing is left to do once the
+    /// job has finished.
+    pub fn release(&mut self, reason: &str) -> Vec<Command> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        self.failure.get_or_insert_with(|| reason.to_string());
+        self.call_up |= matches!(self.state, State::Dialing | State::Connected | State::Sending);
+        self.finish(State::Failed)
+    }
+
+    fn stop(&mut self, kind: &'static str, reason: &str) -> Vec<Command> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        self.aborted.get_or_insert(kind);
+        self.failure.get_or_insert_with(|| reason.to_string());
+        match self.state {
+            State::Connected | State::Sending => vec![Command::fax_abort(self.fax)],
+            _ => self.fail(kind, reason.to_string()),
+        }
+    }
+
+    /// Record why the attempt failed, then retry it if the policy allows or end the job.
+    fn fail(&mut self, kind: &'static str, reason: String) -> Vec<Command> {
+        let reason = self.failure.get_or_insert(reason).clone();
+        self.failures.push(FaxFailure {
+            attempt: self.attempt,
+            kind,
+            state: self.state.name(),
+            reason,
+            page: self.page_started.then_some(self.progress.page),
+            pages_sent: self.progress.pages_sent,
+            speed: self.progress.speed,
+            paper_size: self.progress.paper_size,
+            resolution: self.progress.resolution,
+            ecm: self.progress.ecm,
+            send_speed: self.speed,
+            send_ecm: self.ecm,
+        });
+        match self.retry(kind) {
+            Some(commands) => commands,
+            None => self.finish(State::Failed),
+        }
+    }
+
+    /// Move on to the next retry step, if the policy has one for this kind of failure.
+    fn retry(&mut self, kind: &str) -> Option<Vec<Command>> {
+        let policy = self.options.retry.as_ref().filter(|_| self.aborted.is_none())?;
+        if !policy.retry_on.iter().any(|k| k == kind) {
+            return None;
+        }
+        let step = policy.steps.get(self.attempt as usize - 1)?;
+        let resume_from = if policy.single_page_files {
+            self.progress.pages_sent as usize
+        } else {
+            0
+        };
+        if resume_from >= self.files.len() {
+            return None;
+        }
+
+        self.speed = step.speed.or(self.speed);
+        if step.toggle_ecm {
+            self.ecm = Some(match self.ecm.or(self.progress.ecm) {
+                Some(ECM::No) => ECM::ECM256,
+                _ => ECM::No,
+            });
+        }
+        self.attempt += 1;
+        self.resume_from = resume_from;
+        self.failure = None;
+        self.page_started = false;
+        self.progress = FaxProgress {
+            state: self.progress.state,
+            page: 0,
+            pages_sent: resume_from as u32,
+            speed: None,
+            paper_size: None,
+            resolution: None,
+            ecm: None,
+        };
+        self.set_state(State::Retrying);
+        Some(if std::mem::take(&mut self.call_up) {
+            // dialled again once the call has cleared
+            vec![Command::call_clear(self.call, None)]
+        } else {
+            self.redial()
+        })
+    }
+
+    fn redial(&mut self) -> Vec<Command> {
+        if self.resume_from != self.prepared_from {
+            self.prepare()
+        } else {
+            self.dial()
+        }
+    }
+
+    /// Clear the call if it is still up and delete the job's resources.
+    fn finish(&mut self, state: State) -> Vec<Command> {
+        self.duration_ms = self.started.map_or(0, |started| started.elapsed().as_millis() as u64);
+        self.set_state(state);
+        let mut commands = Vec::new();
+        if std::mem::take(&mut self.call_up) {
+            commands.push(Command::call_clear(self.call, None));
+        }
+        commands.extend([self.document, self.fax, self.call].map(Command::resource_delete));
+        commands
+    }
+}
+
+#[pymethods]
+impl FaxSendJob {
+    #[new]
+    #[pyo3(signature = (number, files, call, fax, document, options=FaxSendOptions::default()))]
+    fn py_new(
+        number: &str,
+        files: Vec<String>,
+        call: ResourceId,
+        fax: ResourceId,
+        document: ResourceId,
+        options: FaxSendOptions,
+    ) -> Self {
+        FaxSendJob::new(number, files, options, call, fax, document)
+    }
+
+    #[getter(finished)]
+    fn py_finished(&self) -> bool {
+        self.is_finished()
+    }
+
+    #[getter(state)]
+    fn py_state(&self) -> &'static str {
+        self.state()
+    }
+
+    #[getter(progress)]
+    fn py_progress(&self) -> FaxProgress {
+        self.progress.clone()
+    }
+
+    #[pyo3(name = "take_progress")]
+    fn py_take_progress(&mut self) -> Vec<FaxProgress> {
+        self.take_progress()
+    }
+
+    #[pyo3(name = "report")]
+    fn py_report(&self) -> Option<FaxReport> {
+        self.report()
+    }
+
+    #[pyo3(name = "start")]
+    fn py_start(&mut self) -> Vec<Command> {
+        self.start()
+    }
+
+    #[pyo3(name = "handle_event")]
+    fn py_handle_event(&mut self, event: Event) -> Vec<Command> {
+        self.handle_event(&event)
+    }
+
+    #[pyo3(name = "abort")]
+    #[pyo3(signature = (reason="aborted"))]
+    fn py_abort(&mut self, reason: &str) -> Vec<Command> {
+        self.abort(reason)
+    }
+
+    #[pyo3(name = "time_out")]
+    fn py_time_out(&mut self) -> Vec<Command> {
+        self.time_out()
+    }
+
+    #[pyo3(name = "release")]
+    fn py_release(&mut self, reason: &str) -> Vec<Command> {
+        self.release(reason)
+    }
+}
+
+/// Settings of the fax receive handler.
+///
+/// Received documents are saved in `directory` on the server, as multipage files of
+/// `document_type` named `<UTC time>_<ANI>_<DNIS>.<ext>`.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxReceiveOptions {
+    #[pyo3(get, set)]
+    pub directory: String,
+    #[pyo3(get, set)]
+    pub document_type: DocumentSaveType,
+    #[pyo3(get, set)]
+    pub fax_mode: Option<FaxReceiveMode>,
+    #[pyo3(get, set)]
+    pub ecm: Option<ECM>,
+    #[pyo3(get, set)]
+    pub csi: Option<FaxIdentifier>,
+}
+
+impl Default for FaxReceiveOptions {
+    fn default() -> Self {
+        FaxReceiveOptions {
+            directory: String::new(),
+            document_type: DocumentSaveType_TIFF,
+            fax_mode: None,
+            ecm: None,
+            csi: None,
+        }
+    }
+}
+
+#[pymethods]
+impl FaxReceiveOptions {
+    #[new]
+    #[pyo3(signature = (directory=String::new(), document_type=DocumentSaveType_TIFF, fax_mode=None, ecm=None, csi=None))]
+    fn py_new(
+        directory: String,
+        document_type: DocumentSaveType,
+        fax_mode: Option<FaxReceiveMode>,
+        ecm: Option<ECM>,
+        csi: Option<FaxIdentifier>,
+    ) -> Self {
+        FaxReceiveOptions {
+            directory,
+            document_type,
+            fax_mode,
+            ecm,
+            csi,
+        }
+    }
+}
+
+/// Parameters of one page, as reported by `EFacsimilePageStarted`.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxPage {
+    #[pyo3(get)]
+    number: u32,
+    #[pyo3(get)]
+    speed: Option<FaxSendSpeed>,
+    #[pyo3(get)]
+    paper_size: Option<DocumentPreparePaperSize>,
+    #[pyo3(get)]
+    resolution: Option<DocumentPrepareResolution>,
+    #[pyo3(get)]
+    ecm: Option<ECM>,
+}
+
+/// A fax received on an incoming call. `path` is set once the document has been saved;
+/// a fax that failed part-way is still saved if any page arrived.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedFax {
+    #[pyo3(get)]
+    call: ResourceId,
+    #[pyo3(get)]
+    ani: Option<String>,
+    #[pyo3(get)]
+    dnis: Option<String>,
+    #[pyo3(get)]
+    received_at: u64,
+    #[pyo3(get)]
+    path: Option<String>,
+    #[pyo3(get)]
+    pages: Vec<FaxPage>,
+    #[pyo3(get)]
+    failure: Option<String>,
+}
+
+impl ReceivedFax {
+    pub fn path(&self) -> Option<String> {
+        self.path.clone()
+    }
+}
+
+#[pymethods]
+impl ReceivedFax {
+    #[getter]
+    fn success(&self) -> bool {
+        self.path.is_some() && self.failure.is_none()
+    }
+}
+
+fn file_extension(document_type: &DocumentSaveType) -> &'static str {
+    match document_type.name {
+        "JPEG" => "jpg",
+        "PNG" => "png",
+        "BMP" => "bmp",
+        "GIF" => "gif",
+        _ => "tif",
+    }
+}
+
+/// Year, month, day, hour, minute and second in UTC for seconds since the Unix epoch.
+fn utc_fields(secs: u64) -> (i64, i64, i64, u64, u64, u64) {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+    // civil date from days since 1970-01-01, after H. Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC for seconds since the Unix epoch.
+fn utc_timestamp(secs: u64) -> String {
+    let (year, month, day, hour, minute, second) = utc_fields(secs);
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, hour, minute, second)
+}
+
+/// A caller or called number made safe for a file name.
+fn file_name_part(number: Option<&str>) -> String {
+    let part: String = number
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '+')
+        .collect();
+    if part.is_empty() {
+        "unknown".to_string()
+    } else {
+        part
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ReceiveState {
+    Answered,
+    Receiving,
+    Saving,
+    Done,
+}
+
+/// One incoming call on a receive line.
+#[derive(Clone, Debug)]
+struct FaxReceiveJob {
+    line: FaxLine,
+    state: ReceiveState,
+    fax: ReceivedFax,
+    page: Option<FaxPage>,
+    call_up: bool,
+}
+
+impl FaxReceiveJob {
+    fn path(&self, options: &FaxReceiveOptions) -> String {
+        let name = format!(
+            "{}_{}_{}.{}",
+            utc_timestamp(self.fax.received_at),
+            file_name_part(self.fax.ani.as_deref()),
+            file_name_part(self.fax.dnis.as_deref()),
+            file_extension(&options.document_type)
+        );
+        match options.directory.trim_end_matches('/') {
+            "" => name,
+            directory => format!("{}/{}", directory, name),
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event, options: &FaxReceiveOptions) -> Vec<Command> {
+        let FaxLine { call, fax, document } = self.line;
+        match event {
+            Event::FaxIncoming(e) if (e.resource_id == call || e.resource_id == fax) && self.state == ReceiveState::Answered => {
+                self.receive(options)
+            }
+            Event::ModeChangeT38(e) if (e.resource_id == call || e.resource_id == fax) && self.state == ReceiveState::Answered => {
+                self.receive(options)
+            }
+            Event::FacsimilePageStarted(e) if e.resource_id == fax => {
+                self.page = Some(FaxPage {
+                    number: self.fax.pages.len() as u32 + 1,
+                    speed: Some(e.speed),
+                    paper_size: Some(e.paper_size),
+                    resolution: Some(e.resolution),
+                    ecm: Some(e.ecm),
+                });
+                Vec::new()
+            }
+            Event::FacsimilePageReceived(e) if e.resource_id == fax => {
+                let number = self.fax.pages.len() as u32 + 1;
+                let page = self.page.take().unwrap_or(FaxPage {
+                    number,
+                    speed: None,
+                    paper_size: None,
+                    resolution: None,
+                    ecm: None,
+                });
+                self.fax.pages.push(page);
+                Vec::new()
+            }
+            Event::FaxOperationFinished(e) if e.resource_id == fax => self.save(options),
+            Event::FaxOperationFailed(e) if e.resource_id == fax => {
+                self.fax.failure = Some("fax operation failed".into());
+                self.save(options)
+            }
+            Event::FaxOperationAborted(e) if e.resource_id == fax => {
+                self.fax.failure = Some("fax operation aborted".into());
+                self.save(options)
+            }
+            Event::CallCleared(e) if e.resource_id == call => {
+                self.call_up = false;
+                if self.state == ReceiveState::Answered {
+                    self.fax.failure = Some(format!("call cleared: {}", e.reason));
+                    return self.finish();
+                }
+                Vec::new()
+            }
+            Event::DocumentSaved(e) if e.resource_id == document && self.state == ReceiveState::Saving => {
+                self.fax.path = Some(self.path(options));
+                self.finish()
+            }
+            Event::DocumentNotSaved(e) if e.resource_id == document && self.state == ReceiveState::Saving => {
+                self.fax.failure = Some(format!("document not saved: {}", e.reason));
+                self.finish()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn receive(&mut self, options: &FaxReceiveOptions) -> Vec<Command> {
+        self.state = ReceiveState::Receiving;
+        vec![Command::fax_receive(
+            self.line.fax,
+            self.line.call,
+            self.line.document,
+            options.fax_mode,
+            options.ecm,
+            options.csi.clone(),
+        )]
+    }
+
+    /// Save what was received; with no page there is nothing to save.
+    fn save(&mut self, options: &FaxReceiveOptions) -> Vec<Command> {
+        if self.fax.pages.is_empty() {
+            self.fax.failure.get_or_insert_with(|| "no page received".into());
+            return self.finish();
+        }
+        self.state = ReceiveState::Saving;
+        vec![Command::document_save(
+            self.line.document,
+            self.path(options),
+            Some(true),
+            Some(options.document_type),
+        )]
+    }
+
+    /// Hang up if needed and empty the document for the next call on the line.
+    fn finish(&mut self) -> Vec<Command> {
+        self.state = ReceiveState::Done;
+        let mut commands = Vec::new();
+        if std::mem::take(&mut self.call_up) {
+            commands.push(Command::call_clear(self.line.call, None));
+        }
+        commands.push(Command::document_clear(self.line.document));
+        commands
+    }
+}
+
+/// Resources answering fax calls: an accepting front-end with its own fax and document.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaxLine {
+    pub call: ResourceId,
+    pub fax: ResourceId,
+    pub document: ResourceId,
+}
+
+/// Answers incoming fax calls on a set of lines and saves the received documents.
+///
+/// For each `ECallIncoming` on an idle line the call is answered; `EFaxIncoming` or
+/// `EModeChangeT38` starts `FaxReceive`, pages are counted and, when the fax ends, the
+/// document is saved and cleared for the next call. Finished faxes are collected with
+/// `take_received`. As with `FaxSendJob`, the commands returned are for the caller to send.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct FaxReceiveHandler {
+    #[pyo3(get)]
+    options: FaxReceiveOptions,
+    lines: Vec<FaxLine>,
+    jobs: Vec<FaxReceiveJob>,
+    received: Vec<ReceivedFax>,
+}
+
+impl FaxReceiveHandler {
+    pub fn new(options: FaxReceiveOptions) -> Self {
+        FaxReceiveHandler {
+            options,
+            lines: Vec::new(),
+            jobs: Vec::new(),
+            received: Vec::new(),
+        }
+    }
+
+    pub fn add_line(&mut self, line: FaxLine) {
+        self.lines.push(line);
+    }
+
+    /// The resources of every line.
+    pub fn resources(&self) -> Vec<ResourceId> {
+        self.lines.iter().flat_map(|line| [line.call, line.fax, line.document]).collect()
+    }
+
+    /// Calls being handled.
+    pub fn active_calls(&self) -> Vec<ResourceId> {
+        self.jobs.iter().map(|job| job.line.call).collect()
+    }
+
+    /// Faxes finished since the previous call, oldest first.
+    pub fn take_received(&mut self) -> Vec<ReceivedFax> {
+        std::mem::take(&mut self.received)
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> Vec<Command> {
+        self.handle_event_at(event, unix_time())
+    }
+
+    /// As `handle_event`, with the time an incoming call is stamped with.
+    pub fn handle_event_at(&mut self, event: &Event, now: u64) -> Vec<Command> {
+        if let Event::CallIncoming(e) = event {
+            let idle = !self.jobs.iter().any(|job| job.line.call == e.resource_id);
+            if let Some(&line) = self.lines.iter().find(|line| line.call == e.resource_id).filter(|_| idle) {
+                self.jobs.push(FaxReceiveJob {
+                    line,
+                    state: ReceiveState::Answered,
+                    fax: ReceivedFax {
+                        call: line.call,
+                        ani: e.ani.clone(),
+                        dnis: e.dnis.clone(),
+                        received_at: now,
+                        path: None,
+                        pages: Vec::new(),
+                        failure: None,
+                    },
+                    page: None,
+                    call_up: true,
+                });
+                return vec![Command::call_answer(line.call)];
+            }
+            return Vec::new();
+        }
+
+        let mut commands = Vec::new();
+        for job in &mut self.jobs {
+            commands.extend(job.handle_event(event, &self.options));
+        }
+        let (done, active) = self
+            .jobs
+            .drain(..)
+            .partition(|job| job.state == ReceiveState::Done);
+        self.jobs = active;
+        self.received.extend(done.into_iter().map(|job: FaxReceiveJob| job.fax));
+        commands
+    }
+
+    /// Clear the calls still up, then delete the resources of every line.
+    pub fn shutdown(&mut self) -> Vec<Command> {
+        let mut commands: Vec<Command> = self
+            .jobs
+            .drain(..)
+            .filter(|job| job.call_up)
+            .map(|job| Command::call_clear(job.line.call, None))
+            .collect();
+        commands.extend(
+            self.lines
+                .drain(..)
+                .flat_map(|line| [line.document, line.fax, line.call])
+                .map(Command::resource_delete),
+        );
+        commands
+    }
+}
+
+#[pymethods]
+impl FaxReceiveHandler {
+    #[new]
+    #[pyo3(signature = (options=FaxReceiveOptions::default()))]
+    fn py_new(options: FaxReceiveOptions) -> Self {
+        FaxReceiveHandler::new(options)
+    }
+
+    #[pyo3(name = "add_line")]
+    fn py_add_line(&mut self, call: ResourceId, fax: ResourceId, document: ResourceId) {
+        self.add_line(FaxLine { call, fax, document })
+    }
+
+    #[pyo3(name = "active_calls")]
+    fn py_active_calls(&self) -> Vec<ResourceId> {
+        self.active_calls()
+    }
+
+    #[pyo3(name = "take_received")]
+    fn py_take_received(&mut self) -> Vec<ReceivedFax> {
+        self.take_received()
+    }
+
+    #[pyo3(name = "handle_event")]
+    fn py_handle_event(&mut self, event: Event) -> Vec<Command> {
+        self.handle_event(&event)
+    }
+
+    #[pyo3(name = "shutdown")]
+    fn py_shutdown(&mut self) -> Vec<Command> {
+        self.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::testing::lines;
+    use crate::constants::{DocumentPrepareResolution_High, FaxSendSpeed_V17At14400};
+    use crate::events::parse_event;
+
+    fn feed(job: &mut FaxSendJob, line: &str) -> Vec<String> {
+        lines(&job.handle_event(&parse_event(line).unwrap()))
+    }
+
+    fn job() -> FaxSendJob {
+        let options = FaxSendOptions {
+            ecm: Some(ECM::ECM256),
+            tsi: "5551234".parse().ok(),
+            ..Default::default()
+        };
+        FaxSendJob::new("5559876", vec!["a.tif".into(), "b.pdf".into()], options, 1, 2, 3)
+    }
+
+    #[test]
+    fn sends_two_pages_and_cleans_up() {
+        let mut job = job();
+        assert_eq!(
+            lines(&job.start()),
+            ["DocumentAddFile 3 a.tif", "DocumentAddFile 3 b.pdf", "DocumentPrepare 3"]
+        );
+        assert_eq!(feed(&mut job, "EDocumentPrepared 1 3"), ["CallMake 1 5559876 TimeOut=30000 Privacy=0 Screen=1"]);
+        assert_eq!(feed(&mut job, "ECallConnectionEstablished 1 1"), ["FaxSend 2 1 3 UseECM=256 TSI=5551234"]);
+        assert!(feed(&mut job, "EFaxOperationsStarted 1 2").is_empty());
+        for _ in 0..2 {
+            feed(&mut job, "EFacsimilePageStarted 1 2 V17At14400 A4 High 256");
+            feed(&mut job, "EFacsimilePageSent 1 2");
+        }
+        assert_eq!((job.progress().page, job.progress().pages_sent), (2, 2));
+        assert_eq!(
+            feed(&mut job, "EFaxOperationFinished 1 2"),
+            ["CallClear 1", "ResourceDelete 3", "ResourceDelete 2", "ResourceDelete 1"]
+        );
+
+        let report = job.report().unwrap();
+        assert!(report.success);
+        assert_eq!(report.pages_sent, 2);
+        assert_eq!(report.speed, Some(FaxSendSpeed_V17At14400));
+        assert_eq!(report.resolution, Some(DocumentPrepareResolution_High));
+        assert_eq!(report.ecm, Some(ECM::ECM256));
+
+        let states: Vec<_> = job.take_progress().iter().map(|p| p.state).collect();
+        assert_eq!(states[..4], ["preparing", "dialing", "connected", "sending"]);
+        assert_eq!(states.last(), Some(&"completed"));
+        assert!(job.take_progress().is_empty());
+    }
+
+    #[test]
+    fn failed_call_does_not_clear() {
+        let mut job = job();
+        job.start();
+        feed(&mut job, "EDocumentPrepared 1 3");
+        assert_eq!(
+            feed(&mut job, "ECallConnectionFailed 1 1 Busy"),
+            ["ResourceDelete 3", "ResourceDelete 2", "ResourceDelete 1"]
+        );
+        let report = job.report().unwrap();
+        assert!(!report.success);
+        assert_eq!(report.failure.as_deref(), Some("call failed: Busy"));
+        assert!(feed(&mut job, "ECallCleared 1 1 Normal").is_empty());
+    }
+
+    #[test]
+    fn abort_while_sending_waits_for_fax() {
+        let mut job = job();
+        job.start();
+        feed(&mut job, "EDocumentPrepared 1 3");
+        feed(&mut job, "ECallConnectionEstablished 1 1");
+        assert_eq!(lines(&job.abort("timed out")), ["FaxAbort 2"]);
+        assert!(job.report().is_none());
+        assert_eq!(feed(&mut job, "EFaxOperationAborted 1 2")[0], "CallClear 1");
+        assert_eq!(job.report().unwrap().failure.as_deref(), Some("timed out"));
+    }
+
+    #[test]
+    fn release_clears_a_dialled_call_and_deletes_resources() {
+        let mut preparing = job();
+        preparing.start();
+        assert_eq!(lines(&preparing.release("interrupted")), ["ResourceDelete 3", "ResourceDelete 2", "ResourceDelete 1"]);
+        assert_eq!(preparing.report().unwrap().failure.as_deref(), Some("interrupted"));
+        assert!(preparing.release("again").is_empty());
+
+        let mut dialing = job();
+        dialing.start();
+        feed(&mut dialing, "EDocumentPrepared 1 3");
+        assert_eq!(lines(&dialing.release("interrupted"))[0], "CallClear 1");
+    }
+
+    fn retrying_job(single_page_files: bool) -> FaxSendJob {
+        let mut job = job();
+        job.options.speed = Some(FaxSendSpeed_V17At14400);
+        job.speed = job.options.speed;
+        job.options.retry = Some(FaxRetryPolicy {
+            single_page_files,
+            ..Default::default()
+        });
+        job.start();
+        feed(&mut job, "EDocumentPrepared 1 3");
+        feed(&mut job, "ECallConnectionEstablished 1 1");
+        job
+    }
+
+    #[test]
+    fn retries_down_the_ladder_with_ecm_toggled() {
+        let mut job = retrying_job(false);
+        assert_eq!(feed(&mut job, "EFaxOperationFailed 1 2"), ["CallClear 1"]);
+        assert_eq!(job.state(), "retrying");
+        assert_eq!(feed(&mut job, "ECallCleared 1 1 EndedByLocalUser"), ["CallMake 1 5559876 TimeOut=30000 Privacy=0 Screen=1"]);
+        assert_eq!(feed(&mut job, "ECallConnectionEstablished 1 1"), ["FaxSend 2 1 3 Speed=V29At9600 UseECM=256 TSI=5551234"]);
+
+        feed(&mut job, "EFacsimilePageStarted 1 2 V29At9600 A4 High 256");
+        feed(&mut job, "EFacsimilePageSent 1 2");
+        feed(&mut job, "EFacsimilePageStarted 1 2 V29At9600 A4 High 256");
+        assert_eq!(feed(&mut job, "EFaxOperationFailed 1 2"), ["CallClear 1"]);
+        feed(&mut job, "ECallCleared 1 1 EndedByLocalUser");
+        assert_eq!(job.progress().pages_sent, 0, "the whole document is sent again");
+        assert_eq!(feed(&mut job, "ECallConnectionEstablished 1 1"), ["FaxSend 2 1 3 Speed=V27At4800 UseECM=256 TSI=5551234"]);
+
+        assert_eq!(feed(&mut job, "ECallCleared 1 1 EndedByRemoteUser"), ["ResourceDelete 3", "ResourceDelete 2", "ResourceDelete 1"]);
+        let report = job.report().unwrap();
+        assert_eq!((report.success, report.attempts), (false, 3));
+        let kinds: Vec<_> = report.failures.iter().map(|f| f.kind).collect();
+        assert_eq!(kinds, ["negotiation", "transmission", "hangup"]);
+
+        let transmission = &report.failures[1];
+        assert_eq!((transmission.page, transmission.pages_sent), (Some(2), 1));
+        assert_eq!(transmission.speed, Some(FaxSendSpeed_V29At9600));
+        assert_eq!(transmission.state, "sending");
+        assert_eq!(report.failures[0].page, None);
+        assert_eq!(report.failures[0].send_speed, Some(FaxSendSpeed_V17At14400));
+        assert_eq!(report.failure.as_deref(), Some("call cleared: EndedByRemoteUser"));
+    }
+
+    #[test]
+    fn retry_resumes_single_page_files_at_failed_page() {
+        let mut job = retrying_job(true);
+        job.options.retry.as_mut().unwrap().steps = vec![FaxRetryStep {
+            speed: None,
+            toggle_ecm: true,
+        }];
+        feed(&mut job, "EFacsimilePageStarted 1 2 V17At14400 A4 High 256");
+        feed(&mut job, "EFacsimilePageSent 1 2");
+        feed(&mut job, "EFacsimilePageStarted 1 2 V17At14400 A4 High 256");
+        feed(&mut job, "EFaxOperationFailed 1 2");
+        assert_eq!(
+            feed(&mut job, "ECallCleared 1 1 EndedByLocalUser"),
+            ["DocumentClear 3", "DocumentAddFile 3 b.pdf", "DocumentPrepare 3"]
+        );
+        feed(&mut job, "EDocumentPrepared 1 3");
+        assert_eq!(feed(&mut job, "ECallConnectionEstablished 1 1"), ["FaxSend 2 1 3 Speed=V17At14400 UseECM=0 TSI=5551234"]);
+        feed(&mut job, "EFacsimilePageStarted 1 2 V17At14400 A4 High 0");
+        assert_eq!(job.progress().page, 2);
+        feed(&mut job, "EFacsimilePageSent 1 2");
+        feed(&mut job, "EFaxOperationFinished 1 2");
+
+        let report = job.report().unwrap();
+        assert_eq!((report.success, report.attempts, report.pages_sent), (true, 2, 2));
+        assert_eq!(report.failures.len(), 1);
+    }
+
+    #[test]
+    fn busy_and_aborted_are_not_retried() {
+        let mut job = job();
+        job.options.retry = Some(FaxRetryPolicy::default());
+        job.start();
+        feed(&mut job, "EDocumentPrepared 1 3");
+        feed(&mut job, "ECallConnectionFailed 1 1 EndedByRemoteBusy");
+        assert_eq!(job.report().unwrap().failures[0].kind, "busy");
+
+        let mut job = retrying_job(false);
+        assert_eq!(lines(&job.time_out()), ["FaxAbort 2"]);
+        assert_eq!(feed(&mut job, "EFaxOperationAborted 1 2")[0], "CallClear 1");
+        let report = job.report().unwrap();
+        assert_eq!((report.attempts, report.failures[0].kind), (1, "timeout"));
+        assert_eq!(report.failure.as_deref(), Some("job timed out"));
+    }
+
+    fn receive_handler() -> FaxReceiveHandler {
+        let options = FaxReceiveOptions {
+            directory: "/var/fax/in/".into(),
+            ..Default::default()
+        };
+        let mut handler = FaxReceiveHandler::new(options);
+        handler.add_line(FaxLine { call: 5, fax: 6, document: 7 });
+        handler
+    }
+
+    fn receive(handler: &mut FaxReceiveHandler, line: &str) -> Vec<String> {
+        // 2026-10-18 21:35:01 UTC
+        lines(&handler.handle_event_at(&parse_event(line).unwrap(), 1_792_359_301))
+    }
+
+    #[test]
+    fn header_templates() {
+        let template = HeaderTemplate::parse("{date} {time} From {sender} to {number} {{p}} {page}/{pages}").unwrap();
+        let values = HeaderValues {
+            time: 1_792_359_301,
+            sender: Some("+1 555 0100".into()),
+            page: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(template.render(&values), "2026-10-18 21:35 From +1 555 0100 to ? {p} 2/?");
+        assert_eq!(HeaderTemplate::parse("no placeholders").unwrap().render(&values), "no placeholders");
+
+        assert_eq!(HeaderTemplate::parse("{fax}"), Err(HeaderError::UnknownPlaceholder("fax".into())));
+        assert_eq!(HeaderTemplate::parse("p {page"), Err(HeaderError::UnmatchedBrace(2)));
+        assert_eq!(HeaderTemplate::parse("p }"), Err(HeaderError::UnmatchedBrace(2)));
+        assert_eq!(HeaderTemplate::parse("a\nb"), Err(HeaderError::ControlCharacter));
+    }
+
+    #[test]
+    fn job_renders_header_for_attempt() {
+        let mut job = job();
+        job.options.header = HeaderTemplate::parse("{sender} > {number} p{page}/{pages}").ok();
+        job.options.retry = Some(FaxRetryPolicy {
+            single_page_files: true,
+            ..Default::default()
+        });
+        assert_eq!(job.header(0).as_deref(), Some("5551234 > 5559876 p1/2"));
+        job.prepared_from = 1;
+        job.options.tsi = None;
+        job.options.caller_number = Some("200".into());
+        assert_eq!(job.header(0).as_deref(), Some("200 > 5559876 p2/2"));
+    }
+
+    #[test]
+    fn utc_timestamps() {
+        assert_eq!(utc_timestamp(0), "19700101-000000");
+        assert_eq!(utc_timestamp(951_782_400), "20000229-000000");
+        assert_eq!(utc_timestamp(1_792_359_301), "20261018-213501");
+    }
+
+    #[test]
+    fn receives_and_saves_named_document() {
+        let mut handler = receive_handler();
+        assert!(receive(&mut handler, "ECallIncoming 1 9 c1 ANI=+1555").is_empty(), "not a fax line");
+        assert_eq!(receive(&mut handler, "ECallIncoming 1 5 c1 ANI=+1(555)0100 DNIS=200"), ["CallAnswer 5"]);
+        assert!(receive(&mut handler, "ECallIncoming 1 5 c2").is_empty(), "line busy");
+        assert_eq!(receive(&mut handler, "EModeChangeT38 1 6"), ["FaxReceive 6 5 7"]);
+        assert!(receive(&mut handler, "EFaxIncoming 1 6").is_empty());
+        receive(&mut handler, "EFacsimilePageStarted 1 6 V17At14400 A4 High 256");
+        receive(&mut handler, "EFacsimilePageReceived 1 6");
+        receive(&mut handler, "EFacsimilePageReceived 1 6");
+        assert_eq!(
+            receive(&mut handler, "EFaxOperationFinished 1 6"),
+            ["DocumentSave 7 /var/fax/in/20261018-213501_+15550100_200.tif Multipage=1 DocumentType=TIFF"]
+        );
+        assert!(handler.take_received().is_empty());
+        assert_eq!(receive(&mut handler, "EDocumentSaved 1 7"), ["CallClear 5", "DocumentClear 7"]);
+
+        let faxes = handler.take_received();
+        assert_eq!(faxes.len(), 1);
+        assert!(faxes[0].success());
+        assert_eq!(faxes[0].path.as_deref(), Some("/var/fax/in/20261018-213501_+15550100_200.tif"));
+        assert_eq!(faxes[0].pages.len(), 2);
+        assert_eq!(faxes[0].pages[0].resolution, Some(DocumentPrepareResolution_High));
+        assert_eq!(faxes[0].pages[1].speed, None);
+        assert!(handler.active_calls().is_empty());
+        assert_eq!(receive(&mut handler, "ECallIncoming 1 5 c3"), ["CallAnswer 5"]);
+    }
+
+    #[test]
+    fn failed_receive_keeps_partial_document() {
+        let mut handler = receive_handler();
+        receive(&mut handler, "ECallIncoming 1 5 c1");
+        receive(&mut handler, "EFaxIncoming 1 5");
+        receive(&mut handler, "EFacsimilePageReceived 1 6");
+        receive(&mut handler, "ECallCleared 1 5 Normal");
+        assert_eq!(
+            receive(&mut handler, "EFaxOperationFailed 1 6"),
+            ["DocumentSave 7 /var/fax/in/20261018-213501_unknown_unknown.tif Multipage=1 DocumentType=TIFF"]
+        );
+        assert_eq!(receive(&mut handler, "EDocumentSaved 1 7"), ["DocumentClear 7"]);
+        let fax = &handler.take_received()[0];
+        assert!(!fax.success());
+        assert!(fax.path.is_some());
+        assert_eq!(fax.failure.as_deref(), Some("fax operation failed"));
+
+        receive(&mut handler, "ECallIncoming 1 5 c2");
+        assert_eq!(receive(&mut handler, "ECallCleared 1 5 Normal"), ["DocumentClear 7"]);
+        assert_eq!(handler.take_received()[0].failure.as_deref(), Some("call cleared: Normal"));
+        assert_eq!(lines(&handler.shutdown()), ["ResourceDelete 7", "ResourceDelete 6", "ResourceDelete 5"]);
+    }
+
+    #[test]
+    fn shutdown_clears_active_calls_first() {
+        let mut handler = receive_handler();
+        handler.add_line(FaxLine { call: 8, fax: 9, document: 10 });
+        receive(&mut handler, "ECallIncoming 1 8 c1");
+        receive(&mut handler, "EFaxIncoming 1 9");
+        assert_eq!(
+            lines(&handler.shutdown()),
+            [
+                "CallClear 8",
+                "ResourceDelete 7",
+                "ResourceDelete 6",
+                "ResourceDelete 5",
+                "ResourceDelete 10",
+                "ResourceDelete 9",
+                "ResourceDelete 8"
+            ]
+        );
+    }
+}
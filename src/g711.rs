@@ -0,0 +1,102 @@
+//! ITU-T G.711 A-law and µ-law companding.
+
+const SEG_AEND: [i16; 8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+const SEG_UEND: [i16; 8] = [0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF, 0x1FFF];
+const ULAW_BIAS: i16 = 0x84;
+const ULAW_CLIP: i16 = 8159;
+
+fn segment(value: i16, table: &[i16; 8]) -> usize {
+    table.iter().position(|&end| value <= end).unwrap_or(8)
+}
+
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    let mut pcm = sample >> 3;
+    let mask = if pcm >= 0 {
+        0xD5
+    } else {
+        pcm = -pcm - 1;
+        0x55
+    };
+    let seg = segment(pcm, &SEG_AEND);
+    if seg >= 8 {
+        return 0x7F ^ mask;
+    }
+    let shift = if seg < 2 { 1 } else { seg };
+    let aval = ((seg as u8) << 4) | ((pcm >> shift) & 0x0F) as u8;
+    aval ^ mask
+}
+
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    let mut pcm = sample >> 2;
+    let mask = if pcm < 0 {
+        pcm = -pcm;
+        0x7F
+    } else {
+        0xFF
+    };
+    pcm = pcm.min(ULAW_CLIP) + (ULAW_BIAS >> 2);
+    let seg = segment(pcm, &SEG_UEND);
+    if seg >= 8 {
+        return 0x7F ^ mask;
+    }
+    let uval = ((seg as u8) << 4) | ((pcm >> (seg + 1)) & 0x0F) as u8;
+    uval ^ mask
+}
+
+pub fn alaw_to_linear(value: u8) -> i16 {
+    let a = value ^ 0x55;
+    let mut t = i16::from(a & 0x0F) << 4;
+    let seg = (a & 0x70) >> 4;
+    match seg {
+        0 => t += 8,
+        1 => t += 0x108,
+        _ => t = (t + 0x108) << (seg - 1),
+    }
+    if a & 0x80 != 0 {
+        t
+    } else {
+        -t
+    }
+}
+
+pub fn ulaw_to_linear(value: u8) -> i16 {
+    let u = !value;
+    let t = ((i16::from(u & 0x0F) << 3) + ULAW_BIAS) << ((u & 0x70) >> 4);
+    if u & 0x80 != 0 {
+        ULAW_BIAS - t
+    } else {
+        t - ULAW_BIAS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alaw_code_words() {
+        assert_eq!(linear_to_alaw(0), 0xD5);
+        assert_eq!(linear_to_alaw(-8), 0x55);
+        assert_eq!(linear_to_alaw(i16::MAX), 0xAA);
+        assert_eq!(linear_to_alaw(i16::MIN), 0x2A);
+    }
+
+    #[test]
+    fn ulaw_code_words() {
+        assert_eq!(linear_to_ulaw(0), 0xFF);
+        assert_eq!(linear_to_ulaw(-4), 0x7E);
+        assert_eq!(linear_to_ulaw(i16::MAX), 0x80);
+        assert_eq!(linear_to_ulaw(i16::MIN), 0x00);
+    }
+
+    #[test]
+    fn round_trip_within_quantisation_error() {
+        for sample in (-32768i32..32768).step_by(97).map(|s| s as i16) {
+            let a = alaw_to_linear(linear_to_alaw(sample));
+            let u = ulaw_to_linear(linear_to_ulaw(sample));
+            let tolerance = (i32::from(sample).abs() / 16).max(16);
+            assert!((i32::from(a) - i32::from(sample)).abs() <= tolerance, "A-law {sample} -> {a}");
+            assert!((i32::from(u) - i32::from(sample)).abs() <= tolerance, "µ-law {sample} -> {u}");
+        }
+    }
+}
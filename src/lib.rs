@@ -1,9 +1,27 @@
 mod client;
 mod commands;
+mod conference;
 mod primitives;
 mod constants;
 mod macros;
+mod detector;
 mod events;
+mod fax;
+mod g711;
+mod mock;
+mod preview;
+mod prompts;
+mod queue;
+mod recording;
+mod relay;
+mod routing;
+mod rtp;
+mod sdp;
+mod statistics;
+mod tones;
+mod trace;
+mod vad;
+mod wav;
 
 use pyo3::prelude::*;
 
@@ -17,5 +35,21 @@ pub fn gridborg_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(sum_as_string, m)?);
     client::init(m)?;
     commands::init(m)?;
+    prompts::init(m)?;
+    tones::init(m)?;
+    detector::init(m)?;
+    vad::init(m)?;
+    rtp::init(m)?;
+    sdp::init(m)?;
+    statistics::init(m)?;
+    routing::init(m)?;
+    conference::init(m)?;
+    recording::init(m)?;
+    fax::init(m)?;
+    preview::init(m)?;
+    relay::init(m)?;
+    queue::init(m)?;
+    mock::init(m)?;
+    trace::init(m)?;
     Ok(())
 }
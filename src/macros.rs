@@ -1,25 +1,67 @@
+/// Declare audio-format constants.
+///
+/// * `$codec` – codec suffix of the constant name.
+/// * `$wav`, `$raw`, `$vap` – `Y` if the codec is available in that container.
+/// * `$ch`    – maximum number of channels the codec can carry.
+/// * `$rates` – sample rates (Hz) the codec can be played or recorded at.
+///
+/// The macro turns `(G729, N, Y, N, Channels::from_u8(1), RATES_8K)` into
+/// `pub const RAW_G729: AudioFormatType = …`, and collects every constant
+/// into `ALL_AUDIO_FORMATS`.
 #[macro_export]
 macro_rules! audio_formats {
-    ( $( ($codec:ident , $wav:tt , $raw:tt , $vap:tt , $ch:expr) ),* $(,)? ) => {
+    ( $( ($codec:ident , $wav:tt , $raw:tt , $vap:tt , $ch:expr , $rates:expr) ),* $(,)? ) => {
         $(
-            audio_formats!(@maybe_const WAV   $wav  $codec $ch);
-            audio_formats!(@maybe_const RAW   $raw  $codec $ch);
-            audio_formats!(@maybe_const VAP   $vap  $codec $ch);
+            audio_formats!(@maybe_const WAV   $wav  $codec $ch, $rates);
+            audio_formats!(@maybe_const RAW   $raw  $codec $ch, $rates);
+            audio_formats!(@maybe_const VAP   $vap  $codec $ch, $rates);
         )*
+
+        audio_formats!(@collect [] $( ($codec $wav $raw $vap) )*);
+    };
+
+    // ── helper ── gather every emitted constant into `ALL_AUDIO_FORMATS`
+    (@collect [$($acc:tt)*]) => {
+        pub const ALL_AUDIO_FORMATS: &[$crate::constants::AudioFormatType] = &[$($acc)*];
+    };
+    (@collect [$($acc:tt)*] ($codec:ident Y Y Y) $($rest:tt)*) => {
+        paste::paste! { audio_formats!(@collect [$($acc)* [<WAV_ $codec>], [<RAW_ $codec>], [<VAP_ $codec>],] $($rest)*); }
+    };
+    (@collect [$($acc:tt)*] ($codec:ident Y Y N) $($rest:tt)*) => {
+        paste::paste! { audio_formats!(@collect [$($acc)* [<WAV_ $codec>], [<RAW_ $codec>],] $($rest)*); }
+    };
+    (@collect [$($acc:tt)*] ($codec:ident Y N Y) $($rest:tt)*) => {
+        paste::paste! { audio_formats!(@collect [$($acc)* [<WAV_ $codec>], [<VAP_ $codec>],] $($rest)*); }
+    };
+    (@collect [$($acc:tt)*] ($codec:ident Y N N) $($rest:tt)*) => {
+        paste::paste! { audio_formats!(@collect [$($acc)* [<WAV_ $codec>],] $($rest)*); }
+    };
+    (@collect [$($acc:tt)*] ($codec:ident N Y Y) $($rest:tt)*) => {
+        paste::paste! { audio_formats!(@collect [$($acc)* [<RAW_ $codec>], [<VAP_ $codec>],] $($rest)*); }
+    };
+    (@collect [$($acc:tt)*] ($codec:ident N Y N) $($rest:tt)*) => {
+        paste::paste! { audio_formats!(@collect [$($acc)* [<RAW_ $codec>],] $($rest)*); }
+    };
+    (@collect [$($acc:tt)*] ($codec:ident N N Y) $($rest:tt)*) => {
+        paste::paste! { audio_formats!(@collect [$($acc)* [<VAP_ $codec>],] $($rest)*); }
+    };
+    (@collect [$($acc:tt)*] ($codec:ident N N N) $($rest:tt)*) => {
+        audio_formats!(@collect [$($acc)*] $($rest)*);
     };
 
     // ── helper ── emit constant only if flag == Y
-    (@maybe_const $prefix:ident Y $codec:ident $ch:expr) => {
+    (@maybe_const $prefix:ident Y $codec:ident $ch:expr , $rates:expr) => {
         paste::paste! {
             #[allow(non_upper_case_globals)]
             pub const [<$prefix _ $codec>]: $crate::constants::AudioFormatType =
                 $crate::constants::AudioFormatType {
                     name: concat!(stringify!($prefix), "_", stringify!($codec)),
                     channels: $ch,
+                    sample_rates: $rates,
                 };
         }
     };
-    (@maybe_const $prefix:ident N $codec:ident $ch:expr) => {};   // nothing
+    (@maybe_const $prefix:ident N $codec:ident $ch:expr , $rates:expr) => {};   // nothing
 }
 
 /// Declare payload-type constants.
@@ -29,7 +71,7 @@ macro_rules! audio_formats {
 /// * `$code`  – RTP payload-type code, or `_` if none.
 /// * `$rate`  – sample-rate in Hertz.
 ///
-/// The macro turns `(G711_ALAW_64K, "G.711-ALaw-64k", 0, 8000)` into
+/// The macro turns `(G711_ALAW_64K, "G.711-ALaw-64k", 8, 8000)` into
 /// `pub const PT_G711_ALAW_64K: PayloadType = …`, and collects every
 /// constant into `ALL_PAYLOAD_TYPES`.
 #[macro_export]
@@ -0,0 +1,723 @@
+use crate::commands::{parse_command, Command, Quoted};
+use crate::primitives::{ResourceId, SessionId};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "mock")?;
+
+    child_module.add_class::<MockServer>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    FrontEnd,
+    Player,
+    Recorder,
+    TransportChannel,
+    RtpChannel,
+    SoundDevice,
+    Fax,
+    Document,
+}
+
+impl Kind {
+    fn created_by(command: &Command) -> Option<Kind> {
+        match command {
+            Command::ResourceCreateFrontEnd(_) => Some(Kind::FrontEnd),
+            Command::ResourceCreatePlayer(_) => Some(Kind::Player),
+            Command::ResourceCreateRecorder(_) => Some(Kind::Recorder),
+            Command::ResourceCreateTransportChannel(_) => Some(Kind::TransportChannel),
+            Command::ResourceCreateRtpChannel(_) => Some(Kind::RtpChannel),
+            Command::ResourceCreateSoundDevice(_) => Some(Kind::SoundDevice),
+            Command::ResourceCreateFax(_) => Some(Kind::Fax),
+            Command::ResourceCreateDocument(_) => Some(Kind::Document),
+            _ => None,
+        }
+    }
+
+    /// The kind of resource the first argument of a command must be, from the command name.
+    fn targeted_by(name: &str) -> Option<Kind> {
+        [
+            ("Call", Kind::FrontEnd),
+            ("Play", Kind::Player),
+            ("Recorder", Kind::Recorder),
+            ("RtpChannel", Kind::RtpChannel),
+            ("SoundDevice", Kind::SoundDevice),
+            ("Fax", Kind::Fax),
+            ("Document", Kind::Document),
+        ]
+        .into_iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, kind)| kind)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Resource {
+    kind: Kind,
+    session: SessionId,
+    /// Files added to a document since it was last cleared.
+    pages: usize,
+    paper_size: &'static str,
+    resolution: &'static str,
+}
+
+enum Response {
+    Lines(Vec<String>),
+    Fail(String),
+}
+
+struct Session {
+    id: SessionId,
+    logged_in: bool,
+}
+
+#[derive(Default)]
+struct MockState {
+    credentials: Option<(String, String)>,
+    next_session: SessionId,
+    next_resource: ResourceId,
+    next_call: u32,
+    resources: HashMap<ResourceId, Resource>,
+    scripts: HashMap<String, VecDeque<Response>>,
+    received: Vec<String>,
+    clients: HashMap<SessionId, TcpStream>,
+}
+
+/// Split the `COMMANDTAG=<tag>` the client appends to every command off the line.
+pub(crate) fn split_tag(line: &str) -> (&str, Option<u64>) {
+    if let Some((command, last)) = line.rsplit_once(' ') {
+        if let Some((key, tag)) = last.split_once('=') {
+            if key.eq_ignore_ascii_case("commandtag") {
+                if let Ok(tag) = tag.parse() {
+                    return (command, Some(tag));
+                }
+            }
+        }
+    }
+    (line, None)
+}
+
+pub(crate) fn tagged(line: String, tag: Option<u64>) -> String {
+    match tag {
+        Some(tag) => format!("{} COMMANDTAG={}", line, tag),
+        None => line,
+    }
+}
+
+fn error_reply(text: &str, tag: Option<u64>) -> String {
+    tagged(format!("Error {}", Quoted(text)), tag)
+}
+
+impl MockState {
+    fn add_resource(&mut self, kind: Kind, session: SessionId) -> ResourceId {
+        self.next_resource += 1;
+        self.resources.insert(
+            self.next_resource,
+            Resource {
+                kind,
+                session,
+                pages: 0,
+                paper_size: "A4",
+                resolution: "High",
+            },
+        );
+        self.next_resource
+    }
+
+    fn call_identifier(&mut self) -> String {
+        self.next_call += 1;
+        format!("call-{}", self.next_call)
+    }
+
+    /// Answer one command line from `session`.
+    fn respond(&mut self, session: &mut Session, line: &str, tag: Option<u64>) -> Vec<String> {
+        let command = match parse_command(line) {
+            Ok(command) => command,
+            Err(e) => return vec![error_reply(&e.to_string(), tag)],
+        };
+        let name = line.split_whitespace().next().unwrap_or_default();
+        let sid = session.id;
+
+        let open = matches!(
+            command,
+            Command::Login(_) | Command::ProtocolVersion(_) | Command::GetVersion(_) | Command::Quit(_)
+        );
+        if self.credentials.is_some() && !session.logged_in && !open {
+            return vec![error_reply("not logged in", tag)];
+        }
+        let ids = command.resource_ids();
+        for id in &ids {
+            if !self.resources.contains_key(id) {
+                return vec![error_reply(&format!("unknown resource {}", id), tag)];
+            }
+        }
+        if let (Some(kind), Some(id)) = (Kind::targeted_by(name), ids.first()) {
+            if self.resources[id].kind != kind {
+                return vec![error_reply(&format!("resource {} is not a {:?}", id, kind), tag)];
+            }
+        }
+
+        match self.scripts.get_mut(name).and_then(VecDeque::pop_front) {
+            Some(Response::Lines(lines)) => {
+                let resource = match Kind::created_by(&command) {
+                    Some(kind) if lines.iter().any(|l| l.contains("{resource}")) => self.add_resource(kind, sid),
+                    _ => ids.first().copied().unwrap_or_default(),
+                };
+                lines
+                    .iter()
+                    .map(|l| {
+                        l.replace("{session}", &sid.to_string())
+                            .replace("{resource}", &resource.to_string())
+                            .replace("{tag}", &tag.map_or(String::new(), |t| t.to_string()))
+                    })
+                    .collect()
+            }
+            Some(Response::Fail(reason)) => self.failure(&command, sid, &ids, &reason, tag),
+            None => self.default_response(&command, session, &ids, tag),
+        }
+    }
+
+    /// The way the server reports `command` failing for `reason`.
+    fn failure(&mut self, command: &Command, sid: SessionId, ids: &[ResourceId], reason: &str, tag: Option<u64>) -> Vec<String> {
+        let id = ids.first().copied().unwrap_or_default();
+        let reason = Quoted(reason);
+        match command {
+            Command::CallMake(cmd) => vec![
+                format!("ECallOutgoing {} {} {} {}", sid, id, Quoted(&cmd.address), self.call_identifier()),
+                format!("ECallConnectionFailed {} {} {}", sid, id, reason),
+            ],
+            Command::PlayFile(_) => vec![format!("EPlayerError {} {} {}", sid, id, reason)],
+            Command::RecorderStartToFile(_) | Command::RecorderStartToStream(_) => {
+                vec![format!("ERecorderError {} {} {}", sid, id, reason)]
+            }
+            Command::SoundDeviceStart(_) => vec![format!("ESoundDeviceError {} {}", sid, id)],
+            Command::DocumentPrepare(_) => vec![format!("EDocumentNotPrepared {} {} {}", sid, id, reason)],
+            Command::DocumentSave(_) => vec![format!("EDocumentNotSaved {} {} {}", sid, id, reason)],
+            Command::FaxSend(_) | Command::FaxReceive(_) => vec![
+                format!("EFaxOperationsStarted {} {}", sid, id),
+                format!("EFaxOperationFailed {} {}", sid, id),
+            ],
+            _ => vec![error_reply(reason.0, tag)],
+        }
+    }
+
+    fn default_response(&mut self, command: &Command, session: &mut Session, ids: &[ResourceId], tag: Option<u64>) -> Vec<String> {
+        let sid = session.id;
+        let id = ids.first().copied().unwrap_or_default();
+        let event = |name: &str, id: ResourceId| format!("{} {} {}", name, sid, id);
+
+        if let Some(kind) = Kind::created_by(command) {
+            let id = self.add_resource(kind, sid);
+            return vec![tagged(format!("ResourceCreated {}", id), tag), event("EResourceCreated", id)];
+        }
+        match command {
+            // Product Information and Session Commands
+            Command::ProtocolVersion(_) => vec![tagged("ProtocolVersion 2 3".to_string(), tag)],
+            Command::GetVersion(_) => vec![tagged("Version \"Gridborg mock server\"".to_string(), tag)],
+            Command::Login(login) => {
+                if let Some((username, password)) = &self.credentials {
+                    if (&login.username, &login.password) != (username, password) {
+                        return vec![error_reply("invalid username or password", tag)];
+                    }
+                }
+                session.logged_in = true;
+                vec![format!("ESessionCreated {}", sid)]
+            }
+            Command::Logout(_) => {
+                session.logged_in = false;
+                vec![format!("ESessionDeleted {}", sid)]
+            }
+            Command::ResourceDelete(_) => {
+                self.resources.remove(&id);
+                vec![event("EResourceDeleted", id)]
+            }
+            Command::ResourceGetStatus(_) => {
+                vec![tagged(format!("ResourceStatus {} Type={:?}", id, self.resources[&id].kind), tag)]
+            }
+
+            // Front-end Resource Commands
+            Command::CallMake(cmd) => vec![
+                format!("ECallOutgoing {} {} {} {}", sid, id, Quoted(&cmd.address), self.call_identifier()),
+                event("ECallRemoteAlerting", id),
+                event("ECallConnectionEstablished", id),
+            ],
+            Command::CallAnswer(_) => vec![event("ECallConnectionEstablished", id)],
+            Command::CallClear(_) => vec![format!("ECallCleared {} {} EndedByLocalUser", sid, id)],
+            Command::CallTransferBlind(_) | Command::CallTransferConsultation(_) => {
+                vec![format!("ECallCleared {} {} EndedByCallForwarded", sid, id)]
+            }
+            Command::CallSendDTMF(_) => vec![event("ECallSendDTMFFinished", id)],
+            Command::CallT38Relay(_) => ids.iter().map(|&id| event("EModeChangeT38", id)).collect(),
+
+            // Player and Recorder Resource Commands
+            Command::PlayFile(_) => vec![event("EPlayerStarted", id), event("EPlayerStopped", id)],
+            Command::PlayStream(_) | Command::PlayTone(_) => vec![event("EPlayerStarted", id)],
+            Command::PlayStop(_) => vec![event("EPlayerStopped", id)],
+            Command::RecorderStartToFile(_) | Command::RecorderStartToStream(_) => {
+                vec![event("ERecorderStarted", id)]
+            }
+            Command::RecorderStop(_) => vec![format!("ERecorderStopped {} {} ExplicitRequest", sid, id)],
+
+            // RTP Channel and Sound Device Resource Commands
+            Command::RtpChannelStartReceiving(_) => vec![format!(
+                "ERtpChannelStartedReceiving {} {} 127.0.0.1:{}",
+                sid,
+                id,
+                40000 + (id % 10000) * 2
+            )],
+            Command::RtpChannelStartSending(_) => vec![event("ERtpChannelStartedSending", id)],
+            Command::RtpChannelStop(_) => vec![event("ERtpChannelStopped", id)],
+            Command::RtpChannelSendDTMF(_) => vec![event("ERtpChannelSendDTMFFinished", id)],
+            Command::SoundDeviceStart(_) => vec![event("ESoundDeviceStarted", id)],
+            Command::SoundDeviceStop(_) => vec![event("ESoundDeviceStopped", id)],
+
+            // Fax Resource Commands
+            Command::FaxSend(cmd) => {
+                let document = &self.resources[&ids[2]];
+                let page = format!(
+                    "EFacsimilePageStarted {} {} {} {} {} {}",
+                    sid,
+                    id,
+                    cmd.speed.map_or("V29At9600", |s| s.name),
+                    document.paper_size,
+                    document.resolution,
+                    cmd.use_ecm.map_or(0, |e| e as u16)
+                );
+                let mut lines = vec![event("EFaxOperationsStarted", id)];
+                for _ in 0..document.pages {
+                    lines.push(page.clone());
+                    lines.push(event("EFacsimilePageSent", id));
+                }
+                lines.push(event("EFaxOperationFinished", id));
+                lines
+            }
+            Command::FaxReceive(cmd) => vec![
+                event("EFaxOperationsStarted", id),
+                format!(
+                    "EFacsimilePageStarted {} {} V29At9600 A4 High {}",
+                    sid,
+                    id,
+                    cmd.use_ecm.map_or(0, |e| e as u16)
+                ),
+                event("EFacsimilePageReceived", id),
+                event("EFaxOperationFinished", id),
+            ],
+            Command::FaxAbort(_) => vec![event("EFaxOperationAborted", id)],
+
+            // Document Resource Commands
+            Command::DocumentAddFile(_) => {
+                self.resources.get_mut(&id).unwrap().pages += 1;
+                Vec::new()
+            }
+            Command::DocumentPrepare(cmd) => {
+                let document = self.resources.get_mut(&id).unwrap();
+                if document.pages == 0 {
+                    return vec![format!("EDocumentNotPrepared {} {} NoFiles", sid, id)];
+                }
+                document.paper_size = cmd.paper_size.map_or("A4", |p| p.name);
+                document.resolution = cmd.resolution.map_or("High", |r| r.name);
+                vec![event("EDocumentPrepared", id)]
+            }
+            Command::DocumentSave(_) => vec![event("EDocumentSaved", id)],
+            Command::DocumentClear(_) => {
+                self.resources.get_mut(&id).unwrap().pages = 0;
+                vec![event("EDocumentCleared", id)]
+            }
+
+            // Miscellaneous Commands
+            Command::GetRtpStatistics(_) => vec![tagged(
+                format!("RtpStatistics {} PacketsSent=0 PacketsReceived=0 PacketsLost=0 Jitter=0", id),
+                tag,
+            )],
+
+            // audio routing and the remaining call commands change nothing the mock reports
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Read commands from one client until it quits or disconnects.
+fn serve(state: Arc<Mutex<MockState>>, stream: TcpStream) -> io::Result<()> {
+    let mut session = {
+        let mut state = lock(&state);
+        state.next_session += 1;
+        let id = state.next_session;
+        state.clients.insert(id, stream.try_clone()?);
+        Session { id, logged_in: false }
+    };
+    let served = serve_session(&state, &mut session, stream);
+    // the session ends with its connection, whether or not the client quit
+    lock(&state).clients.remove(&session.id);
+    served
+}
+
+fn serve_session(state: &Mutex<MockState>, session: &mut Session, stream: TcpStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let (command, tag) = split_tag(line.trim());
+        if command.is_empty() {
+            continue;
+        }
+        let reply: String = {
+            let mut state = lock(state);
+            state.received.push(command.to_string());
+            state.respond(session, command, tag)
+        }
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect();
+        writer.write_all(reply.as_bytes())?;
+        if command == "Quit" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn lock(state: &Mutex<MockState>) -> MutexGuard<'_, MockState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A stand-in for the Gridborg server that tests can run a client against.
+///
+/// The server listens on `127.0.0.1`, serves each connection in its own session and answers
+/// commands with the replies and events a server would send: `ResourceCreate*` commands get
+/// `ResourceCreated <id>` and an `EResourceCreated` event, calls connect, players play,
+/// documents prepare and faxes send one page per file added. Commands that cannot be
+/// parsed, refer to unknown resources, or come before `Login` when credentials are set are
+/// answered with `Error "<text>"`. Tagged replies carry the `COMMANDTAG` of the command.
+///
+/// `script` and `fail` change the answer to the next command of a given name, and `emit`
+/// sends events that no command asked for, such as incoming calls.
+#[pyclass]
+pub struct MockServer {
+    state: Arc<Mutex<MockState>>,
+    stopped: Arc<AtomicBool>,
+    #[pyo3(get)]
+    port: u16,
+}
+
+impl MockServer {
+    /// Listen on `port`, or a free port for 0. With `credentials`, clients must log in
+    /// with them first.
+    pub fn start(port: u16, credentials: Option<(String, String)>) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let port = listener.local_addr()?.port();
+        let state = Arc::new(Mutex::new(MockState {
+            credentials,
+            ..Default::default()
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (accept_state, accept_stopped) = (state.clone(), stopped.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accept_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let state = accept_state.clone();
+                    thread::spawn(move || serve(state, stream));
+                }
+            }
+        });
+        Ok(MockServer { state, stopped, port })
+    }
+
+    /// Answer the next `command` (a command name such as `CallMake`) with `lines` instead.
+    ///
+    /// `{session}`, `{resource}` and `{tag}` in the lines are replaced by the session id,
+    /// the first resource of the command and its command tag. A `ResourceCreate*` command
+    /// creates its resource only when the lines use `{resource}`.
+    pub fn script(&self, command: &str, lines: Vec<String>) {
+        lock(&self.state)
+            .scripts
+            .entry(command.to_string())
+            .or_default()
+            .push_back(Response::Lines(lines));
+    }
+
+    /// Make the next `command` fail for `reason`, reported the way the server reports that
+    /// command failing: `ECallConnectionFailed` for `CallMake`, `EFaxOperationFailed` for
+    /// faxes, `EDocumentNotPrepared` for `DocumentPrepare` and so on, otherwise an `Error`.
+    pub fn fail(&self, command: &str, reason: &str) {
+        lock(&self.state)
+            .scripts
+            .entry(command.to_string())
+            .or_default()
+            .push_back(Response::Fail(reason.to_string()));
+    }
+
+    /// Send `line` to every connected client and return how many it reached; clients
+    /// that cannot be written to are skipped.
+    pub fn emit(&self, line: &str) -> usize {
+        let clients: Vec<TcpStream> = lock(&self.state)
+            .clients
+            .values()
+            .filter_map(|client| client.try_clone().ok())
+            .collect();
+        let line = format!("{}\n", line);
+        clients
+            .into_iter()
+            .filter(|mut client| client.write_all(line.as_bytes()).is_ok())
+            .count()
+    }
+
+    /// Announce an incoming call on front-end `resource` to the client that created it.
+    pub fn incoming_call(&self, resource: ResourceId, ani: &str, dnis: &str) -> io::Result<()> {
+        let (line, client) = {
+            let mut state = lock(&self.state);
+            let session = state
+                .resources
+                .get(&resource)
+                .filter(|r| r.kind == Kind::FrontEnd)
+                .map(|r| r.session)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no front-end {}", resource)))?;
+            let line = format!(
+                "ECallIncoming {} {} {} ANI={} DNIS={}\n",
+                session,
+                resource,
+                state.call_identifier(),
+                Quoted(ani),
+                Quoted(dnis)
+            );
+            (line, state.clients.get(&session).map(TcpStream::try_clone).transpose()?)
+        };
+        match client {
+            Some(mut client) => client.write_all(line.as_bytes()),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "client has disconnected")),
+        }
+    }
+
+    /// The commands received so far, without their command tags.
+    pub fn received(&self) -> Vec<String> {
+        lock(&self.state).received.clone()
+    }
+
+    /// Stop listening and disconnect every client.
+    pub fn stop(&self) {
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // wake the accept loop so that it sees the flag
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        for (_, client) in lock(&self.state).clients.drain() {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[pymethods]
+impl MockServer {
+    #[new]
+    #[pyo3(signature = (port=0, username=None, password=None))]
+    fn py_new(port: u16, username: Option<String>, password: Option<String>) -> PyResult<Self> {
+        let credentials = username.map(|username| (username, password.unwrap_or_default()));
+        Ok(MockServer::start(port, credentials)?)
+    }
+
+    #[pyo3(name = "script")]
+    fn py_script(&self, command: &str, lines: Vec<String>) -> PyResult<()> {
+        if command.contains(char::is_whitespace) {
+            return Err(PyValueError::new_err(format!("not a command name: '{}'", command)));
+        }
+        self.script(command, lines);
+        Ok(())
+    }
+
+    #[pyo3(name = "fail")]
+    fn py_fail(&self, command: &str, reason: &str) {
+        self.fail(command, reason)
+    }
+
+    #[pyo3(name = "emit")]
+    fn py_emit(&self, line: &str) -> usize {
+        self.emit(line)
+    }
+
+    #[pyo3(name = "incoming_call")]
+    fn py_incoming_call(&self, resource: ResourceId, ani: &str, dnis: &str) -> PyResult<()> {
+        Ok(self.incoming_call(resource, ani, dnis)?)
+    }
+
+    #[getter(received)]
+    fn py_received(&self) -> Vec<String> {
+        self.received()
+    }
+
+    #[pyo3(name = "stop")]
+    fn py_stop(&self) {
+        self.stop()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&self, _exc_type: PyObject, _exc_value: PyObject, _traceback: PyObject) {
+        self.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+        tag: u64,
+    }
+
+    impl Client {
+        fn connect(server: &MockServer) -> Self {
+            let stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+            Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+                tag: 0,
+            }
+        }
+
+        /// Send `command` and read the `replies` lines it is answered with.
+        fn send(&mut self, command: &str, replies: usize) -> Vec<String> {
+            self.writer
+                .write_all(format!("{} COMMANDTAG={}\n", command, self.tag).as_bytes())
+                .unwrap();
+            self.tag += 1;
+            self.read(replies)
+        }
+
+        fn read(&mut self, lines: usize) -> Vec<String> {
+            (0..lines)
+                .map(|_| {
+                    let mut line = String::new();
+                    self.reader.read_line(&mut line).unwrap();
+                    line.trim_end().to_string()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn logs_in_creates_resources_and_calls() {
+        let server = MockServer::start(0, Some(("user1".into(), "abc".into()))).unwrap();
+        let mut client = Client::connect(&server);
+
+        assert_eq!(client.send("ResourceCreatePlayer", 1), ["Error \"not logged in\" COMMANDTAG=0"]);
+        assert_eq!(client.send("Login user1 nope 2 3", 1), ["Error \"invalid username or password\" COMMANDTAG=1"]);
+        assert_eq!(client.send("Login user1 abc 2 3", 1), ["ESessionCreated 1"]);
+        assert_eq!(
+            client.send("ResourceCreateFrontEnd", 2),
+            ["ResourceCreated 1 COMMANDTAG=3", "EResourceCreated 1 1"]
+        );
+        assert_eq!(
+            client.send("CallMake 1 \"sip:bob@example.com\"", 3),
+            [
+                "ECallOutgoing 1 1 sip:bob@example.com call-1",
+                "ECallRemoteAlerting 1 1",
+                "ECallConnectionEstablished 1 1"
+            ]
+        );
+        assert_eq!(client.send("PlayFile 1 x.wav", 1), ["Error \"resource 1 is not a Player\" COMMANDTAG=5"]);
+        assert_eq!(client.send("CallClear 9", 1), ["Error \"unknown resource 9\" COMMANDTAG=6"]);
+        assert_eq!(client.send("CallClear 1", 1), ["ECallCleared 1 1 EndedByLocalUser"]);
+        assert_eq!(client.send("Dial 1", 1), ["Error \"unknown command 'Dial'\" COMMANDTAG=8"]);
+
+        server.incoming_call(1, "5550100", "42").unwrap();
+        assert_eq!(client.read(1), ["ECallIncoming 1 1 call-2 ANI=5550100 DNIS=42"]);
+        assert_eq!(server.received()[3], "ResourceCreateFrontEnd");
+        assert_eq!(server.received().len(), 9);
+    }
+
+    #[test]
+    fn sends_one_fax_page_per_file() {
+        let server = MockServer::start(0, None).unwrap();
+        let mut client = Client::connect(&server);
+        client.send("ResourceCreateFrontEnd", 2);
+        client.send("ResourceCreateFax", 2);
+        client.send("ResourceCreateDocument", 2);
+
+        assert_eq!(client.send("DocumentPrepare 3", 1), ["EDocumentNotPrepared 1 3 NoFiles"]);
+        client.writer.write_all(b"DocumentAddFile 3 a.tif\nDocumentAddFile 3 b.tif\n").unwrap();
+        assert_eq!(client.send("DocumentPrepare 3 Resolution=Low", 1), ["EDocumentPrepared 1 3"]);
+        assert_eq!(
+            client.send("FaxSend 2 1 3 Speed=V17At14400 UseECM=256", 6),
+            [
+                "EFaxOperationsStarted 1 2",
+                "EFacsimilePageStarted 1 2 V17At14400 A4 Low 256",
+                "EFacsimilePageSent 1 2",
+                "EFacsimilePageStarted 1 2 V17At14400 A4 Low 256",
+                "EFacsimilePageSent 1 2",
+                "EFaxOperationFinished 1 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn scripted_responses_and_failures() {
+        let server = MockServer::start(0, None).unwrap();
+        let mut client = Client::connect(&server);
+        client.send("ResourceCreateFrontEnd", 2);
+
+        server.fail("CallMake", "EndedByRemoteBusy");
+        server.fail("ResourceCreateFax", "no fax licence");
+        server.script("GetRtpStatistics", vec!["RtpStatistics {resource} PacketsLost=3 COMMANDTAG={tag}".into()]);
+        server.script("ResourceCreateRecorder", vec!["ResourceCreated {resource} COMMANDTAG={tag}".into()]);
+
+        assert_eq!(
+            client.send("CallMake 1 200", 2),
+            ["ECallOutgoing 1 1 200 call-1", "ECallConnectionFailed 1 1 EndedByRemoteBusy"]
+        );
+        assert_eq!(client.send("CallMake 1 200", 3)[2], "ECallConnectionEstablished 1 1");
+        assert_eq!(client.send("ResourceCreateFax", 1), ["Error \"no fax licence\" COMMANDTAG=3"]);
+        assert_eq!(client.send("ResourceCreateRecorder", 1), ["ResourceCreated 2 COMMANDTAG=4"]);
+        assert_eq!(client.send("GetRtpStatistics 2", 1), ["RtpStatistics 2 PacketsLost=3 COMMANDTAG=5"]);
+
+        assert_eq!(server.emit("ERecorderVoiceTrigger 1 2"), 1);
+        assert_eq!(client.read(1), ["ERecorderVoiceTrigger 1 2"]);
+        server.stop();
+        assert_eq!(client.read(1), [""]);
+    }
+
+    #[test]
+    fn forgets_clients_that_disconnect() {
+        let server = MockServer::start(0, None).unwrap();
+        let mut staying = Client::connect(&server);
+        let mut leaving = Client::connect(&server);
+        staying.send("ResourceCreateFrontEnd", 2);
+        leaving.send("ResourceCreateFrontEnd", 2);
+        drop(leaving);
+
+        // the session ends once the server sees the connection close
+        for _ in 0..100 {
+            if lock(&server.state).clients.len() == 1 {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(server.emit("EStreamBufferStateNotification 1 1 Optimum"), 1);
+        assert_eq!(staying.read(1), ["EStreamBufferStateNotification 1 1 Optimum"]);
+        assert_eq!(
+            server.incoming_call(2, "5550100", "42").unwrap_err().kind(),
+            io::ErrorKind::NotConnected
+        );
+    }
+}
@@ -0,0 +1,701 @@
+//! Local preview of fax documents: source images laid out on fax pages the way
+//! `DocumentAddFile` and `DocumentPrepare` ask the server to, down to 1-bit pixels.
+use crate::constants::{
+    DocumentAddFileTransformation, DocumentPreparePaperSize, DocumentPrepareResolution,
+    DocumentPreparePaperSize_A4, DocumentPrepareResolution_High,
+};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::borrow::Cow;
+use std::io;
+use std::path::Path;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "preview")?;
+
+    child_module.add_class::<SourceImage>()?;
+    child_module.add_class::<FaxPageImage>()?;
+    child_module.add_class::<DocumentPreview>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PreviewError {
+    #[error("cannot read image: {0}")]
+    Io(#[from] io::Error),
+    #[error("unsupported image format, expected PNM or uncompressed BMP")]
+    UnsupportedFormat,
+    #[error("invalid image: {0}")]
+    Invalid(&'static str),
+    #[error("{len} bytes do not make a {width}x{height} image with {channels} channels")]
+    PixelCount {
+        len: usize,
+        width: u32,
+        height: u32,
+        channels: u8,
+    },
+}
+
+impl From<PreviewError> for PyErr {
+    fn from(err: PreviewError) -> Self {
+        match err {
+            PreviewError::Io(e) => PyIOError::new_err(e.to_string()),
+            other => PyValueError::new_err(other.to_string()),
+        }
+    }
+}
+
+/// Dots per scan line and paper length in mm, after ITU-T T.4.
+const PAPER_SIZES: [(&str, u32, f64); 5] = [
+    ("A4", 1728, 297.0),
+    ("Letter", 1728, 279.4),
+    ("Legal", 1728, 355.6),
+    ("B4", 2048, 364.0),
+    ("A3", 2432, 420.0),
+];
+
+/// Horizontal resolution of a fax page in dpi; `Low` has half as many lines per inch.
+const DOTS_PER_INCH: f64 = 200.0;
+
+/// Width in dots and length in scan lines of a page.
+pub fn page_size(paper_size: &DocumentPreparePaperSize, resolution: &DocumentPrepareResolution) -> (u32, u32) {
+    let (_, width, length_mm) = PAPER_SIZES
+        .iter()
+        .find(|(name, _, _)| *name == paper_size.name)
+        .unwrap_or(&PAPER_SIZES[0]);
+    let lines_per_inch = DOTS_PER_INCH / line_height(resolution) as f64;
+    (*width, (length_mm / 25.4 * lines_per_inch).round() as u32)
+}
+
+/// Dots covered by one scan line, vertically.
+fn line_height(resolution: &DocumentPrepareResolution) -> u32 {
+    if resolution.name == "Low" {
+        2
+    } else {
+        1
+    }
+}
+
+/// An image to be faxed, reduced to 8-bit luminance.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceImage {
+    #[pyo3(get)]
+    width: u32,
+    #[pyo3(get)]
+    height: u32,
+    luma: Vec<u8>,
+}
+
+/// Product of image dimensions read from a file, which may not fit in memory.
+fn checked_size(factors: &[usize]) -> Result<usize, PreviewError> {
+    factors
+        .iter()
+        .try_fold(1usize, |size, &factor| size.checked_mul(factor))
+        .ok_or(PreviewError::Invalid("image dimensions too large"))
+}
+
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000) as u8
+}
+
+impl SourceImage {
+    /// Image from raw pixels, row by row from the top: 1 channel for grayscale, 3 for RGB,
+    /// 4 for RGBA (alpha is ignored).
+    pub fn from_pixels(width: u32, height: u32, channels: u8, data: &[u8]) -> Result<Self, PreviewError> {
+        let wrong_size = || PreviewError::PixelCount {
+            len: data.len(),
+            width,
+            height,
+            channels,
+        };
+        let expected = checked_size(&[width as usize, height as usize, channels as usize]).ok();
+        if !matches!(channels, 1 | 3 | 4) || expected != Some(data.len()) {
+            return Err(wrong_size());
+        }
+        let luma = match channels {
+            1 => data.to_vec(),
+            n => data
+                .chunks_exact(n as usize)
+                .map(|px| luminance(px[0], px[1], px[2]))
+                .collect(),
+        };
+        Ok(SourceImage { width, height, luma })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, PreviewError> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    /// Decode a PNM (PBM, PGM or PPM, plain or raw) or uncompressed BMP file.
+    pub fn decode(data: &[u8]) -> Result<Self, PreviewError> {
+        match data {
+            [b'P', b'1'..=b'6', ..] => decode_pnm(data),
+            [b'B', b'M', ..] => decode_bmp(data),
+            _ => Err(PreviewError::UnsupportedFormat),
+        }
+    }
+
+    fn luma(&self, x: u32, y: u32) -> u8 {
+        self.luma[y as usize * self.width as usize + x as usize]
+    }
+}
+
+/// Reads the whitespace-separated header fields of a PNM file, skipping comments.
+struct PnmReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PnmReader<'a> {
+    fn skip_space(&mut self) {
+        while let Some(&c) = self.data.get(self.pos) {
+            if c == b'#' {
+                while self.data.get(self.pos).is_some_and(|&c| c != b'\n') {
+                    self.pos += 1;
+                }
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, PreviewError> {
+        self.skip_space();
+        let start = self.pos;
+        while self.data.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or(PreviewError::Invalid("bad PNM number"))
+    }
+
+    /// The next plain PBM pixel, which need not be separated from the following one.
+    fn bit(&mut self) -> Result<u8, PreviewError> {
+        self.skip_space();
+        let bit = match self.data.get(self.pos) {
+            Some(b'0') => 0,
+            Some(b'1') => 1,
+            _ => return Err(PreviewError::Invalid("bad PBM pixel")),
+        };
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    /// Raster of a raw file, which starts after a single whitespace byte.
+    fn raster(&self, len: usize) -> Result<&'a [u8], PreviewError> {
+        let short = || PreviewError::Invalid("PNM raster too short");
+        let end = (self.pos + 1).checked_add(len).ok_or_else(short)?;
+        self.data.get(self.pos + 1..end).ok_or_else(short)
+    }
+}
+
+fn decode_pnm(data: &[u8]) -> Result<SourceImage, PreviewError> {
+    let kind = data[1];
+    let mut reader = PnmReader { data, pos: 2 };
+    let width = reader.number()?;
+    let height = reader.number()?;
+    let pixels = checked_size(&[width as usize, height as usize])?;
+    let maxval = if matches!(kind, b'1' | b'4') { 1 } else { reader.number()? };
+    if maxval == 0 || maxval > 65535 {
+        return Err(PreviewError::Invalid("bad PNM maximum value"));
+    }
+    let scale = |v: u32| (v.min(maxval) * 255 / maxval) as u8;
+
+    let luma = match kind {
+        // bitmaps use 1 for black
+        b'1' => (0..pixels)
+            .map(|_| reader.bit().map(|bit| if bit == 1 { 0 } else { 255 }))
+            .collect::<Result<_, _>>()?,
+        b'4' => {
+            let stride = (width as usize).div_ceil(8);
+            let raster = reader.raster(checked_size(&[stride, height as usize])?)?;
+            (0..pixels)
+                .map(|i| {
+                    let (y, x) = (i / width as usize, i % width as usize);
+                    if raster[y * stride + x / 8] & (0x80 >> (x % 8)) != 0 {
+                        0
+                    } else {
+                        255
+                    }
+                })
+                .collect()
+        }
+        b'2' => (0..pixels)
+            .map(|_| reader.number().map(scale))
+            .collect::<Result<_, _>>()?,
+        b'3' => (0..pixels)
+            .map(|_| Ok(luminance(scale(reader.number()?), scale(reader.number()?), scale(reader.number()?))))
+            .collect::<Result<_, PreviewError>>()?,
+        _ => {
+            let channels = if kind == b'5' { 1 } else { 3 };
+            let bytes = if maxval > 255 { 2 } else { 1 };
+            let raster = reader.raster(checked_size(&[pixels, channels, bytes])?)?;
+            let samples: Vec<u8> = raster
+                .chunks_exact(bytes)
+                .map(|s| scale(s.iter().fold(0, |v, &b| v << 8 | b as u32)))
+                .collect();
+            return SourceImage::from_pixels(width, height, channels as u8, &samples);
+        }
+    };
+    Ok(SourceImage { width, height, luma })
+}
+
+fn le_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn le_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// Decode an uncompressed BMP of 1, 4, 8, 24 or 32 bits per pixel.
+fn decode_bmp(data: &[u8]) -> Result<SourceImage, PreviewError> {
+    if data.len() < 54 {
+        return Err(PreviewError::Invalid("BMP header too short"));
+    }
+    let offset = le_u32(data, 10) as usize;
+    let header_size = le_u32(data, 14) as usize;
+    let width = le_u32(data, 18) as i32;
+    let height = le_u32(data, 22) as i32;
+    let bits = le_u16(data, 28) as u32;
+    if le_u32(data, 30) != 0 {
+        return Err(PreviewError::Invalid("compressed BMP"));
+    }
+    if width <= 0 || height == 0 || !matches!(bits, 1 | 4 | 8 | 24 | 32) {
+        return Err(PreviewError::Invalid("unsupported BMP layout"));
+    }
+    let (width, top_down) = (width as u32, height < 0);
+    let height = height.unsigned_abs();
+
+    let palette: Vec<u8> = if bits <= 8 {
+        let colors = match le_u32(data, 46) {
+            0 => 1 << bits,
+            n => n as usize,
+        };
+        let short = || PreviewError::Invalid("BMP palette too short");
+        let start = header_size.checked_add(14).ok_or_else(short)?;
+        let end = checked_size(&[colors, 4])?.checked_add(start).ok_or_else(short)?;
+        data.get(start..end)
+            .ok_or_else(short)?
+            .chunks_exact(4)
+            .map(|bgr| luminance(bgr[2], bgr[1], bgr[0]))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let stride = checked_size(&[bits as usize, width as usize])?.div_ceil(32) * 4;
+    let short = || PreviewError::Invalid("BMP raster too short");
+    let end = checked_size(&[stride, height as usize])?.checked_add(offset).ok_or_else(short)?;
+    let raster = data.get(offset..end).ok_or_else(short)?;
+    let mut luma = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        let row_index = if top_down { y } else { height - 1 - y };
+        let row = &raster[row_index as usize * stride..][..stride];
+        for x in 0..width as usize {
+            let value = match bits {
+                24 | 32 => {
+                    let px = &row[x * bits as usize / 8..];
+                    luminance(px[2], px[1], px[0])
+                }
+                _ => {
+                    let bit = x * bits as usize;
+                    let index = (row[bit / 8] >> (8 - bits as usize - bit % 8)) & ((1 << bits) - 1) as u8;
+                    *palette
+                        .get(index as usize)
+                        .ok_or(PreviewError::Invalid("BMP palette index out of range"))?
+                }
+            };
+            luma.push(value);
+        }
+    }
+    Ok(SourceImage { width, height, luma })
+}
+
+/// A page as the recipient gets it: 1-bit scan lines, `width` dots each.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxPageImage {
+    #[pyo3(get)]
+    width: u32,
+    #[pyo3(get)]
+    height: u32,
+    #[pyo3(get)]
+    paper_size: DocumentPreparePaperSize,
+    #[pyo3(get)]
+    resolution: DocumentPrepareResolution,
+    /// Rows of `width` bits rounded up to whole bytes, most significant bit first, 1 for black.
+    bits: Vec<u8>,
+}
+
+impl FaxPageImage {
+    fn stride(&self) -> usize {
+        (self.width as usize).div_ceil(8)
+    }
+
+    pub fn is_black(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.bits[y as usize * self.stride() + x as usize / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    pub fn black_dots(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// The page as a raw PBM (`P4`) file. Low resolution pages keep their lines as they are,
+    /// so they look squashed unless shown at twice the height.
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut pbm = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+        pbm.extend_from_slice(&self.bits);
+        pbm
+    }
+}
+
+/// Lay `image` out on a page and reduce it to 1 bit, dots darker than `threshold` being black.
+///
+/// The image is placed at the top left, one pixel per dot at the fax resolution: cut off at
+/// the page edges for `Crop` (and without a transformation), enlarged or reduced to fit the
+/// page keeping its aspect ratio for `Scale`, and stretched over the whole page for
+/// `ScaleToFit`. Each dot is the average of the image pixels it covers.
+pub fn render_page(
+    image: &SourceImage,
+    transformation: Option<&DocumentAddFileTransformation>,
+    paper_size: &DocumentPreparePaperSize,
+    resolution: &DocumentPrepareResolution,
+    threshold: u8,
+) -> FaxPageImage {
+    let (width, height) = page_size(paper_size, resolution);
+    let line_height = line_height(resolution) as f64;
+    let (image_width, image_height) = (image.width as f64, image.height as f64);
+    // size of the image on the page, in dots
+    let (placed_width, placed_height) = match transformation.map(|t| t.name) {
+        Some("Scale") => {
+            let factor = (width as f64 / image_width).min(height as f64 * line_height / image_height);
+            (image_width * factor, image_height * factor)
+        }
+        Some("ScaleToFit") => (width as f64, height as f64 * line_height),
+        _ => (image_width, image_height),
+    };
+
+    // image pixels under dots [start, end), if any
+    let span = |start: f64, end: f64, placed: f64, size: u32| {
+        let scale = size as f64 / placed;
+        let first = (start * scale).floor() as u32;
+        (start < placed && first < size)
+            .then(|| (first, ((end.min(placed) * scale).ceil() as u32).clamp(first + 1, size)))
+    };
+    let columns: Vec<_> = (0..width)
+        .map(|x| span(x as f64, x as f64 + 1.0, placed_width, image.width))
+        .collect();
+
+    let stride = (width as usize).div_ceil(8);
+    let mut bits = vec![0u8; stride * height as usize];
+    for y in 0..height {
+        let top = y as f64 * line_height;
+        let Some((y0, y1)) = span(top, top + line_height, placed_height, image.height) else {
+            break;
+        };
+        for (x, &(x0, x1)) in columns.iter().enumerate().filter_map(|(x, c)| c.as_ref().map(|c| (x, c))) {
+            let mut sum = 0u64;
+            for sy in y0..y1 {
+                for sx in x0..x1 {
+                    sum += image.luma(sx, sy) as u64;
+                }
+            }
+            if sum < threshold as u64 * ((y1 - y0) * (x1 - x0)) as u64 {
+                bits[y as usize * stride + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    FaxPageImage {
+        width,
+        height,
+        paper_size: *paper_size,
+        resolution: *resolution,
+        bits,
+    }
+}
+
+/// Pages of a fax document as `DocumentAddFile` and `DocumentPrepare` would make them, one
+/// page per image added.
+///
+/// `add_file` and `prepare` mirror the commands, so a preview can be built from the same
+/// arguments before sending. The server's defaults are not known here: `prepare` without a
+/// paper size or resolution previews A4 at `High`.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct DocumentPreview {
+    images: Vec<(SourceImage, Option<DocumentAddFileTransformation>)>,
+    #[pyo3(get, set)]
+    threshold: u8,
+}
+
+impl Default for DocumentPreview {
+    fn default() -> Self {
+        DocumentPreview {
+            images: Vec::new(),
+            threshold: 128,
+        }
+    }
+}
+
+impl DocumentPreview {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_image(&mut self, image: SourceImage, transformation: Option<DocumentAddFileTransformation>) {
+        self.images.push((image, transformation));
+    }
+
+    pub fn add_file(
+        &mut self,
+        path: &Path,
+        transformation: Option<DocumentAddFileTransformation>,
+    ) -> Result<(), PreviewError> {
+        self.add_image(SourceImage::open(path)?, transformation);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+
+    pub fn prepare(
+        &self,
+        paper_size: Option<DocumentPreparePaperSize>,
+        resolution: Option<DocumentPrepareResolution>,
+    ) -> Vec<FaxPageImage> {
+        let paper_size = paper_size.unwrap_or(DocumentPreparePaperSize_A4);
+        let resolution = resolution.unwrap_or(DocumentPrepareResolution_High);
+        self.images
+            .iter()
+            .map(|(image, transformation)| {
+                render_page(image, transformation.as_ref(), &paper_size, &resolution, self.threshold)
+            })
+            .collect()
+    }
+}
+
+#[pymethods]
+impl SourceImage {
+    #[staticmethod]
+    #[pyo3(name = "from_pixels")]
+    fn py_from_pixels(width: u32, height: u32, channels: u8, data: &[u8]) -> PyResult<Self> {
+        Ok(SourceImage::from_pixels(width, height, channels, data)?)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "open")]
+    fn py_open(path: &str) -> PyResult<Self> {
+        Ok(SourceImage::open(Path::new(path))?)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "decode")]
+    fn py_decode(data: &[u8]) -> PyResult<Self> {
+        Ok(SourceImage::decode(data)?)
+    }
+}
+
+#[pymethods]
+impl FaxPageImage {
+    #[pyo3(name = "is_black")]
+    fn py_is_black(&self, x: u32, y: u32) -> bool {
+        self.is_black(x, y)
+    }
+
+    #[pyo3(name = "black_dots")]
+    fn py_black_dots(&self) -> usize {
+        self.black_dots()
+    }
+
+    #[pyo3(name = "to_pbm")]
+    fn py_to_pbm(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.to_pbm())
+    }
+
+    fn save(&self, path: &str) -> PyResult<()> {
+        std::fs::write(path, self.to_pbm()).map_err(PreviewError::from)?;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl DocumentPreview {
+    #[new]
+    fn py_new() -> Self {
+        DocumentPreview::new()
+    }
+
+    #[pyo3(name = "add_image")]
+    #[pyo3(signature = (image, transformation=None))]
+    fn py_add_image(&mut self, image: SourceImage, transformation: Option<DocumentAddFileTransformation>) {
+        self.add_image(image, transformation)
+    }
+
+    #[pyo3(name = "add_file")]
+    #[pyo3(signature = (path, transformation=None))]
+    fn py_add_file(&mut self, path: &str, transformation: Option<DocumentAddFileTransformation>) -> PyResult<()> {
+        Ok(self.add_file(Path::new(path), transformation)?)
+    }
+
+    #[pyo3(name = "clear")]
+    fn py_clear(&mut self) {
+        self.clear()
+    }
+
+    #[pyo3(name = "prepare")]
+    #[pyo3(signature = (paper_size=None, resolution=None))]
+    fn py_prepare(
+        &self,
+        paper_size: Option<DocumentPreparePaperSize>,
+        resolution: Option<DocumentPrepareResolution>,
+    ) -> Vec<FaxPageImage> {
+        self.prepare(paper_size, resolution)
+    }
+
+    fn __len__(&self) -> usize {
+        self.images.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{
+        DocumentAddFileTransformation_Crop, DocumentAddFileTransformation_Scale,
+        DocumentAddFileTransformation_ScaleToFit, DocumentPreparePaperSize_A3, DocumentPreparePaperSize_Letter,
+        DocumentPrepareResolution_Low,
+    };
+
+    /// A 4x2 image, black on the left half and white on the right.
+    fn half_black() -> SourceImage {
+        SourceImage::from_pixels(4, 2, 1, &[0, 0, 255, 255, 0, 0, 255, 255]).unwrap()
+    }
+
+    #[test]
+    fn page_sizes() {
+        assert_eq!(page_size(&DocumentPreparePaperSize_A4, &DocumentPrepareResolution_High), (1728, 2339));
+        assert_eq!(page_size(&DocumentPreparePaperSize_A4, &DocumentPrepareResolution_Low), (1728, 1169));
+        assert_eq!(page_size(&DocumentPreparePaperSize_Letter, &DocumentPrepareResolution_High), (1728, 2200));
+        assert_eq!(page_size(&DocumentPreparePaperSize_A3, &DocumentPrepareResolution_Low), (2432, 1654));
+    }
+
+    #[test]
+    fn decodes_pnm_and_bmp() {
+        let pbm = SourceImage::decode(b"P1\n# comment\n3 1\n101").unwrap();
+        assert_eq!(pbm.luma, [0, 255, 0]);
+        let raw_pbm = SourceImage::decode(b"P4 9 1 \x80\x80").unwrap();
+        assert_eq!((raw_pbm.luma[0], raw_pbm.luma[1], raw_pbm.luma[8]), (0, 255, 0));
+        let pgm = SourceImage::decode(b"P2 2 1 15 0 15").unwrap();
+        assert_eq!(pgm.luma, [0, 255]);
+        let ppm = SourceImage::decode(b"P6 1 1 255 \xff\x00\x00").unwrap();
+        assert_eq!(ppm.luma, [76]);
+
+        // 2x2, 24 bits, bottom-up: white then black on the bottom row, black then white on top
+        let mut bmp = b"BM".to_vec();
+        bmp.extend_from_slice(&[0; 8]);
+        bmp.extend_from_slice(&54u32.to_le_bytes());
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&2u32.to_le_bytes());
+        bmp.extend_from_slice(&2u32.to_le_bytes());
+        bmp.extend_from_slice(&[1, 0, 24, 0]);
+        bmp.extend_from_slice(&[0; 24]);
+        bmp.extend_from_slice(&[255, 255, 255, 0, 0, 0, 0, 0]);
+        bmp.extend_from_slice(&[0, 0, 0, 255, 255, 255, 0, 0]);
+        assert_eq!(SourceImage::decode(&bmp).unwrap().luma, [0, 255, 255, 0]);
+
+        assert!(matches!(SourceImage::decode(b"GIF89a"), Err(PreviewError::UnsupportedFormat)));
+        assert!(matches!(
+            SourceImage::from_pixels(2, 2, 3, &[0; 6]),
+            Err(PreviewError::PixelCount { len: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_headers() {
+        for pnm in [&b"P5 4294967295 4294967295 255 \0"[..], b"P6 4294967295 2 65535 \0", b"P4 4294967295 4294967295 \0"] {
+            assert!(matches!(SourceImage::decode(pnm), Err(PreviewError::Invalid(_))), "{pnm:?}");
+        }
+
+        let bmp = |offset: u32, header_size: u32, width: i32, height: i32, bits: u16| {
+            let mut bmp = b"BM".to_vec();
+            bmp.extend_from_slice(&[0; 8]);
+            bmp.extend_from_slice(&offset.to_le_bytes());
+            bmp.extend_from_slice(&header_size.to_le_bytes());
+            bmp.extend_from_slice(&width.to_le_bytes());
+            bmp.extend_from_slice(&height.to_le_bytes());
+            bmp.extend_from_slice(&1u16.to_le_bytes());
+            bmp.extend_from_slice(&bits.to_le_bytes());
+            bmp.extend_from_slice(&[0; 24]);
+            bmp
+        };
+        for data in [
+            bmp(54, 40, i32::MAX, i32::MIN, 32),
+            bmp(u32::MAX, 40, 1, 1, 24),
+            bmp(54, u32::MAX, 1, 1, 8),
+        ] {
+            assert!(matches!(SourceImage::decode(&data), Err(PreviewError::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn crop_places_pixels_one_to_one() {
+        let page = render_page(
+            &half_black(),
+            Some(&DocumentAddFileTransformation_Crop),
+            &DocumentPreparePaperSize_A4,
+            &DocumentPrepareResolution_High,
+            128,
+        );
+        assert!(page.is_black(0, 0) && page.is_black(1, 1));
+        assert!(!page.is_black(2, 0) && !page.is_black(0, 2));
+        assert_eq!(page.black_dots(), 4);
+        assert!(page.to_pbm().starts_with(b"P4\n1728 2339\n"));
+        assert_eq!(page.to_pbm().len(), 13 + 216 * 2339);
+    }
+
+    #[test]
+    fn scale_keeps_aspect_and_scale_to_fit_fills_page() {
+        let image = half_black();
+        let scaled = render_page(
+            &image,
+            Some(&DocumentAddFileTransformation_Scale),
+            &DocumentPreparePaperSize_A4,
+            &DocumentPrepareResolution_Low,
+            128,
+        );
+        // 4x2 image scaled to 1728 dots wide is 864 dots high, 432 low resolution lines
+        assert!(scaled.is_black(863, 431) && !scaled.is_black(864, 0) && !scaled.is_black(0, 432));
+        assert_eq!(scaled.black_dots(), 864 * 432);
+
+        let stretched = render_page(
+            &image,
+            Some(&DocumentAddFileTransformation_ScaleToFit),
+            &DocumentPreparePaperSize_A4,
+            &DocumentPrepareResolution_Low,
+            128,
+        );
+        assert_eq!(stretched.black_dots(), 864 * 1169);
+    }
+
+    #[test]
+    fn document_preview_renders_a_page_per_image() {
+        let mut preview = DocumentPreview::new();
+        preview.add_image(half_black(), None);
+        preview.add_image(SourceImage::from_pixels(1, 1, 3, &[40, 40, 40]).unwrap(), None);
+        let pages = preview.prepare(Some(DocumentPreparePaperSize_Letter), None);
+        assert_eq!(pages.len(), 2);
+        assert_eq!((pages[0].width, pages[0].height), (1728, 2200));
+        assert_eq!(pages[1].black_dots(), 1);
+
+        preview.threshold = 30;
+        assert_eq!(preview.prepare(None, None)[1].black_dots(), 0);
+    }
+}
@@ -1,9 +1,23 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
 use pyo3::pyclass;
+use pyo3::types::{PyString, PyTuple};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type SessionId = u32;
 pub type ResourceId = u32;
 pub type SampleRate = u16;
+
+/// Seconds since the Unix epoch, as used for timestamps in reports.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
 #[repr(u8)]
 #[pyclass]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -22,6 +36,18 @@ impl Channels {
     }
 }
 
+impl TryFrom<u16> for Channels {
+    type Error = ();
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Channels::Mono),
+            2 => Ok(Channels::Stereo),
+            _ => Err(()),
+        }
+    }
+}
+
 #[repr(u16)]
 #[pyclass]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -48,7 +74,192 @@ impl FromStr for ECM {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parsed: u16 = s.parse().map_err(|_| ())?;
-        Ok(ECM::from_u16(parsed))
+        match s.parse::<u16>().map_err(|_| ())? {
+            0 => Ok(ECM::No),
+            64 => Ok(ECM::ECM64),
+            128 => Ok(ECM::ECM128),
+            256 => Ok(ECM::ECM256),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Transport address of an RTP or RTCP socket, written `ip:port` (`[ip]:port` for IPv6)
+/// in Gridborg commands and events.
+///
+/// From Python it is given as an `"ip:port"` string or an `(ip, port)` tuple and
+/// read back as an `(ip, port)` tuple.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct NetworkAddress(SocketAddr);
+
+impl NetworkAddress {
+    /// Port 0 cannot be used to send or receive media and is rejected.
+    pub fn new(ip: IpAddr, port: u16) -> Option<Self> {
+        (port != 0).then_some(NetworkAddress(SocketAddr::new(ip, port)))
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.0.ip()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.0.port()
+    }
+}
+
+impl From<NetworkAddress> for SocketAddr {
+    fn from(address: NetworkAddress) -> Self {
+        address.0
+    }
+}
+
+impl TryFrom<SocketAddr> for NetworkAddress {
+    type Error = ();
+
+    fn try_from(address: SocketAddr) -> Result<Self, Self::Error> {
+        NetworkAddress::new(address.ip(), address.port()).ok_or(())
+    }
+}
+
+impl FromStr for NetworkAddress {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<SocketAddr>().map_err(|_| ())?.try_into()
+    }
+}
+
+impl fmt::Display for NetworkAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<'py> FromPyObject<'py> for NetworkAddress {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(text) = ob.extract::<String>() {
+            return text
+                .parse()
+                .map_err(|_| PyValueError::new_err(format!("Invalid network address: {}", text)));
+        }
+        if let Ok((host, port)) = ob.extract::<(String, u16)>() {
+            let ip = host
+                .parse()
+                .map_err(|_| PyValueError::new_err(format!("Invalid IP address: {}", host)))?;
+            return NetworkAddress::new(ip, port)
+                .ok_or_else(|| PyValueError::new_err("Network address port must not be 0"));
+        }
+        Err(PyTypeError::new_err(
+            "network address must be an 'ip:port' string or an (ip, port) tuple",
+        ))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for NetworkAddress {
+    type Target = PyTuple;
+    type Output = Bound<'py, PyTuple>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        (self.ip().to_string(), self.port()).into_pyobject(py)
+    }
+}
+
+/// A fax terminal identification: the TSI of a sender or the CSI of a receiver.
+///
+/// T.30 allows at most 20 characters, each a digit, `+` or space, e.g. `"+1 555 0100"`.
+/// From Python it is given and read back as a string.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FaxIdentifier(String);
+
+impl FaxIdentifier {
+    pub const MAX_LEN: usize = 20;
+
+    pub fn new(value: &str) -> Option<Self> {
+        let valid = value.len() <= Self::MAX_LEN && value.chars().all(|c| c.is_ascii_digit() || c == '+' || c == ' ');
+        valid.then(|| FaxIdentifier(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for FaxIdentifier {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FaxIdentifier::new(s).ok_or(())
+    }
+}
+
+impl fmt::Display for FaxIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'py> FromPyObject<'py> for FaxIdentifier {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let text = ob.extract::<String>()?;
+        FaxIdentifier::new(&text).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "Invalid fax identifier '{}': at most {} digits, '+' and spaces",
+                text,
+                FaxIdentifier::MAX_LEN
+            ))
+        })
+    }
+}
+
+impl<'py> IntoPyObject<'py> for FaxIdentifier {
+    type Target = PyString;
+    type Output = Bound<'py, PyString>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        Ok(PyString::new(py, &self.0))
+    }
+}
+
+impl<'py> IntoPyObject<'py> for &FaxIdentifier {
+    type Target = PyString;
+    type Output = Bound<'py, PyString>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        Ok(PyString::new(py, &self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_address_text_format() {
+        let v4: NetworkAddress = "192.168.1.10:5004".parse().unwrap();
+        assert_eq!(v4.port(), 5004);
+        assert_eq!(v4.to_string(), "192.168.1.10:5004");
+
+        let v6: NetworkAddress = "[2001:db8::1]:5006".parse().unwrap();
+        assert_eq!(v6.ip(), "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(v6.to_string(), "[2001:db8::1]:5006");
+    }
+
+    #[test]
+    fn network_address_validation() {
+        for bad in ["", "192.168.1.10", "192.168.1.10:0", "host.example:5004", "2001:db8::1:5004", "1.2.3.4:70000"] {
+            assert_eq!(bad.parse::<NetworkAddress>(), Err(()), "{bad}");
+        }
+    }
+
+    #[test]
+    fn fax_identifier_validation() {
+        assert_eq!("+1 555 0100".parse::<FaxIdentifier>().unwrap().as_str(), "+1 555 0100");
+        assert!("12345678901234567890".parse::<FaxIdentifier>().is_ok());
+        for bad in ["123456789012345678901", "555-0100", "ACME", "1\n2"] {
+            assert_eq!(bad.parse::<FaxIdentifier>(), Err(()), "{bad}");
+        }
     }
 }
@@ -0,0 +1,284 @@
+use crate::commands::{Command, CommandError};
+use crate::constants::{
+    AudioFormatType, RAW_ALAW, RAW_G7231, RAW_G729, RAW_GSM610, RAW_MULAW, RAW_PCM16,
+    RAW_PCM8, WAV_ADPCM4, WAV_ALAW, WAV_GSM610, WAV_IMA_ADPCM, WAV_MSADPCM4, WAV_MS_GSM,
+    WAV_MULAW, WAV_PCM16, WAV_PCM8,
+};
+use crate::primitives::{Channels, ResourceId, SampleRate};
+use crate::wav::{self, WavHeader};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "prompts")?;
+
+    child_module.add_class::<PromptInfo>()?;
+    child_module.add_function(wrap_pyfunction!(py_inspect_prompt, &child_module)?)?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PromptError {
+    #[error("cannot read prompt: {0}")]
+    Io(#[from] io::Error),
+    #[error("unsupported WAV encoding (format tag {format_tag:#06x}, {bits_per_sample} bits per sample)")]
+    UnsupportedWavFormat { format_tag: u16, bits_per_sample: u16 },
+    #[error("unsupported channel count {0}")]
+    UnsupportedChannels(u16),
+    #[error("unsupported sample rate {0} Hz")]
+    UnsupportedSampleRate(u32),
+    #[error("cannot infer the audio type of '{0}', pass an audio_type hint")]
+    MissingAudioType(String),
+    #[error("{0} is not a {1} format")]
+    WrongContainer(&'static str, &'static str),
+    #[error(transparent)]
+    Invalid(#[from] CommandError),
+}
+
+impl From<PromptError> for PyErr {
+    fn from(err: PromptError) -> Self {
+        match err {
+            PromptError::Io(e) => PyIOError::new_err(e.to_string()),
+            other => PyValueError::new_err(other.to_string()),
+        }
+    }
+}
+
+/// Format parameters of a local prompt file, ready to be used with `PlayFile`.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PromptInfo {
+    #[pyo3(get)]
+    file_name: String,
+    #[pyo3(get)]
+    audio_type: AudioFormatType,
+    #[pyo3(get)]
+    sample_rate: SampleRate,
+    #[pyo3(get)]
+    channels: Channels,
+}
+
+#[pymethods]
+impl PromptInfo {
+    /// Build a `PlayFile` command for this prompt.
+    ///
+    /// `file_name` overrides the path sent to the server, for prompts that
+    /// live at a different location on the Gridborg host.
+    #[pyo3(signature = (resource_id, file_name=None, index=None, skip_bytes=None))]
+    pub fn play_file(
+        &self,
+        resource_id: ResourceId,
+        file_name: Option<String>,
+        index: Option<u32>,
+        skip_bytes: Option<i64>,
+    ) -> Command {
+        Command::play_file(
+            resource_id,
+            file_name.unwrap_or_else(|| self.file_name.clone()),
+            Some(self.audio_type),
+            Some(self.sample_rate),
+            Some(self.channels),
+            index,
+            skip_bytes,
+        )
+    }
+}
+
+/// Format parameters for prompts without a header; ignored for WAV files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PromptHints {
+    pub audio_type: Option<AudioFormatType>,
+    pub sample_rate: Option<SampleRate>,
+    pub channels: Option<Channels>,
+}
+
+fn wav_format(header: &WavHeader) -> Option<AudioFormatType> {
+    match (header.format_tag, header.bits_per_sample) {
+        (wav::WAVE_FORMAT_PCM, 16) => Some(WAV_PCM16),
+        (wav::WAVE_FORMAT_PCM, 8) => Some(WAV_PCM8),
+        (wav::WAVE_FORMAT_ALAW, _) => Some(WAV_ALAW),
+        (wav::WAVE_FORMAT_MULAW, _) => Some(WAV_MULAW),
+        (wav::WAVE_FORMAT_IMA_ADPCM, _) => Some(WAV_IMA_ADPCM),
+        (wav::WAVE_FORMAT_MS_ADPCM, _) => Some(WAV_MSADPCM4),
+        (wav::WAVE_FORMAT_DIALOGIC_OKI_ADPCM, _) => Some(WAV_ADPCM4),
+        // Microsoft frames two GSM 06.10 frames into a 65 byte block
+        (wav::WAVE_FORMAT_GSM610, _) if header.block_align == 65 => Some(WAV_MS_GSM),
+        (wav::WAVE_FORMAT_GSM610, _) => Some(WAV_GSM610),
+        _ => None,
+    }
+}
+
+/// Guess the codec of a headerless file from common extensions.
+fn raw_format_from_extension(extension: &str) -> Option<AudioFormatType> {
+    match extension {
+        "al" | "alaw" | "a8" => Some(RAW_ALAW),
+        "ul" | "ulaw" | "mulaw" | "u8" => Some(RAW_MULAW),
+        "sln" | "s16" => Some(RAW_PCM16),
+        "pcm8" => Some(RAW_PCM8),
+        "gsm" => Some(RAW_GSM610),
+        "g729" => Some(RAW_G729),
+        "g723" | "g7231" => Some(RAW_G7231),
+        _ => None,
+    }
+}
+
+/// Inspect a local WAV, VAP or RAW prompt and work out its `PlayFile` parameters.
+///
+/// WAV files are described by their header. VAP and RAW files carry no codec
+/// information we can read, so the audio type comes from `hints` (or, for RAW
+/// files, a well-known extension such as `.alaw` or `.g729`); a missing sample
+/// rate defaults to the lowest rate the codec supports and channels to mono.
+pub fn inspect_prompt(path: &Path, hints: &PromptHints) -> Result<PromptInfo, PromptError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    let file_name = path.to_string_lossy().into_owned();
+
+    let info = if extension == "wav" {
+        let header = wav::read_header(&mut BufReader::new(File::open(path)?))?;
+        let audio_type = wav_format(&header).ok_or(PromptError::UnsupportedWavFormat {
+            format_tag: header.format_tag,
+            bits_per_sample: header.bits_per_sample,
+        })?;
+        let channels = Channels::try_from(header.channels)
+            .map_err(|_| PromptError::UnsupportedChannels(header.channels))?;
+        let sample_rate = SampleRate::try_from(header.sample_rate)
+            .map_err(|_| PromptError::UnsupportedSampleRate(header.sample_rate))?;
+        PromptInfo {
+            file_name,
+            audio_type,
+            sample_rate,
+            channels,
+        }
+    } else {
+        // make sure the file exists and is readable, even though there is no header to parse
+        File::open(path)?;
+
+        let container = if extension == "vap" { "VAP" } else { "RAW" };
+        let audio_type = hints
+            .audio_type
+            .or_else(|| (container == "RAW").then(|| raw_format_from_extension(&extension)).flatten())
+            .ok_or_else(|| PromptError::MissingAudioType(file_name.clone()))?;
+        if audio_type.container() != container {
+            return Err(PromptError::WrongContainer(audio_type.name, container));
+        }
+        PromptInfo {
+            file_name,
+            audio_type,
+            sample_rate: hints.sample_rate.unwrap_or(audio_type.sample_rates[0]),
+            channels: hints.channels.unwrap_or(Channels::Mono),
+        }
+    };
+
+    info.play_file(0, None, None, None).validate()?;
+    Ok(info)
+}
+
+#[pyfunction]
+#[pyo3(name = "inspect_prompt", signature = (path, audio_type=None, sample_rate=None, channels=None))]
+fn py_inspect_prompt(
+    path: String,
+    audio_type: Option<AudioFormatType>,
+    sample_rate: Option<SampleRate>,
+    channels: Option<Channels>,
+) -> PyResult<PromptInfo> {
+    let hints = PromptHints {
+        audio_type,
+        sample_rate,
+        channels,
+    };
+    Ok(inspect_prompt(Path::new(&path), &hints)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{RAW_G726_32K, VAP_ALAW};
+    use std::io::Write;
+
+    fn write_temp(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("gridborg-prompts-{}-{name}", std::process::id()));
+        File::create(&path).unwrap().write_all(bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn inspect_wav_prompt() {
+        let header = WavHeader {
+            format_tag: wav::WAVE_FORMAT_PCM,
+            channels: 2,
+            sample_rate: 16000,
+            block_align: 4,
+            bits_per_sample: 16,
+            data_len: 0,
+        };
+        let path = write_temp("stereo.wav", &wav::header_bytes(&header));
+
+        let info = inspect_prompt(&path, &PromptHints::default()).unwrap();
+        assert_eq!(info.audio_type, WAV_PCM16);
+        assert_eq!(info.sample_rate, 16000);
+        assert_eq!(info.channels, Channels::Stereo);
+        assert_eq!(
+            info.play_file(3, Some("prompts/stereo.wav".into()), None, None).to_string(),
+            "PlayFile 3 prompts/stereo.wav Type=WAV_PCM16 SampleRate=16000 Channels=2"
+        );
+    }
+
+    #[test]
+    fn inspect_raw_prompt_uses_extension_and_hints() {
+        let path = write_temp("greeting.alaw", &[0xd5; 80]);
+        let info = inspect_prompt(&path, &PromptHints::default()).unwrap();
+        assert_eq!(info.audio_type, RAW_ALAW);
+        assert_eq!(info.sample_rate, 8000);
+        assert_eq!(info.channels, Channels::Mono);
+
+        let path = write_temp("greeting.raw", &[0; 80]);
+        assert!(matches!(
+            inspect_prompt(&path, &PromptHints::default()),
+            Err(PromptError::MissingAudioType(_))
+        ));
+        let hints = PromptHints {
+            audio_type: Some(RAW_G726_32K),
+            ..Default::default()
+        };
+        assert_eq!(inspect_prompt(&path, &hints).unwrap().audio_type, RAW_G726_32K);
+    }
+
+    #[test]
+    fn inspect_vap_prompt_requires_vap_hint() {
+        let path = write_temp("menu.vap", &[0; 16]);
+        let hints = PromptHints {
+            audio_type: Some(RAW_ALAW),
+            ..Default::default()
+        };
+        assert!(matches!(
+            inspect_prompt(&path, &hints),
+            Err(PromptError::WrongContainer("RAW_ALAW", "VAP"))
+        ));
+
+        let hints = PromptHints {
+            audio_type: Some(VAP_ALAW),
+            ..Default::default()
+        };
+        assert_eq!(inspect_prompt(&path, &hints).unwrap().audio_type, VAP_ALAW);
+    }
+
+    #[test]
+    fn inspect_rejects_invalid_hints() {
+        let path = write_temp("hint.g729", &[0; 20]);
+        let hints = PromptHints {
+            channels: Some(Channels::Stereo),
+            ..Default::default()
+        };
+        assert!(matches!(
+            inspect_prompt(&path, &hints),
+            Err(PromptError::Invalid(CommandError::UnsupportedChannels { .. }))
+        ));
+    }
+}
@@ -0,0 +1,792 @@
+use crate::constants::{FaxSendSpeed, ALL_FAX_SEND_SPEEDS};
+use crate::fax::{FaxReport, FaxSendOptions, HeaderTemplate};
+use crate::primitives::{unix_time, FaxIdentifier, ECM};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "queue")?;
+
+    child_module.add_class::<FaxSchedule>()?;
+    child_module.add_class::<FaxAttempt>()?;
+    child_module.add_class::<QueuedFax>()?;
+    child_module.add_class::<FaxQueue>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum QueueError {
+    #[error("cannot access fax queue: {0}")]
+    Io(#[from] io::Error),
+    #[error("fax queue file is corrupt at line {0}: {1}")]
+    Corrupt(usize, &'static str),
+    #[error("no fax job {0} in the queue")]
+    UnknownJob(u64),
+    #[error("fax job {0} is {1}")]
+    WrongStatus(u64, &'static str),
+}
+
+impl From<QueueError> for PyErr {
+    fn from(err: QueueError) -> Self {
+        match err {
+            QueueError::Io(e) => PyIOError::new_err(e.to_string()),
+            other => PyValueError::new_err(other.to_string()),
+        }
+    }
+}
+
+const HEADER: &str = "# gridborg fax queue 1";
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// When queued faxes may be sent.
+///
+/// `windows` are `(start, end)` minutes of the day, in UTC shifted by `utc_offset_minutes`,
+/// during which calls are made; a window ending before it starts runs over midnight, and no
+/// windows means any time. Calls to one number are at least `min_interval_s` apart and at
+/// most `max_per_hour` in any hour. A failed fax is tried again `retry_delay_s` later, up
+/// to `max_attempts` attempts in all.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxSchedule {
+    #[pyo3(get, set)]
+    pub windows: Vec<(u32, u32)>,
+    #[pyo3(get, set)]
+    pub utc_offset_minutes: i32,
+    #[pyo3(get, set)]
+    pub min_interval_s: u64,
+    #[pyo3(get, set)]
+    pub max_per_hour: Option<u32>,
+    #[pyo3(get, set)]
+    pub max_attempts: u32,
+    #[pyo3(get, set)]
+    pub retry_delay_s: u64,
+}
+
+impl Default for FaxSchedule {
+    fn default() -> Self {
+        FaxSchedule {
+            windows: Vec::new(),
+            utc_offset_minutes: 0,
+            min_interval_s: 0,
+            max_per_hour: None,
+            max_attempts: 3,
+            retry_delay_s: 300,
+        }
+    }
+}
+
+impl FaxSchedule {
+    pub fn in_window(&self, now: u64) -> bool {
+        let local = now as i64 + self.utc_offset_minutes as i64 * 60;
+        let minute = (local.rem_euclid(SECONDS_PER_DAY) / 60) as u32;
+        self.windows.is_empty()
+            || self.windows.iter().any(|&(start, end)| {
+                if start <= end {
+                    (start..end).contains(&minute)
+                } else {
+                    minute >= start || minute < end
+                }
+            })
+    }
+}
+
+#[pymethods]
+impl FaxSchedule {
+    #[new]
+    #[pyo3(signature = (windows=Vec::new(), utc_offset_minutes=0, min_interval_s=0, max_per_hour=None, max_attempts=3, retry_delay_s=300))]
+    fn py_new(
+        windows: Vec<(u32, u32)>,
+        utc_offset_minutes: i32,
+        min_interval_s: u64,
+        max_per_hour: Option<u32>,
+        max_attempts: u32,
+        retry_delay_s: u64,
+    ) -> Self {
+        FaxSchedule {
+            windows,
+            utc_offset_minutes,
+            min_interval_s,
+            max_per_hour,
+            max_attempts,
+            retry_delay_s,
+        }
+    }
+
+    #[pyo3(name = "in_window")]
+    #[pyo3(signature = (now=None))]
+    fn py_in_window(&self, now: Option<u64>) -> bool {
+        self.in_window(now.unwrap_or_else(unix_time))
+    }
+}
+
+/// One attempt at sending a queued fax; times are seconds since the Unix epoch.
+///
+/// `ended_at` is `None` while the attempt is running. `failure_kind` is the `FaxFailure`
+/// kind, or `interrupted` when the process stopped or the client failed mid-attempt.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaxAttempt {
+    #[pyo3(get)]
+    started_at: u64,
+    #[pyo3(get)]
+    ended_at: Option<u64>,
+    #[pyo3(get)]
+    success: bool,
+    #[pyo3(get)]
+    pages_sent: u32,
+    #[pyo3(get)]
+    failure_kind: Option<String>,
+    #[pyo3(get)]
+    failure: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    Pending,
+    Sending,
+    Sent,
+    Failed,
+    Cancelled,
+}
+
+const STATUSES: [Status; 5] = [Status::Pending, Status::Sending, Status::Sent, Status::Failed, Status::Cancelled];
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Sending => "sending",
+            Status::Sent => "sent",
+            Status::Failed => "failed",
+            Status::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// A fax in the queue, with the send settings stored for it and its attempts so far.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueuedFax {
+    #[pyo3(get)]
+    id: u64,
+    #[pyo3(get)]
+    number: String,
+    #[pyo3(get)]
+    files: Vec<String>,
+    #[pyo3(get)]
+    header: Option<HeaderTemplate>,
+    #[pyo3(get)]
+    tsi: Option<FaxIdentifier>,
+    #[pyo3(get)]
+    speed: Option<FaxSendSpeed>,
+    #[pyo3(get)]
+    ecm: Option<ECM>,
+    status: Status,
+    /// Not sent before this time, in seconds since the Unix epoch.
+    #[pyo3(get)]
+    not_before: u64,
+    #[pyo3(get)]
+    attempts: Vec<FaxAttempt>,
+}
+
+impl QueuedFax {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn number(&self) -> &str {
+        &self.number
+    }
+
+    pub fn files(&self) -> Vec<String> {
+        self.files.clone()
+    }
+
+    /// `options` with the header, TSI, speed and ECM stored for this fax.
+    pub fn options(&self, options: &FaxSendOptions) -> FaxSendOptions {
+        FaxSendOptions {
+            header: self.header.clone(),
+            tsi: self.tsi.clone(),
+            speed: self.speed,
+            ecm: self.ecm,
+            ..options.clone()
+        }
+    }
+}
+
+#[pymethods]
+impl QueuedFax {
+    #[getter]
+    fn status(&self) -> &'static str {
+        self.status.name()
+    }
+
+    #[pyo3(name = "options")]
+    #[pyo3(signature = (options=FaxSendOptions::default()))]
+    fn py_options(&self, options: FaxSendOptions) -> FaxSendOptions {
+        self.options(&options)
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped += "\\\\",
+            '\t' => escaped += "\\t",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next() {
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some(other) => other,
+            None => '\\',
+        });
+    }
+    unescaped
+}
+
+/// Field of a present but empty value, which `escape` never produces.
+const EMPTY_FIELD: &str = "\\e";
+
+/// An optional field, empty for `None` and `EMPTY_FIELD` for an empty value.
+fn opt_field<T: ToString>(value: &Option<T>) -> String {
+    match value.as_ref().map(T::to_string) {
+        None => String::new(),
+        Some(v) if v.is_empty() => EMPTY_FIELD.to_string(),
+        Some(v) => escape(&v),
+    }
+}
+
+fn parse_opt<T>(field: &str, parse: impl FnOnce(&str) -> Option<T>) -> Result<Option<T>, &'static str> {
+    match field {
+        "" => Ok(None),
+        EMPTY_FIELD => parse("").map(Some).ok_or("bad value"),
+        field => parse(&unescape(field)).map(Some).ok_or("bad value"),
+    }
+}
+
+/// Fax jobs kept in a file, so that they survive restarts of the process.
+///
+/// The queue is written again after every change, to a temporary file renamed over the old
+/// one. On opening, jobs that were being sent when the process stopped get a failed
+/// `interrupted` attempt and are scheduled again. `GridborgClient.process_fax_queue` sends the
+/// jobs that are due; `next_due`, `begin` and `finish` are the steps it takes.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct FaxQueue {
+    path: PathBuf,
+    #[pyo3(get, set)]
+    schedule: FaxSchedule,
+    jobs: Vec<QueuedFax>,
+    next_id: u64,
+}
+
+impl FaxQueue {
+    /// Open the queue stored at `path`, or an empty one if the file does not exist yet.
+    pub fn open(path: &Path, schedule: FaxSchedule) -> Result<Self, QueueError> {
+        let mut queue = FaxQueue {
+            path: path.to_path_buf(),
+            schedule,
+            jobs: Vec::new(),
+            next_id: 1,
+        };
+        match fs::read_to_string(path) {
+            Ok(text) => queue.load(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(queue),
+            Err(e) => return Err(e.into()),
+        }
+        let now = unix_time();
+        let interrupted: Vec<u64> = queue.jobs_with(Status::Sending).collect();
+        for &id in &interrupted {
+            queue.interrupt(id, "process stopped while sending", now)?;
+        }
+        Ok(queue)
+    }
+
+    fn load(&mut self, text: &str) -> Result<(), QueueError> {
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            self.load_line(&fields).map_err(|reason| QueueError::Corrupt(number, reason))?;
+        }
+        Ok(())
+    }
+
+    fn load_line(&mut self, fields: &[&str]) -> Result<(), &'static str> {
+        let number = |field: &str| field.parse::<u64>().map_err(|_| "bad number");
+        match fields {
+            ["next_id", id] => self.next_id = number(id)?,
+            ["job", id, status, not_before, destination, header, tsi, speed, ecm] => {
+                self.jobs.push(QueuedFax {
+                    id: number(id)?,
+                    number: unescape(destination),
+                    files: Vec::new(),
+                    header: parse_opt(header, |v| HeaderTemplate::parse(v).ok())?,
+                    tsi: parse_opt(tsi, |v| v.parse().ok())?,
+                    speed: parse_opt(speed, |v| ALL_FAX_SEND_SPEEDS.iter().find(|s| s.name == v).copied())?,
+                    ecm: parse_opt(ecm, |v| v.parse().ok())?,
+                    status: *STATUSES.iter().find(|s| s.name() == *status).ok_or("unknown status")?,
+                    not_before: number(not_before)?,
+                    attempts: Vec::new(),
+                });
+            }
+            ["file", path] => self.jobs.last_mut().ok_or("file before job")?.files.push(unescape(path)),
+            ["attempt", started_at, ended_at, success, pages_sent, failure_kind, failure] => {
+                let attempt = FaxAttempt {
+                    started_at: number(started_at)?,
+                    ended_at: parse_opt(ended_at, |v| v.parse().ok())?,
+                    success: *success == "1",
+                    pages_sent: pages_sent.parse().map_err(|_| "bad page count")?,
+                    failure_kind: parse_opt(failure_kind, |v| Some(v.to_string()))?,
+                    failure: parse_opt(failure, |v| Some(v.to_string()))?,
+                };
+                self.jobs.last_mut().ok_or("attempt before job")?.attempts.push(attempt);
+            }
+            _ => return Err("unknown record"),
+        }
+        Ok(())
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("{}\nnext_id\t{}\n", HEADER, self.next_id);
+        for job in &self.jobs {
+            text += &format!(
+                "job\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                job.id,
+                job.status.name(),
+                job.not_before,
+                escape(&job.number),
+                opt_field(&job.header.as_ref().map(HeaderTemplate::as_str)),
+                opt_field(&job.tsi),
+                opt_field(&job.speed.map(|s| s.name)),
+                opt_field(&job.ecm.map(|e| e as u16)),
+            );
+            for file in &job.files {
+                text += &format!("file\t{}\n", escape(file));
+            }
+            for attempt in &job.attempts {
+                text += &format!(
+                    "attempt\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    attempt.started_at,
+                    opt_field(&attempt.ended_at),
+                    attempt.success as u8,
+                    attempt.pages_sent,
+                    opt_field(&attempt.failure_kind),
+                    opt_field(&attempt.failure),
+                );
+            }
+        }
+        text
+    }
+
+    fn save(&self) -> Result<(), QueueError> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_text())?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+
+    pub fn jobs(&self) -> &[QueuedFax] {
+        &self.jobs
+    }
+
+    pub fn job(&self, id: u64) -> Result<&QueuedFax, QueueError> {
+        self.jobs.iter().find(|job| job.id == id).ok_or(QueueError::UnknownJob(id))
+    }
+
+    fn job_mut(&mut self, id: u64) -> Result<&mut QueuedFax, QueueError> {
+        self.jobs.iter_mut().find(|job| job.id == id).ok_or(QueueError::UnknownJob(id))
+    }
+
+    fn jobs_with(&self, status: Status) -> impl Iterator<Item = u64> + '_ {
+        self.jobs.iter().filter(move |job| job.status == status).map(|job| job.id)
+    }
+
+    /// Queue `files` for `number` with the header, TSI, speed and ECM of `options`, not to
+    /// be sent before `not_before`. Returns the id of the new job.
+    pub fn submit(
+        &mut self,
+        number: &str,
+        files: Vec<String>,
+        options: &FaxSendOptions,
+        not_before: u64,
+    ) -> Result<u64, QueueError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(QueuedFax {
+            id,
+            number: number.to_string(),
+            files,
+            header: options.header.clone(),
+            tsi: options.tsi.clone(),
+            speed: options.speed,
+            ecm: options.ecm,
+            status: Status::Pending,
+            not_before,
+            attempts: Vec::new(),
+        });
+        self.save()?;
+        Ok(id)
+    }
+
+    /// Take a job that has not started yet out of the schedule.
+    pub fn cancel(&mut self, id: u64) -> Result<(), QueueError> {
+        let job = self.job_mut(id)?;
+        if job.status != Status::Pending {
+            return Err(QueueError::WrongStatus(id, job.status.name()));
+        }
+        job.status = Status::Cancelled;
+        self.save()
+    }
+
+    /// Whether the rate limits allow calling `number` at `now`; a call in progress to the
+    /// number holds off any other.
+    fn destination_free(&self, number: &str, now: u64) -> bool {
+        let mut attempts = self
+            .jobs
+            .iter()
+            .filter(|job| job.number == number)
+            .flat_map(|job| &job.attempts);
+        let mut last_hour = 0;
+        let free = attempts.all(|attempt| {
+            if now.saturating_sub(attempt.started_at) < 3600 {
+                last_hour += 1;
+            }
+            attempt.ended_at.is_some() && now.saturating_sub(attempt.started_at) >= self.schedule.min_interval_s
+        });
+        free && self.schedule.max_per_hour.is_none_or(|max| last_hour < max)
+    }
+
+    /// The first pending job that may be sent at `now`, in order of submission.
+    pub fn next_due(&self, now: u64) -> Option<u64> {
+        if !self.schedule.in_window(now) {
+            return None;
+        }
+        self.jobs
+            .iter()
+            .filter(|job| job.status == Status::Pending && job.not_before <= now)
+            .find(|job| self.destination_free(&job.number, now))
+            .map(|job| job.id)
+    }
+
+    /// Mark a job as being sent, starting a new attempt.
+    pub fn begin(&mut self, id: u64, now: u64) -> Result<QueuedFax, QueueError> {
+        let job = self.job_mut(id)?;
+        if job.status != Status::Pending {
+            return Err(QueueError::WrongStatus(id, job.status.name()));
+        }
+        job.status = Status::Sending;
+        job.attempts.push(FaxAttempt {
+            started_at: now,
+            ended_at: None,
+            success: false,
+            pages_sent: 0,
+            failure_kind: None,
+            failure: None,
+        });
+        let job = job.clone();
+        self.save()?;
+        Ok(job)
+    }
+
+    /// Record the outcome of the attempt in progress.
+    pub fn finish(&mut self, id: u64, report: &FaxReport, now: u64) -> Result<(), QueueError> {
+        let failure = (!report.success).then(|| {
+            let kind = report.failures.last().map_or("failed", |failure| failure.kind);
+            (kind, report.failure.clone().unwrap_or_default())
+        });
+        self.end_attempt(id, now, report.pages_sent, failure)
+    }
+
+    /// Record the attempt in progress as failed because the client stopped part-way.
+    pub fn interrupt(&mut self, id: u64, reason: &str, now: u64) -> Result<(), QueueError> {
+        self.end_attempt(id, now, 0, Some(("interrupted", reason.to_string())))
+    }
+
+    /// End the running attempt. A failure, as kind and reason, is retried later unless the
+    /// attempts have run out or the document itself was refused.
+    fn end_attempt(
+        &mut self,
+        id: u64,
+        now: u64,
+        pages_sent: u32,
+        failure: Option<(&str, String)>,
+    ) -> Result<(), QueueError> {
+        let schedule = self.schedule.clone();
+        let job = self.job_mut(id)?;
+        if job.status != Status::Sending {
+            return Err(QueueError::WrongStatus(id, job.status.name()));
+        }
+        let attempts = job.attempts.len() as u32;
+        let attempt = job.attempts.last_mut().ok_or(QueueError::WrongStatus(id, "without attempt"))?;
+        attempt.ended_at = Some(now);
+        attempt.pages_sent = pages_sent;
+        attempt.success = failure.is_none();
+        job.status = match failure {
+            None => Status::Sent,
+            Some((kind, _)) if attempts >= schedule.max_attempts || kind == "document" => Status::Failed,
+            Some(_) => {
+                job.not_before = now + schedule.retry_delay_s;
+                Status::Pending
+            }
+        };
+        if let Some((kind, reason)) = failure {
+            attempt.failure_kind = Some(kind.to_string());
+            attempt.failure = Some(reason);
+        }
+        self.save()
+    }
+}
+
+#[pymethods]
+impl FaxQueue {
+    #[new]
+    #[pyo3(signature = (path, schedule=FaxSchedule::default()))]
+    fn py_new(path: &str, schedule: FaxSchedule) -> PyResult<Self> {
+        Ok(FaxQueue::open(Path::new(path), schedule)?)
+    }
+
+    #[getter]
+    fn path(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    #[getter(jobs)]
+    fn py_jobs(&self) -> Vec<QueuedFax> {
+        self.jobs.clone()
+    }
+
+    #[pyo3(name = "job")]
+    fn py_job(&self, id: u64) -> PyResult<QueuedFax> {
+        Ok(self.job(id)?.clone())
+    }
+
+    #[pyo3(name = "submit")]
+    #[pyo3(signature = (number, files, options=FaxSendOptions::default(), not_before=0))]
+    fn py_submit(&mut self, number: &str, files: Vec<String>, options: FaxSendOptions, not_before: u64) -> PyResult<u64> {
+        Ok(self.submit(number, files, &options, not_before)?)
+    }
+
+    #[pyo3(name = "cancel")]
+    fn py_cancel(&mut self, id: u64) -> PyResult<()> {
+        Ok(self.cancel(id)?)
+    }
+
+    #[pyo3(name = "next_due")]
+    #[pyo3(signature = (now=None))]
+    fn py_next_due(&self, now: Option<u64>) -> Option<u64> {
+        self.next_due(now.unwrap_or_else(unix_time))
+    }
+
+    #[pyo3(name = "begin")]
+    #[pyo3(signature = (id, now=None))]
+    fn py_begin(&mut self, id: u64, now: Option<u64>) -> PyResult<QueuedFax> {
+        Ok(self.begin(id, now.unwrap_or_else(unix_time))?)
+    }
+
+    #[pyo3(name = "finish")]
+    #[pyo3(signature = (id, report, now=None))]
+    fn py_finish(&mut self, id: u64, report: FaxReport, now: Option<u64>) -> PyResult<()> {
+        Ok(self.finish(id, &report, now.unwrap_or_else(unix_time))?)
+    }
+
+    #[pyo3(name = "interrupt")]
+    #[pyo3(signature = (id, reason, now=None))]
+    fn py_interrupt(&mut self, id: u64, reason: &str, now: Option<u64>) -> PyResult<()> {
+        Ok(self.interrupt(id, reason, now.unwrap_or_else(unix_time))?)
+    }
+
+    fn __len__(&self) -> usize {
+        self.jobs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::FaxSendSpeed_V29At9600;
+    use crate::events::parse_event;
+    use crate::fax::FaxSendJob;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gridborg-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// The report of a job run through `lines`, with the events of a call on resource 1.
+    fn report(lines: &[&str]) -> FaxReport {
+        let mut job = FaxSendJob::new("100", vec!["a.tif".into()], FaxSendOptions::default(), 1, 2, 3);
+        job.start();
+        for line in lines {
+            job.handle_event(&parse_event(line).unwrap());
+        }
+        job.report().unwrap()
+    }
+
+    fn options() -> FaxSendOptions {
+        FaxSendOptions {
+            header: HeaderTemplate::parse("ACME {date} p{page}").ok(),
+            tsi: "+1 555 0100".parse().ok(),
+            speed: Some(FaxSendSpeed_V29At9600),
+            ecm: Some(ECM::ECM64),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn time_windows() {
+        let schedule = FaxSchedule {
+            windows: vec![(8 * 60, 18 * 60), (22 * 60, 60)],
+            utc_offset_minutes: 120,
+            ..Default::default()
+        };
+        let at = |hour: u64, minute: u64| 1_792_281_600 + hour * 3600 + minute * 60 - 7200;
+        assert!(schedule.in_window(at(8, 0)) && schedule.in_window(at(17, 59)));
+        assert!(!schedule.in_window(at(18, 0)) && !schedule.in_window(at(7, 59)));
+        assert!(schedule.in_window(at(23, 30)) && schedule.in_window(at(0, 30)) && !schedule.in_window(at(1, 0)));
+        assert!(FaxSchedule::default().in_window(0));
+    }
+
+    #[test]
+    fn persists_jobs_and_attempts() {
+        let path = temp_path("persist.queue");
+        let mut queue = FaxQueue::open(&path, FaxSchedule::default()).unwrap();
+        let id = queue.submit("5550100", vec!["a.tif".into(), "b\tc.pdf".into()], &options(), 0).unwrap();
+        queue.begin(id, 1000).unwrap();
+        queue.finish(id, &report(&["ECallConnectionFailed 1 1 EndedByRemoteBusy"]), 1010).unwrap();
+        let second = queue.submit("5550199", Vec::new(), &FaxSendOptions::default(), 50).unwrap();
+
+        let reopened = FaxQueue::open(&path, FaxSchedule::default()).unwrap();
+        assert_eq!(reopened.jobs(), queue.jobs());
+        let job = reopened.job(id).unwrap();
+        assert_eq!(job.files, ["a.tif", "b\tc.pdf"]);
+        assert_eq!(job.header.as_ref().map(HeaderTemplate::as_str), Some("ACME {date} p{page}"));
+        assert_eq!((job.status, job.not_before), (Status::Pending, 1310));
+        assert_eq!(job.attempts[0].failure_kind.as_deref(), Some("busy"));
+        assert_eq!(reopened.next_id, second + 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn empty_values_stay_apart_from_missing_ones() {
+        assert_eq!(opt_field(&Some("")), EMPTY_FIELD);
+        assert_eq!(opt_field::<&str>(&None), "");
+        assert_eq!(opt_field(&Some("\\e")), "\\\\e");
+        let text = |field: &str| parse_opt(field, |v| Some(v.to_string()));
+        assert_eq!(text(EMPTY_FIELD), Ok(Some(String::new())));
+        assert_eq!(text(""), Ok(None));
+        assert_eq!(text("\\\\e"), Ok(Some("\\e".into())));
+
+        let path = temp_path("empty.queue");
+        let mut queue = FaxQueue::open(&path, FaxSchedule::default()).unwrap();
+        let with_empty = FaxSendOptions {
+            header: HeaderTemplate::parse("").ok(),
+            ..options()
+        };
+        let id = queue.submit("5550100", Vec::new(), &with_empty, 0).unwrap();
+        let bare = queue.submit("5550100", Vec::new(), &FaxSendOptions::default(), 0).unwrap();
+
+        let reopened = FaxQueue::open(&path, FaxSchedule::default()).unwrap();
+        assert_eq!(reopened.job(id).unwrap().header.as_ref().map(HeaderTemplate::as_str), Some(""));
+        assert_eq!(reopened.job(bare).unwrap().header, None);
+        assert_eq!(reopened.job(id).unwrap().ecm, Some(ECM::ECM64));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_stored_values_are_corrupt() {
+        let path = temp_path("invalid.queue");
+        for (header, tsi) in [("{fax}", ""), ("", "ACME +1 555 0100")] {
+            fs::write(&path, format!("{HEADER}\nnext_id\t2\njob\t1\tpending\t0\t5550100\t{header}\t{tsi}\t\t\n")).unwrap();
+            let err = FaxQueue::open(&path, FaxSchedule::default()).unwrap_err();
+            assert!(matches!(err, QueueError::Corrupt(3, "bad value")), "{err}");
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn interrupted_job_is_rescheduled_on_open() {
+        let path = temp_path("interrupted.queue");
+        let mut queue = FaxQueue::open(&path, FaxSchedule::default()).unwrap();
+        let id = queue.submit("5550100", vec!["a.tif".into()], &options(), 0).unwrap();
+        queue.begin(id, 1000).unwrap();
+
+        let reopened = FaxQueue::open(&path, FaxSchedule::default()).unwrap();
+        let job = reopened.job(id).unwrap();
+        assert_eq!(job.status, Status::Pending);
+        assert_eq!(job.attempts[0].failure_kind.as_deref(), Some("interrupted"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rate_limits_and_outcomes() {
+        let path = temp_path("limits.queue");
+        let schedule = FaxSchedule {
+            min_interval_s: 60,
+            max_per_hour: Some(2),
+            max_attempts: 2,
+            retry_delay_s: 0,
+            ..Default::default()
+        };
+        let mut queue = FaxQueue::open(&path, schedule).unwrap();
+        let a = queue.submit("100", vec!["a.tif".into()], &options(), 0).unwrap();
+        let b = queue.submit("100", vec!["b.tif".into()], &options(), 0).unwrap();
+        let c = queue.submit("200", vec!["c.tif".into()], &options(), 0).unwrap();
+
+        assert_eq!(queue.next_due(1000), Some(a));
+        queue.begin(a, 1000).unwrap();
+        assert_eq!(queue.next_due(1000), Some(c), "100 is busy with a");
+        queue.finish(a, &report(&["EDocumentNotPrepared 1 3 BadFile"]), 1010).unwrap();
+        assert_eq!(queue.job(a).unwrap().status(), "failed", "documents are not retried");
+        assert_eq!(queue.next_due(1030), Some(c), "too soon after a for 100");
+
+        assert_eq!(queue.next_due(1060), Some(b));
+        queue.begin(b, 1060).unwrap();
+        queue.finish(b, &report(&["EFaxOperationFailed 1 2"]), 1070).unwrap();
+        queue.cancel(c).unwrap();
+        assert_eq!(queue.next_due(1200), None, "two calls to 100 in the last hour");
+        assert_eq!(queue.next_due(4601), Some(b));
+
+        queue.begin(b, 4601).unwrap();
+        queue.finish(
+            b,
+            &report(&[
+                "EDocumentPrepared 1 3",
+                "ECallConnectionEstablished 1 1",
+                "EFacsimilePageSent 1 2",
+                "EFaxOperationFinished 1 2",
+            ]),
+            4700,
+        )
+        .unwrap();
+        let job = queue.job(b).unwrap();
+        assert_eq!(job.status(), "sent");
+        assert!(job.attempts[1].success && job.attempts[1].pages_sent == 1);
+        assert!(matches!(queue.cancel(b), Err(QueueError::WrongStatus(_, "sent"))));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+use crate::commands::Command;
+use crate::constants::PlayTone_RecorderWarningTone;
+use crate::events::Event;
+use crate::primitives::{unix_time, Channels, ResourceId};
+use pyo3::prelude::*;
+use std::time::Instant;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "recording")?;
+
+    child_module.add_class::<CallRecording>()?;
+    child_module.add_class::<RecordingMetadata>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+/// Recorder channel receiving the first leg.
+pub const LEFT_CHANNEL: u8 = 1;
+/// Recorder channel receiving the second leg.
+pub const RIGHT_CHANNEL: u8 = 2;
+
+/// What is known about a stereo recording once it has ended.
+///
+/// `left` and `right` are the calls recorded on each channel. Times are in seconds
+/// since the Unix epoch.
+#[pyclass]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordingMetadata {
+    #[pyo3(get)]
+    path: String,
+    #[pyo3(get)]
+    left: ResourceId,
+    #[pyo3(get)]
+    right: ResourceId,
+    #[pyo3(get)]
+    started_at: u64,
+    #[pyo3(get)]
+    ended_at: Option<u64>,
+    #[pyo3(get)]
+    duration_ms: Option<u64>,
+    /// The leg whose clearing ended the recording.
+    #[pyo3(get)]
+    cleared_by: Option<ResourceId>,
+    #[pyo3(get)]
+    clear_reason: Option<String>,
+    #[pyo3(get)]
+    stop_reason: Option<String>,
+    #[pyo3(get)]
+    error: Option<String>,
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            c if c.is_control() => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json + "\""
+}
+
+fn json_opt<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or("null".to_string(), T::to_string)
+}
+
+#[pymethods]
+impl RecordingMetadata {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"path\":{},\"left\":{},\"right\":{},\"started_at\":{},\"ended_at\":{},\"duration_ms\":{},\
+             \"cleared_by\":{},\"clear_reason\":{},\"stop_reason\":{},\"error\":{}}}",
+            json_string(&self.path),
+            self.left,
+            self.right,
+            self.started_at,
+            json_opt(&self.ended_at),
+            json_opt(&self.duration_ms),
+            json_opt(&self.cleared_by),
+            json_opt(&self.clear_reason.as_deref().map(json_string)),
+            json_opt(&self.stop_reason.as_deref().map(json_string)),
+            json_opt(&self.error.as_deref().map(json_string)),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    Recording,
+    Stopping,
+    Finished,
+}
+
+/// A two-party call recorded to one stereo file, each leg on its own channel.
+///
+/// Like `Conference`, the recording plans commands and leaves sending them to the caller:
+/// `start` routes the legs to the recorder (and the optional warning tone player to the
+/// legs), `handle_event` stops the recorder when either leg clears and, once it has
+/// stopped, fills in `metadata` and deletes the recorder and player.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct CallRecording {
+    #[pyo3(get)]
+    call_a: ResourceId,
+    #[pyo3(get)]
+    call_b: ResourceId,
+    #[pyo3(get)]
+    recorder: ResourceId,
+    #[pyo3(get)]
+    player: Option<ResourceId>,
+    #[pyo3(get)]
+    metadata: RecordingMetadata,
+    state: State,
+    started: Option<Instant>,
+}
+
+impl CallRecording {
+    /// With a `player`, `start` plays the `RecorderWarningTone` to both legs through it.
+    pub fn new(
+        call_a: ResourceId,
+        call_b: ResourceId,
+        recorder: ResourceId,
+        player: Option<ResourceId>,
+        path: &str,
+    ) -> Self {
+        CallRecording {
+            call_a,
+            call_b,
+            recorder,
+            player,
+            metadata: RecordingMetadata {
+                path: path.to_string(),
+                left: call_a,
+                right: call_b,
+                ..Default::default()
+            },
+            state: State::Idle,
+            started: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Finished
+    }
+
+    pub fn start(&mut self) -> Vec<Command> {
+        if self.state != State::Idle {
+            return Vec::new();
+        }
+        self.state = State::Recording;
+        self.started = Some(Instant::now());
+        self.metadata.started_at = unix_time();
+
+        let leg = |call, channel| {
+            Command::audio_send(call, self.recorder, None, Some(channel), None, None, None, None, None, None)
+        };
+        let mut commands = vec![
+            leg(self.call_a, LEFT_CHANNEL),
+            leg(self.call_b, RIGHT_CHANNEL),
+            Command::recorder_start_to_file(
+                self.recorder,
+                self.metadata.path.clone(),
+                None,
+                None,
+                Some(Channels::Stereo),
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+        ];
+        if let Some(player) = self.player {
+            for call in [self.call_a, self.call_b] {
+                commands.push(Command::audio_send(player, call, None, None, None, None, None, None, None, None));
+            }
+            commands.push(Command::play_tone(
+                player,
+                None,
+                None,
+                Some(PlayTone_RecorderWarningTone),
+                None,
+                None,
+            ));
+        }
+        commands
+    }
+
+    /// Stop recording before either leg clears.
+    pub fn stop(&mut self) -> Vec<Command> {
+        if self.state != State::Recording {
+            return Vec::new();
+        }
+        self.state = State::Stopping;
+        vec![Command::recorder_stop(self.recorder)]
+    }
+
+    /// Follow an event from the server, returning the commands it calls for.
+    pub fn handle_event(&mut self, event: &Event) -> Vec<Command> {
+        match event {
+            Event::CallCleared(e) if e.resource_id == self.call_a || e.resource_id == self.call_b => {
+                if self.metadata.cleared_by.is_none() {
+                    self.metadata.cleared_by = Some(e.resource_id);
+                    self.metadata.clear_reason = Some(e.reason.clone());
+                }
+                self.stop()
+            }
+            Event::RecorderStopped(e) if e.resource_id == self.recorder => {
+                self.metadata.stop_reason = Some(e.reason.name.to_string());
+                self.finish()
+            }
+            Event::RecorderError(e) if e.resource_id == self.recorder => {
+                self.metadata.error = Some(e.error_text.clone());
+                self.finish()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Fill in the end of the recording and delete the resources it created; deleting
+    /// them also cancels their routes.
+    fn finish(&mut self) -> Vec<Command> {
+        if self.state == State::Finished {
+            return Vec::new();
+        }
+        self.state = State::Finished;
+        self.metadata.ended_at = Some(unix_time());
+        self.metadata.duration_ms = self.started.map(|started| started.elapsed().as_millis() as u64);
+        std::iter::once(self.recorder)
+            .chain(self.player)
+            .map(Command::resource_delete)
+            .collect()
+    }
+}
+
+#[pymethods]
+impl CallRecording {
+    #[new]
+    #[pyo3(signature = (call_a, call_b, recorder, path, player=None))]
+    fn py_new(
+        call_a: ResourceId,
+        call_b: ResourceId,
+        recorder: ResourceId,
+        path: &str,
+        player: Option<ResourceId>,
+    ) -> Self {
+        CallRecording::new(call_a, call_b, recorder, player, path)
+    }
+
+    #[getter(finished)]
+    fn py_finished(&self) -> bool {
+        self.is_finished()
+    }
+
+    #[pyo3(name = "start")]
+    fn py_start(&mut self) -> Vec<Command> {
+        self.start()
+    }
+
+    #[pyo3(name = "stop")]
+    fn py_stop(&mut self) -> Vec<Command> {
+        self.stop()
+    }
+
+    #[pyo3(name = "handle_event")]
+    fn py_handle_event(&mut self, event: Event) -> Vec<Command> {
+        self.handle_event(&event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::testing::lines;
+    use crate::events::parse_event;
+
+    fn event(line: &str) -> Event {
+        parse_event(line).unwrap()
+    }
+
+    #[test]
+    fn start_routes_legs_to_channels() {
+        let mut recording = CallRecording::new(1, 2, 7, Some(8), "calls/1.wav");
+        let commands = lines(&recording.start());
+        assert_eq!(
+            commands[..3],
+            [
+                "AudioSend 1 7 SinkChannel=1",
+                "AudioSend 2 7 SinkChannel=2",
+                "RecorderStartToFile 7 calls/1.wav Channels=2",
+            ]
+        );
+        assert_eq!(commands[3..5], ["AudioSend 8 1", "AudioSend 8 2"]);
+        assert!(commands[5].starts_with("PlayTone 8 Tone="));
+        assert!(recording.start().is_empty());
+    }
+
+    #[test]
+    fn leg_clearing_stops_and_finalizes() {
+        let mut recording = CallRecording::new(1, 2, 7, None, "calls/1.wav");
+        recording.start();
+        assert!(recording.handle_event(&event("ECallCleared 1 5 Normal")).is_empty());
+        assert_eq!(lines(&recording.handle_event(&event("ECallCleared 1 2 Normal"))), ["RecorderStop 7"]);
+        assert!(recording.handle_event(&event("ECallCleared 1 1 Normal")).is_empty());
+        assert!(!recording.is_finished());
+
+        assert_eq!(
+            lines(&recording.handle_event(&event("ERecorderStopped 1 7 ExplicitRequest"))),
+            ["ResourceDelete 7"]
+        );
+        assert!(recording.is_finished());
+        let metadata = &recording.metadata;
+        assert_eq!((metadata.cleared_by, metadata.clear_reason.as_deref()), (Some(2), Some("Normal")));
+        assert_eq!(metadata.stop_reason.as_deref(), Some("ExplicitRequest"));
+        assert!(metadata.ended_at.is_some() && metadata.duration_ms.is_some());
+        assert!(metadata.to_json().starts_with("{\"path\":\"calls/1.wav\",\"left\":1,\"right\":2,"));
+        assert!(metadata.to_json().ends_with("\"cleared_by\":2,\"clear_reason\":\"Normal\",\"stop_reason\":\"ExplicitRequest\",\"error\":null}"));
+    }
+
+    #[test]
+    fn recorder_error_cleans_up() {
+        let mut recording = CallRecording::new(1, 2, 7, Some(8), "x.wav");
+        recording.start();
+        assert_eq!(
+            lines(&recording.handle_event(&event("ERecorderError 1 7 DiskFull"))),
+            ["ResourceDelete 7", "ResourceDelete 8"]
+        );
+        assert_eq!(recording.metadata.error.as_deref(), Some("DiskFull"));
+        assert!(recording.handle_event(&event("ERecorderStopped 1 7 ExplicitRequest")).is_empty());
+    }
+}
@@ -0,0 +1,266 @@
+use crate::commands::Command;
+use crate::events::Event;
+use crate::primitives::ResourceId;
+use pyo3::prelude::*;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "relay")?;
+
+    child_module.add_class::<T38Relay>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Audio,
+    T38,
+    Refused,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Audio => "audio",
+            Mode::T38 => "t38",
+            Mode::Refused => "refused",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    Relaying,
+    Clearing,
+    Finished,
+}
+
+/// Media mode and clearing of one leg of the relay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Leg {
+    call: ResourceId,
+    mode: Mode,
+    cleared: bool,
+}
+
+impl Leg {
+    fn new(call: ResourceId) -> Self {
+        Leg {
+            call,
+            mode: Mode::Audio,
+            cleared: false,
+        }
+    }
+}
+
+/// A T.38 relay between two front-end calls.
+///
+/// Like `CallRecording`, the relay plans commands and leaves sending them to the caller:
+/// `start` sends `CallT38Relay`, `handle_event` follows `EModeChangeT38` and
+/// `EModeChangeT38Refused` on each leg and, when either leg clears, clears the other one.
+/// The relay is finished once both legs have cleared. The calls are not deleted.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct T38Relay {
+    a: Leg,
+    b: Leg,
+    state: State,
+    /// Legs that refused the switch to T.38, in the order they did.
+    #[pyo3(get)]
+    refusals: Vec<ResourceId>,
+    /// The leg whose clearing ended the relay.
+    #[pyo3(get)]
+    cleared_by: Option<ResourceId>,
+    #[pyo3(get)]
+    clear_reason: Option<String>,
+}
+
+impl T38Relay {
+    pub fn new(call_a: ResourceId, call_b: ResourceId) -> Self {
+        T38Relay {
+            a: Leg::new(call_a),
+            b: Leg::new(call_b),
+            state: State::Idle,
+            refusals: Vec::new(),
+            cleared_by: None,
+            clear_reason: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Finished
+    }
+
+    /// Both legs have switched to T.38.
+    pub fn is_established(&self) -> bool {
+        self.state == State::Relaying && self.a.mode == Mode::T38 && self.b.mode == Mode::T38
+    }
+
+    /// `audio`, `t38` or `refused`; `None` for a call that is not a leg of the relay.
+    pub fn mode(&self, call: ResourceId) -> Option<&'static str> {
+        self.leg(call).map(|leg| leg.mode.name())
+    }
+
+    fn leg(&self, call: ResourceId) -> Option<&Leg> {
+        [&self.a, &self.b].into_iter().find(|leg| leg.call == call)
+    }
+
+    fn leg_mut(&mut self, call: ResourceId) -> Option<&mut Leg> {
+        [&mut self.a, &mut self.b].into_iter().find(|leg| leg.call == call)
+    }
+
+    pub fn start(&mut self) -> Vec<Command> {
+        if self.state != State::Idle {
+            return Vec::new();
+        }
+        self.state = State::Relaying;
+        vec![Command::call_t38_relay(self.a.call, self.b.call)]
+    }
+
+    /// Clear both legs that are still up.
+    pub fn stop(&mut self) -> Vec<Command> {
+        if !matches!(self.state, State::Idle | State::Relaying) {
+            return Vec::new();
+        }
+        self.state = State::Clearing;
+        [self.a, self.b]
+            .iter()
+            .filter(|leg| !leg.cleared)
+            .map(|leg| Command::call_clear(leg.call, None))
+            .collect()
+    }
+
+    /// Follow an event from the server, returning the commands it calls for.
+    pub fn handle_event(&mut self, event: &Event) -> Vec<Command> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        match event {
+            Event::ModeChangeT38(e) => {
+                if let Some(leg) = self.leg_mut(e.resource_id) {
+                    leg.mode = Mode::T38;
+                }
+                Vec::new()
+            }
+            Event::ModeChangeT38Refused(e) => {
+                if let Some(leg) = self.leg_mut(e.resource_id) {
+                    leg.mode = Mode::Refused;
+                    self.refusals.push(e.resource_id);
+                }
+                Vec::new()
+            }
+            Event::CallCleared(e) => {
+                let Some(leg) = self.leg_mut(e.resource_id) else {
+                    return Vec::new();
+                };
+                leg.cleared = true;
+                if self.cleared_by.is_none() {
+                    self.cleared_by = Some(e.resource_id);
+                    self.clear_reason = Some(e.reason.clone());
+                }
+                if self.a.cleared && self.b.cleared {
+                    self.state = State::Finished;
+                    Vec::new()
+                } else {
+                    self.stop()
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[pymethods]
+impl T38Relay {
+    #[new]
+    fn py_new(call_a: ResourceId, call_b: ResourceId) -> Self {
+        T38Relay::new(call_a, call_b)
+    }
+
+    #[getter]
+    fn call_a(&self) -> ResourceId {
+        self.a.call
+    }
+
+    #[getter]
+    fn call_b(&self) -> ResourceId {
+        self.b.call
+    }
+
+    #[getter(finished)]
+    fn py_finished(&self) -> bool {
+        self.is_finished()
+    }
+
+    #[getter(established)]
+    fn py_established(&self) -> bool {
+        self.is_established()
+    }
+
+    #[pyo3(name = "mode")]
+    fn py_mode(&self, call: ResourceId) -> Option<&'static str> {
+        self.mode(call)
+    }
+
+    #[pyo3(name = "start")]
+    fn py_start(&mut self) -> Vec<Command> {
+        self.start()
+    }
+
+    #[pyo3(name = "stop")]
+    fn py_stop(&mut self) -> Vec<Command> {
+        self.stop()
+    }
+
+    #[pyo3(name = "handle_event")]
+    fn py_handle_event(&mut self, event: Event) -> Vec<Command> {
+        self.handle_event(&event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::testing::lines;
+    use crate::events::parse_event;
+
+    fn feed(relay: &mut T38Relay, line: &str) -> Vec<String> {
+        lines(&relay.handle_event(&parse_event(line).unwrap()))
+    }
+
+    #[test]
+    fn follows_mode_changes_and_clears_other_leg() {
+        let mut relay = T38Relay::new(1, 2);
+        assert_eq!(lines(&relay.start()), ["CallT38Relay 1 2"]);
+        assert!(relay.start().is_empty());
+
+        feed(&mut relay, "EModeChangeT38 1 1");
+        feed(&mut relay, "EModeChangeT38 1 9");
+        assert_eq!((relay.mode(1), relay.mode(2), relay.mode(9)), (Some("t38"), Some("audio"), None));
+        assert!(!relay.is_established());
+        feed(&mut relay, "EModeChangeT38 1 2");
+        assert!(relay.is_established());
+
+        assert_eq!(feed(&mut relay, "ECallCleared 1 2 EndedByRemoteUser"), ["CallClear 1"]);
+        assert!(!relay.is_finished());
+        assert!(feed(&mut relay, "ECallCleared 1 1 EndedByLocalUser").is_empty());
+        assert!(relay.is_finished());
+        assert_eq!((relay.cleared_by, relay.clear_reason.as_deref()), (Some(2), Some("EndedByRemoteUser")));
+    }
+
+    #[test]
+    fn reports_refusals_and_stops() {
+        let mut relay = T38Relay::new(1, 2);
+        relay.start();
+        feed(&mut relay, "EModeChangeT38Refused 1 2");
+        assert_eq!(relay.refusals, [2]);
+        assert_eq!(relay.mode(2), Some("refused"));
+
+        assert_eq!(lines(&relay.stop()), ["CallClear 1", "CallClear 2"]);
+        assert!(relay.stop().is_empty());
+        assert!(feed(&mut relay, "ECallCleared 1 1 EndedByLocalUser").is_empty());
+        feed(&mut relay, "ECallCleared 1 2 EndedByLocalUser");
+        assert!(relay.is_finished());
+    }
+}
@@ -0,0 +1,299 @@
+use crate::commands::{AudioSend, Command};
+use crate::primitives::ResourceId;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "routing")?;
+
+    child_module.add_class::<RoutingGraph>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum RoutingError {
+    #[error("audio is already routed from {0} to {1}")]
+    Duplicate(ResourceId, ResourceId),
+    #[error("no audio route from {0} to {1}")]
+    NotRouted(ResourceId, ResourceId),
+}
+
+impl From<RoutingError> for PyErr {
+    fn from(err: RoutingError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// Audio links set up with `AudioSend` and not yet cancelled, keyed by source and sink.
+///
+/// The graph follows the commands sent to the server: `prepare` checks a command
+/// before it is sent and `apply` records it once it has been.
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct RoutingGraph {
+    routes: BTreeMap<(ResourceId, ResourceId), AudioSend>,
+}
+
+/// Settings of a route that were given in its `AudioSend`, as name and value.
+fn settings(route: &AudioSend) -> Vec<(&'static str, String)> {
+    let mut settings = Vec::new();
+    let mut add = |name, value: Option<String>| {
+        if let Some(value) = value {
+            settings.push((name, value));
+        }
+    };
+    add("source_channel", route.source_channel.map(|v| v.to_string()));
+    add("sink_channel", route.sink_channel.map(|v| v.to_string()));
+    add("volume", route.volume.map(|v| v.to_string()));
+    add("auto_gain", route.auto_gain.map(|v| v.to_string()));
+    add("auto_gain_resolution", route.auto_gain_resolution.map(|v| v.to_string()));
+    add("auto_gain_rise_time", route.auto_gain_rise_time.map(|v| v.to_string()));
+    add("auto_gain_fall_time", route.auto_gain_fall_time.map(|v| v.to_string()));
+    add("auto_gain_kill_time", route.auto_gain_kill_time.map(|v| v.to_string()));
+    settings
+}
+
+impl RoutingGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_routed(&self, source: ResourceId, sink: ResourceId) -> bool {
+        self.routes.contains_key(&(source, sink))
+    }
+
+    /// Source and sink of every route, ordered by source.
+    pub fn links(&self) -> Vec<(ResourceId, ResourceId)> {
+        self.routes.keys().copied().collect()
+    }
+
+    /// Routes from or to a resource.
+    pub fn links_of(&self, resource_id: ResourceId) -> Vec<(ResourceId, ResourceId)> {
+        self.routes
+            .keys()
+            .filter(|(source, sink)| *source == resource_id || *sink == resource_id)
+            .copied()
+            .collect()
+    }
+
+    /// Check a command before it is sent, returning the commands that must go out first.
+    ///
+    /// A second `AudioSend` between the same resources and an `AudioCancel` without a route
+    /// are refused. A `ResourceDelete` is preceded by an `AudioCancel` for each route of the
+    /// resource, so none is left behind pointing at it.
+    pub fn prepare(&self, command: &Command) -> Result<Vec<Command>, RoutingError> {
+        match command {
+            Command::AudioSend(cmd) if self.is_routed(cmd.source_resource_id, cmd.sink_resource_id) => Err(
+                RoutingError::Duplicate(cmd.source_resource_id, cmd.sink_resource_id),
+            ),
+            Command::AudioCancel(cmd) if !self.is_routed(cmd.source_resource_id, cmd.sink_resource_id) => Err(
+                RoutingError::NotRouted(cmd.source_resource_id, cmd.sink_resource_id),
+            ),
+            Command::ResourceDelete(cmd) => Ok(self
+                .links_of(cmd.resource_id)
+                .into_iter()
+                .map(|(source, sink)| Command::audio_cancel(source, sink))
+                .collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Record a command that has been sent; commands other than audio routing and
+    /// `ResourceDelete` are ignored.
+    pub fn apply(&mut self, command: &Command) {
+        match command {
+            Command::AudioSend(cmd) => {
+                self.routes
+                    .insert((cmd.source_resource_id, cmd.sink_resource_id), cmd.clone());
+            }
+            Command::AudioCancel(cmd) => {
+                self.routes.remove(&(cmd.source_resource_id, cmd.sink_resource_id));
+            }
+            Command::ResourceDelete(cmd) => {
+                let id = cmd.resource_id;
+                self.routes.retain(|(source, sink), _| *source != id && *sink != id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Commands moving the route from `source` to `old_sink` over to `new_sink`, keeping
+    /// its channels, volume and AGC settings.
+    ///
+    /// The new route is set up before the old one is cancelled, and both are checked
+    /// before any is returned, so the graph is never left half-way.
+    pub fn reroute(
+        &self,
+        source: ResourceId,
+        old_sink: ResourceId,
+        new_sink: ResourceId,
+    ) -> Result<Vec<Command>, RoutingError> {
+        let route = self
+            .routes
+            .get(&(source, old_sink))
+            .ok_or(RoutingError::NotRouted(source, old_sink))?;
+        if self.is_routed(source, new_sink) {
+            return Err(RoutingError::Duplicate(source, new_sink));
+        }
+        let moved = AudioSend {
+            sink_resource_id: new_sink,
+            ..route.clone()
+        };
+        Ok(vec![Command::AudioSend(moved), Command::audio_cancel(source, old_sink)])
+    }
+
+    /// The graph in Graphviz DOT, with the settings of each route as its edge label.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph audio {\n");
+        for ((source, sink), route) in &self.routes {
+            let label = settings(route)
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(" ");
+            dot += &format!("    \"{}\" -> \"{}\" [label=\"{}\"];\n", source, sink, label);
+        }
+        dot + "}\n"
+    }
+
+    /// The routes as a JSON array of objects with `source`, `sink` and the settings given.
+    pub fn to_json(&self) -> String {
+        let routes = self
+            .routes
+            .iter()
+            .map(|((source, sink), route)| {
+                let mut fields = vec![format!("\"source\":{}", source), format!("\"sink\":{}", sink)];
+                fields.extend(
+                    settings(route)
+                        .into_iter()
+                        .map(|(name, value)| format!("\"{}\":{}", name, value)),
+                );
+                format!("{{{}}}", fields.join(","))
+            })
+            .collect::<Vec<_>>();
+        format!("[{}]", routes.join(","))
+    }
+}
+
+#[pymethods]
+impl RoutingGraph {
+    #[new]
+    fn py_new() -> Self {
+        RoutingGraph::new()
+    }
+
+    #[pyo3(name = "is_routed")]
+    fn py_is_routed(&self, source: ResourceId, sink: ResourceId) -> bool {
+        self.is_routed(source, sink)
+    }
+
+    #[pyo3(name = "links")]
+    fn py_links(&self) -> Vec<(ResourceId, ResourceId)> {
+        self.links()
+    }
+
+    #[pyo3(name = "links_of")]
+    fn py_links_of(&self, resource_id: ResourceId) -> Vec<(ResourceId, ResourceId)> {
+        self.links_of(resource_id)
+    }
+
+    #[pyo3(name = "prepare")]
+    fn py_prepare(&self, command: Command) -> PyResult<Vec<Command>> {
+        Ok(self.prepare(&command)?)
+    }
+
+    #[pyo3(name = "apply")]
+    fn py_apply(&mut self, command: Command) {
+        self.apply(&command)
+    }
+
+    #[pyo3(name = "reroute")]
+    fn py_reroute(&self, source: ResourceId, old_sink: ResourceId, new_sink: ResourceId) -> PyResult<Vec<Command>> {
+        Ok(self.reroute(source, old_sink, new_sink)?)
+    }
+
+    #[pyo3(name = "to_dot")]
+    fn py_to_dot(&self) -> String {
+        self.to_dot()
+    }
+
+    #[pyo3(name = "to_json")]
+    fn py_to_json(&self) -> String {
+        self.to_json()
+    }
+
+    fn __len__(&self) -> usize {
+        self.routes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(source: ResourceId, sink: ResourceId, volume: Option<i16>) -> Command {
+        Command::audio_send(source, sink, None, Some(1), volume, Some(true), None, None, None, None)
+    }
+
+    fn graph(commands: &[Command]) -> RoutingGraph {
+        let mut graph = RoutingGraph::new();
+        for command in commands {
+            assert!(graph.prepare(command).unwrap().is_empty());
+            graph.apply(command);
+        }
+        graph
+    }
+
+    #[test]
+    fn refuses_duplicate_and_unknown_routes() {
+        let graph = graph(&[send(1, 2, None)]);
+        assert_eq!(graph.prepare(&send(1, 2, Some(-3))).err(), Some(RoutingError::Duplicate(1, 2)));
+        assert_eq!(
+            graph.prepare(&Command::audio_cancel(2, 1)).err(),
+            Some(RoutingError::NotRouted(2, 1))
+        );
+        assert!(graph.prepare(&send(2, 1, None)).is_ok());
+    }
+
+    #[test]
+    fn delete_cancels_routes_of_resource() {
+        let mut graph = graph(&[send(1, 2, None), send(2, 1, None), send(3, 4, None)]);
+        let delete = Command::resource_delete(2);
+        let cancels: Vec<String> = graph.prepare(&delete).unwrap().iter().map(|c| c.to_string()).collect();
+        assert_eq!(cancels, ["AudioCancel 1 2", "AudioCancel 2 1"]);
+        graph.apply(&delete);
+        assert_eq!(graph.links(), [(3, 4)]);
+    }
+
+    #[test]
+    fn reroute_keeps_settings() {
+        let mut graph = graph(&[send(1, 2, Some(-6)), send(1, 3, None)]);
+        assert_eq!(graph.reroute(1, 2, 3).err(), Some(RoutingError::Duplicate(1, 3)));
+        assert_eq!(graph.reroute(1, 4, 5).err(), Some(RoutingError::NotRouted(1, 4)));
+
+        let commands = graph.reroute(1, 2, 5).unwrap();
+        assert_eq!(commands[0].to_string(), send(1, 5, Some(-6)).to_string());
+        assert_eq!(commands[1].to_string(), "AudioCancel 1 2");
+        for command in &commands {
+            graph.apply(command);
+        }
+        assert_eq!(graph.links(), [(1, 3), (1, 5)]);
+    }
+
+    #[test]
+    fn export_dot_and_json() {
+        let graph = graph(&[send(1, 2, Some(-6)), send(3, 1, None)]);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph audio {\n    \"1\" -> \"2\" [label=\"sink_channel=1 volume=-6 auto_gain=true\"];\n    \
+             \"3\" -> \"1\" [label=\"sink_channel=1 auto_gain=true\"];\n}\n"
+        );
+        assert_eq!(
+            graph.to_json(),
+            "[{\"source\":1,\"sink\":2,\"sink_channel\":1,\"volume\":-6,\"auto_gain\":true},\
+             {\"source\":3,\"sink\":1,\"sink_channel\":1,\"auto_gain\":true}]"
+        );
+    }
+}
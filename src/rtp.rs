@@ -0,0 +1,538 @@
+use crate::commands::Command;
+use crate::constants::{
+    PayloadType, PayloadType_G711_ALAW_64K, PayloadType_G711_ULAW_64K, PayloadType_LINEAR16_16K,
+    PayloadType_LINEAR16_8K, PayloadType_PCM16, PayloadType_PCM_S8, PayloadType_PCM_U8,
+};
+use crate::primitives::{NetworkAddress, ResourceId};
+use crate::sdp::static_rtp_code;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::borrow::Cow;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "rtp")?;
+
+    child_module.add_class::<RtpEndpoint>()?;
+    child_module.add_class::<RtpPacket>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+const RTP_VERSION: u8 = 2;
+const RTP_HEADER_LEN: usize = 12;
+const RTCP_SR: u8 = 200;
+const RTCP_SDES: u8 = 202;
+const RTCP_BYE: u8 = 203;
+const SDES_CNAME: u8 = 1;
+/// Packetization interval used by `send_audio`.
+const PTIME_MS: u32 = 20;
+/// Attempts at finding a free even/odd port pair when binding to ephemeral ports.
+const BIND_ATTEMPTS: usize = 32;
+/// Seconds between the NTP epoch (1900) and the Unix epoch (1970).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RtpError {
+    #[error("RTP socket error: {0}")]
+    Io(#[from] io::Error),
+    #[error("no free RTP/RTCP port pair")]
+    NoFreePorts,
+    #[error("remote RTP address is not known yet, call set_remote first")]
+    NoRemote,
+    #[error("remote RTP port {0} leaves no port for RTCP, set the control address")]
+    NoRemoteControlPort(u16),
+    #[error("invalid local address {0}")]
+    InvalidAddress(String),
+    #[error("payload type {0} has no static RTP payload code, give a dynamic one")]
+    NoPayloadCode(&'static str),
+    #[error("RTP payload code {0} is out of range (0-127)")]
+    InvalidPayloadCode(u8),
+    #[error("payload type {0} has no fixed sample size, send pre-encoded frames instead")]
+    UnsupportedPayload(&'static str),
+    #[error("malformed RTP packet")]
+    Malformed,
+}
+
+impl From<RtpError> for PyErr {
+    fn from(err: RtpError) -> Self {
+        match err {
+            RtpError::Io(e) => PyIOError::new_err(e.to_string()),
+            other => PyValueError::new_err(other.to_string()),
+        }
+    }
+}
+
+/// A received RTP packet.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtpPacket {
+    #[pyo3(get)]
+    payload_type: u8,
+    #[pyo3(get)]
+    marker: bool,
+    #[pyo3(get)]
+    sequence_number: u16,
+    #[pyo3(get)]
+    timestamp: u32,
+    #[pyo3(get)]
+    ssrc: u32,
+    payload: Vec<u8>,
+}
+
+impl RtpPacket {
+    pub fn parse(data: &[u8]) -> Result<Self, RtpError> {
+        if data.len() < RTP_HEADER_LEN || data[0] >> 6 != RTP_VERSION {
+            return Err(RtpError::Malformed);
+        }
+        let padding = data[0] & 0x20 != 0;
+        let extension = data[0] & 0x10 != 0;
+        let csrc_count = usize::from(data[0] & 0x0F);
+        let mut start = RTP_HEADER_LEN + 4 * csrc_count;
+        if extension {
+            let words = data.get(start + 2..start + 4).ok_or(RtpError::Malformed)?;
+            start += 4 + 4 * usize::from(u16::from_be_bytes([words[0], words[1]]));
+        }
+        let mut end = data.len();
+        if padding {
+            end = end.checked_sub(usize::from(data[end - 1])).ok_or(RtpError::Malformed)?;
+        }
+        let payload = data.get(start..end).ok_or(RtpError::Malformed)?.to_vec();
+        Ok(RtpPacket {
+            payload_type: data[1] & 0x7F,
+            marker: data[1] & 0x80 != 0,
+            sequence_number: u16::from_be_bytes([data[2], data[3]]),
+            timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ssrc: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            payload,
+        })
+    }
+}
+
+#[pymethods]
+impl RtpPacket {
+    #[getter]
+    fn payload(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self.payload)
+    }
+}
+
+/// Bytes per sample for payload types `send_audio` can packetize itself.
+fn bytes_per_sample(payload_type: &PayloadType) -> Option<u32> {
+    const ONE_BYTE: &[PayloadType] = &[
+        PayloadType_G711_ALAW_64K,
+        PayloadType_G711_ULAW_64K,
+        PayloadType_PCM_S8,
+        PayloadType_PCM_U8,
+    ];
+    const TWO_BYTES: &[PayloadType] = &[PayloadType_LINEAR16_8K, PayloadType_LINEAR16_16K, PayloadType_PCM16];
+    if ONE_BYTE.contains(payload_type) {
+        Some(1)
+    } else if TWO_BYTES.contains(payload_type) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+fn local_address(socket: &UdpSocket) -> Result<NetworkAddress, RtpError> {
+    let address = socket.local_addr()?;
+    NetworkAddress::try_from(address).map_err(|_| RtpError::InvalidAddress(address.to_string()))
+}
+
+/// Local RTP/RTCP endpoint to pair with an `RtpChannel` resource.
+///
+/// The endpoint binds an even RTP port and the following RTCP port on `local_ip`,
+/// builds the `RtpChannelStartReceiving`/`RtpChannelStartSending` commands that
+/// point the server at it, and sends and receives packetized audio. The server's
+/// receiving address, reported by `ERtpChannelStartedReceiving`, is passed to
+/// `set_remote` before sending.
+///
+/// Packets carry `payload_code`, by default the static RFC 3551 code of the payload type. A
+/// payload type without one needs a dynamic code, such as the one given to
+/// `GridborgClient.map_payload_type`; the start commands announce a code that differs
+/// from Gridborg's own numbering.
+#[pyclass]
+pub struct RtpEndpoint {
+    #[pyo3(get)]
+    payload_type: PayloadType,
+    #[pyo3(get)]
+    payload_code: Option<u8>,
+    #[pyo3(get)]
+    ssrc: u32,
+    rtp: UdpSocket,
+    rtcp: UdpSocket,
+    remote_data: Option<SocketAddr>,
+    remote_control: Option<SocketAddr>,
+    sequence_number: u16,
+    timestamp: u32,
+    #[pyo3(get)]
+    packets_sent: u32,
+    #[pyo3(get)]
+    octets_sent: u32,
+}
+
+impl RtpEndpoint {
+    /// Bind a port pair on `local_ip`, within `port_range` (inclusive) if given. Packets
+    /// carry `payload_code`, or else the static code of `payload_type`.
+    pub fn bind(
+        local_ip: IpAddr,
+        payload_type: PayloadType,
+        payload_code: Option<u8>,
+        port_range: Option<(u16, u16)>,
+    ) -> Result<Self, RtpError> {
+        if let Some(code) = payload_code.filter(|&code| code > 127) {
+            return Err(RtpError::InvalidPayloadCode(code));
+        }
+        let (rtp, rtcp) = match port_range {
+            Some((first, last)) => (first..last)
+                .filter(|port| port % 2 == 0)
+                .find_map(|port| bind_pair(local_ip, port))
+                .ok_or(RtpError::NoFreePorts)?,
+            None => (0..BIND_ATTEMPTS)
+                .find_map(|_| {
+                    let rtp = UdpSocket::bind((local_ip, 0)).ok()?;
+                    let port = rtp.local_addr().ok()?.port();
+                    if port % 2 != 0 {
+                        return None;
+                    }
+                    let rtcp = UdpSocket::bind((local_ip, port.checked_add(1)?)).ok()?;
+                    Some((rtp, rtcp))
+                })
+                .ok_or(RtpError::NoFreePorts)?,
+        };
+        let port = rtp.local_addr()?.port();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+        let ssrc = nanos ^ std::process::id().rotate_left(16) ^ u32::from(port);
+        Ok(RtpEndpoint {
+            payload_type,
+            payload_code: payload_code.or_else(|| static_rtp_code(&payload_type)),
+            ssrc,
+            rtp,
+            rtcp,
+            remote_data: None,
+            remote_control: None,
+            sequence_number: (ssrc >> 16) as u16,
+            timestamp: ssrc.rotate_left(8),
+            packets_sent: 0,
+            octets_sent: 0,
+        })
+    }
+
+    pub fn data_address(&self) -> Result<NetworkAddress, RtpError> {
+        local_address(&self.rtp)
+    }
+
+    pub fn control_address(&self) -> Result<NetworkAddress, RtpError> {
+        local_address(&self.rtcp)
+    }
+
+    fn payload_code(&self) -> Result<u8, RtpError> {
+        self.payload_code.ok_or(RtpError::NoPayloadCode(self.payload_type.name))
+    }
+
+    /// `command` with the payload code attached when Gridborg numbers the payload type
+    /// differently or not at all.
+    fn with_payload_code(&self, command: Command) -> Command {
+        match self.payload_code {
+            Some(code) if self.payload_type.type_code != Some(code) => command.with_rtp_payload_type(code),
+            _ => command,
+        }
+    }
+
+    /// Send one RTP packet holding `samples` samples worth of encoded `payload`.
+    pub fn send_frame(&mut self, payload: &[u8], samples: u32) -> Result<(), RtpError> {
+        let remote = self.remote_data.ok_or(RtpError::NoRemote)?;
+        let marker = if self.packets_sent == 0 { 0x80 } else { 0 };
+        let mut packet = Vec::with_capacity(RTP_HEADER_LEN + payload.len());
+        packet.push(RTP_VERSION << 6);
+        packet.push(marker | self.payload_code()?);
+        packet.extend_from_slice(&self.sequence_number.to_be_bytes());
+        packet.extend_from_slice(&self.timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(payload);
+        self.rtp.send_to(&packet, remote)?;
+
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        self.timestamp = self.timestamp.wrapping_add(samples);
+        self.packets_sent = self.packets_sent.wrapping_add(1);
+        self.octets_sent = self.octets_sent.wrapping_add(payload.len() as u32);
+        Ok(())
+    }
+
+    /// Split encoded audio into 20 ms packets and send them; returns the number of packets.
+    pub fn send_audio(&mut self, data: &[u8]) -> Result<usize, RtpError> {
+        let sample_size = bytes_per_sample(&self.payload_type)
+            .ok_or(RtpError::UnsupportedPayload(self.payload_type.name))?;
+        let samples_per_packet = u32::from(self.payload_type.sample_rate) * PTIME_MS / 1000;
+        let chunk = (samples_per_packet * sample_size) as usize;
+        let mut sent = 0;
+        for frame in data.chunks(chunk) {
+            self.send_frame(frame, frame.len() as u32 / sample_size)?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Wait up to `timeout` for an RTP packet; `None` when nothing arrived.
+    pub fn receive(&self, timeout: Duration) -> Result<Option<RtpPacket>, RtpError> {
+        let mut buf = [0u8; 2048];
+        self.rtp.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        match self.rtp.recv_from(&mut buf) {
+            Ok((len, _)) => RtpPacket::parse(&buf[..len]).map(Some),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Compound RTCP sender report with an SDES CNAME, as sent by `send_sender_report`.
+    fn sender_report(&self) -> Vec<u8> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let ntp_seconds = (now.as_secs() + NTP_UNIX_OFFSET) as u32;
+        let ntp_fraction = ((u64::from(now.subsec_nanos()) << 32) / 1_000_000_000) as u32;
+
+        let mut packet = vec![RTP_VERSION << 6, RTCP_SR, 0, 6];
+        for word in [self.ssrc, ntp_seconds, ntp_fraction, self.timestamp, self.packets_sent, self.octets_sent] {
+            packet.extend_from_slice(&word.to_be_bytes());
+        }
+
+        let cname = format!("gridborg-rs@{}", self.rtp.local_addr().map(|a| a.ip().to_string()).unwrap_or_default());
+        let mut chunk = self.ssrc.to_be_bytes().to_vec();
+        chunk.extend_from_slice(&[SDES_CNAME, cname.len() as u8]);
+        chunk.extend_from_slice(cname.as_bytes());
+        // item list ends with a null octet, chunk padded to a 32-bit boundary
+        chunk.resize((chunk.len() + 4) / 4 * 4, 0);
+        let words = (chunk.len() / 4) as u16;
+        packet.extend_from_slice(&[(RTP_VERSION << 6) | 1, RTCP_SDES]);
+        packet.extend_from_slice(&words.to_be_bytes());
+        packet.extend_from_slice(&chunk);
+        packet
+    }
+
+    fn send_rtcp(&self, packet: &[u8]) -> Result<(), RtpError> {
+        let remote = match (self.remote_control, self.remote_data) {
+            (Some(control), _) => control,
+            (None, Some(data)) => {
+                let port = data.port().checked_add(1).ok_or(RtpError::NoRemoteControlPort(data.port()))?;
+                SocketAddr::new(data.ip(), port)
+            }
+            (None, None) => return Err(RtpError::NoRemote),
+        };
+        self.rtcp.send_to(packet, remote)?;
+        Ok(())
+    }
+
+    pub fn send_sender_report(&self) -> Result<(), RtpError> {
+        self.send_rtcp(&self.sender_report())
+    }
+
+    pub fn send_bye(&self) -> Result<(), RtpError> {
+        let mut packet = vec![(RTP_VERSION << 6) | 1, RTCP_BYE, 0, 1];
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        self.send_rtcp(&packet)
+    }
+}
+
+fn bind_pair(local_ip: IpAddr, port: u16) -> Option<(UdpSocket, UdpSocket)> {
+    let rtp = UdpSocket::bind((local_ip, port)).ok()?;
+    let rtcp = UdpSocket::bind((local_ip, port.checked_add(1)?)).ok()?;
+    Some((rtp, rtcp))
+}
+
+#[pymethods]
+impl RtpEndpoint {
+    #[new]
+    #[pyo3(signature = (local_ip, payload_type, port_range=None, payload_code=None))]
+    fn py_new(
+        local_ip: String,
+        payload_type: PayloadType,
+        port_range: Option<(u16, u16)>,
+        payload_code: Option<u8>,
+    ) -> PyResult<Self> {
+        let local_ip = local_ip
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("Invalid IP address: {}", local_ip)))?;
+        Ok(RtpEndpoint::bind(local_ip, payload_type, payload_code, port_range)?)
+    }
+
+    #[getter(data_address)]
+    fn py_data_address(&self) -> PyResult<NetworkAddress> {
+        Ok(self.data_address()?)
+    }
+
+    #[getter(control_address)]
+    fn py_control_address(&self) -> PyResult<NetworkAddress> {
+        Ok(self.control_address()?)
+    }
+
+    /// Where to send media, e.g. the addresses from `ERtpChannelStartedReceiving`.
+    /// Without a control address RTCP goes to the port after the data port.
+    #[pyo3(signature = (data_address, control_address=None))]
+    pub fn set_remote(&mut self, data_address: NetworkAddress, control_address: Option<NetworkAddress>) {
+        self.remote_data = Some(data_address.into());
+        self.remote_control = control_address.map(SocketAddr::from);
+    }
+
+    /// `RtpChannelStartReceiving` for media we send, with our RTCP address as the sender's.
+    pub fn start_receiving_command(&self, resource_id: ResourceId) -> PyResult<Command> {
+        Ok(self.with_payload_code(Command::rtp_channel_start_receiving(
+            resource_id,
+            Some(self.control_address()?),
+            None,
+            None,
+            Some(self.payload_type),
+            None,
+            None,
+            None,
+            None,
+        )))
+    }
+
+    /// `RtpChannelStartSending` directing the server's media to this endpoint.
+    pub fn start_sending_command(&self, resource_id: ResourceId) -> PyResult<Command> {
+        Ok(self.with_payload_code(Command::rtp_channel_start_sending(
+            resource_id,
+            self.data_address()?,
+            Some(self.control_address()?),
+            None,
+            None,
+            Some(self.payload_type),
+            None,
+            None,
+        )))
+    }
+
+    #[pyo3(name = "send_frame")]
+    fn py_send_frame(&mut self, payload: &[u8], samples: u32) -> PyResult<()> {
+        Ok(self.send_frame(payload, samples)?)
+    }
+
+    #[pyo3(name = "send_audio")]
+    fn py_send_audio(&mut self, data: &[u8]) -> PyResult<usize> {
+        Ok(self.send_audio(data)?)
+    }
+
+    #[pyo3(name = "receive", signature = (timeout_ms=1000))]
+    fn py_receive(&self, py: Python<'_>, timeout_ms: u64) -> PyResult<Option<RtpPacket>> {
+        Ok(py.allow_threads(|| self.receive(Duration::from_millis(timeout_ms)))?)
+    }
+
+    #[pyo3(name = "send_sender_report")]
+    fn py_send_sender_report(&self) -> PyResult<()> {
+        Ok(self.send_sender_report()?)
+    }
+
+    #[pyo3(name = "send_bye")]
+    fn py_send_bye(&self) -> PyResult<()> {
+        Ok(self.send_bye()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn binds_even_port_pair() {
+        let endpoint = RtpEndpoint::bind(LOCALHOST, PayloadType_G711_ALAW_64K, None, None).unwrap();
+        let data = endpoint.data_address().unwrap().port();
+        assert_eq!(data % 2, 0);
+        assert_eq!(endpoint.control_address().unwrap().port(), data + 1);
+    }
+
+    #[test]
+    fn start_commands_use_local_addresses() {
+        let endpoint = RtpEndpoint::bind(LOCALHOST, PayloadType_G711_ULAW_64K, None, None).unwrap();
+        let port = endpoint.data_address().unwrap().port();
+        assert_eq!(
+            endpoint.start_receiving_command(5).unwrap().to_string(),
+            format!(
+                "RtpChannelStartReceiving 5 SenderControlAddress=127.0.0.1:{} PayloadType=G.711-uLaw-64k RtpPayloadType=0",
+                port + 1
+            )
+        );
+        assert_eq!(
+            endpoint.start_sending_command(5).unwrap().to_string(),
+            format!(
+                "RtpChannelStartSending 5 127.0.0.1:{} ReceiverControlAddress=127.0.0.1:{} PayloadType=G.711-uLaw-64k RtpPayloadType=0",
+                port,
+                port + 1
+            )
+        );
+    }
+
+    #[test]
+    fn packetizes_and_receives_audio() {
+        let mut sender = RtpEndpoint::bind(LOCALHOST, PayloadType_G711_ALAW_64K, None, None).unwrap();
+        let receiver = RtpEndpoint::bind(LOCALHOST, PayloadType_G711_ALAW_64K, None, None).unwrap();
+        assert!(matches!(sender.send_audio(&[0xD5; 10]), Err(RtpError::NoRemote)));
+
+        sender.set_remote(receiver.data_address().unwrap(), None);
+        // 50 ms of A-law: two full 20 ms packets and a 10 ms tail
+        assert_eq!(sender.send_audio(&[0xD5; 400]).unwrap(), 3);
+
+        let packets: Vec<_> = (0..3)
+            .map(|_| receiver.receive(Duration::from_secs(1)).unwrap().unwrap())
+            .collect();
+        assert!(packets[0].marker && !packets[1].marker);
+        // RFC 3551 PCMA, not Gridborg's type code 0
+        assert_eq!(packets[0].payload_type, 8);
+        assert_eq!(packets[0].ssrc, sender.ssrc);
+        assert_eq!(packets[1].sequence_number, packets[0].sequence_number.wrapping_add(1));
+        assert_eq!(packets[1].timestamp, packets[0].timestamp.wrapping_add(160));
+        assert_eq!(packets.iter().map(|p| p.payload.len()).collect::<Vec<_>>(), [160, 160, 80]);
+        assert!(receiver.receive(Duration::from_millis(10)).unwrap().is_none());
+    }
+
+    #[test]
+    fn dynamic_payload_types_need_a_code() {
+        let mut endpoint = RtpEndpoint::bind(LOCALHOST, PayloadType_LINEAR16_16K, None, None).unwrap();
+        let receiver = RtpEndpoint::bind(LOCALHOST, PayloadType_LINEAR16_16K, Some(101), None).unwrap();
+        endpoint.set_remote(receiver.data_address().unwrap(), None);
+        assert!(matches!(endpoint.send_frame(&[0; 4], 2), Err(RtpError::NoPayloadCode("Linear-16-Mono-16kHz"))));
+        assert!(matches!(
+            RtpEndpoint::bind(LOCALHOST, PayloadType_LINEAR16_16K, Some(128), None),
+            Err(RtpError::InvalidPayloadCode(128))
+        ));
+
+        let mut sender = RtpEndpoint::bind(LOCALHOST, PayloadType_LINEAR16_16K, Some(101), None).unwrap();
+        sender.set_remote(receiver.data_address().unwrap(), None);
+        sender.send_frame(&[0; 4], 2).unwrap();
+        assert_eq!(receiver.receive(Duration::from_secs(1)).unwrap().unwrap().payload_type, 101);
+        assert!(sender.start_sending_command(5).unwrap().to_string().ends_with(" RtpPayloadType=101"));
+    }
+
+    #[test]
+    fn rtcp_needs_a_port_after_the_remote_data_port() {
+        let mut endpoint = RtpEndpoint::bind(LOCALHOST, PayloadType_G711_ALAW_64K, None, None).unwrap();
+        endpoint.set_remote("127.0.0.1:65535".parse().unwrap(), None);
+        assert!(matches!(endpoint.send_bye(), Err(RtpError::NoRemoteControlPort(65535))));
+    }
+
+    #[test]
+    fn sender_report_layout() {
+        let mut endpoint = RtpEndpoint::bind(LOCALHOST, PayloadType_G711_ALAW_64K, None, None).unwrap();
+        endpoint.packets_sent = 3;
+        endpoint.octets_sent = 480;
+        let report = endpoint.sender_report();
+        assert_eq!(report[1], RTCP_SR);
+        assert_eq!(u32::from_be_bytes(report[4..8].try_into().unwrap()), endpoint.ssrc);
+        assert_eq!(u32::from_be_bytes(report[20..24].try_into().unwrap()), 3);
+        assert_eq!(u32::from_be_bytes(report[24..28].try_into().unwrap()), 480);
+        assert_eq!(report[29], RTCP_SDES);
+        assert_eq!(report.len() % 4, 0);
+        let sdes_words = u16::from_be_bytes([report[30], report[31]]) as usize;
+        assert_eq!(report.len(), 28 + 4 + 4 * sdes_words);
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        assert!(matches!(RtpPacket::parse(&[0x80, 0]), Err(RtpError::Malformed)));
+        assert!(matches!(RtpPacket::parse(&[0; 12]), Err(RtpError::Malformed)));
+    }
+}
@@ -0,0 +1,554 @@
+use crate::commands::Command;
+use crate::constants::{PayloadType, PayloadTypeMap, ALL_PAYLOAD_TYPES};
+use crate::primitives::{unix_time, NetworkAddress, ResourceId};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "sdp")?;
+
+    child_module.add_class::<SessionDescription>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+const TELEPHONE_EVENT: &str = "telephone-event";
+const TELEPHONE_EVENT_RATE: u32 = 8000;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SdpError {
+    #[error("malformed SDP line '{0}'")]
+    Malformed(String),
+    #[error("SDP has no {0} line")]
+    Missing(&'static str),
+    #[error("SDP has no RTP/AVP audio stream")]
+    NoAudio,
+    #[error("none of the offered codecs is supported")]
+    NoCommonCodec,
+    #[error("payload type {0} has no static RTP payload code and none is mapped")]
+    NoPayloadCode(&'static str),
+}
+
+impl From<SdpError> for PyErr {
+    fn from(err: SdpError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// SDP encoding name of a Gridborg payload type, as used in `a=rtpmap`.
+pub fn encoding_name(payload_type: &PayloadType) -> &'static str {
+    let name = payload_type.name;
+    if name.starts_with("G.711-ALaw") {
+        "PCMA"
+    } else if name.starts_with("G.711-uLaw") {
+        "PCMU"
+    } else if name.starts_with("G.729") {
+        "G729"
+    } else if name.starts_with("G.723.1") {
+        "G723"
+    } else if name.starts_with("Linear-16") {
+        "L16"
+    } else if name.starts_with("iLBC") {
+        "iLBC"
+    } else if name.starts_with("Speex") {
+        "speex"
+    } else {
+        match name {
+            "GSM-06.10" => "GSM",
+            "G.728" => "G728",
+            "G.726-40k" => "G726-40",
+            "G.726-32k" => "G726-32",
+            "G.726-24k" => "G726-24",
+            "G.726-16k" => "G726-16",
+            "LPC-10" => "LPC",
+            other => other,
+        }
+    }
+}
+
+/// Static RTP payload code of a payload type as assigned by RFC 3551, used in SDP.
+///
+/// Gridborg numbers G.711 A-law 0 and μ-law 8, the other way round from RFC 3551, so its
+/// `type_code` stays internal to the control protocol.
+pub fn static_rtp_code(payload_type: &PayloadType) -> Option<u8> {
+    match encoding_name(payload_type) {
+        "PCMU" => Some(0),
+        "PCMA" => Some(8),
+        _ => payload_type.type_code,
+    }
+}
+
+/// The payload type an `a=rtpmap` entry (or a bare static payload code) stands for.
+fn payload_type_for(code: u8, rtpmap: Option<(&str, u32)>) -> Option<PayloadType> {
+    match rtpmap {
+        Some((encoding, rate)) => ALL_PAYLOAD_TYPES.iter().copied().find(|pt| {
+            encoding_name(pt).eq_ignore_ascii_case(encoding) && u32::from(pt.sample_rate) == rate
+        }),
+        None => ALL_PAYLOAD_TYPES.iter().copied().find(|pt| static_rtp_code(pt) == Some(code)),
+    }
+}
+
+/// A session description with a single RTP audio stream.
+///
+/// `control_address` is carried in an `a=rtcp` attribute; without it the RTCP port
+/// is the one after the media port.
+#[pyclass(str)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionDescription {
+    #[pyo3(get)]
+    session_id: u64,
+    #[pyo3(get)]
+    session_version: u64,
+    #[pyo3(get)]
+    address: NetworkAddress,
+    #[pyo3(get)]
+    control_address: Option<NetworkAddress>,
+    /// Payload types in order of preference, each with the RTP code used in this session.
+    payload_types: Vec<(u8, PayloadType)>,
+    #[pyo3(get)]
+    rfc2833_payload_type: Option<u8>,
+    #[pyo3(get)]
+    ptime: Option<u32>,
+}
+
+impl SessionDescription {
+    /// Payload types are offered with their RFC 3551 static code, or without one with the
+    /// dynamic code `payload_type_map` assigns them.
+    pub fn offer(
+        address: NetworkAddress,
+        control_address: Option<NetworkAddress>,
+        payload_types: &[PayloadType],
+        payload_type_map: &PayloadTypeMap,
+        rfc2833_payload_type: Option<u8>,
+        ptime: Option<u32>,
+    ) -> Result<Self, SdpError> {
+        let payload_types = payload_types
+            .iter()
+            .map(|pt| {
+                static_rtp_code(pt)
+                    .or_else(|| payload_type_map.dynamic_code_for(pt))
+                    .map(|code| (code, *pt))
+                    .ok_or(SdpError::NoPayloadCode(pt.name))
+            })
+            .collect::<Result<_, _>>()?;
+        let now = unix_time();
+        Ok(SessionDescription {
+            session_id: now,
+            session_version: now,
+            address,
+            control_address,
+            payload_types,
+            rfc2833_payload_type,
+            ptime,
+        })
+    }
+
+    /// Answer this offer from `address`, keeping the first offered codec in `supported`.
+    pub fn answer_with(
+        &self,
+        address: NetworkAddress,
+        control_address: Option<NetworkAddress>,
+        supported: &[PayloadType],
+    ) -> Result<Self, SdpError> {
+        let chosen = self
+            .payload_types
+            .iter()
+            .find(|(_, pt)| supported.contains(pt))
+            .ok_or(SdpError::NoCommonCodec)?;
+        let mut answer = SessionDescription::offer(
+            address,
+            control_address,
+            &[],
+            &PayloadTypeMap::default(),
+            self.rfc2833_payload_type,
+            self.ptime,
+        )?;
+        answer.payload_types.push(*chosen);
+        Ok(answer)
+    }
+
+    /// Parse the first RTP/AVP audio stream of an SDP body.
+    pub fn parse(text: &str) -> Result<Self, SdpError> {
+        let mut origin = None;
+        let mut session_ip = None;
+        let mut media_ip = None;
+        let mut port = None;
+        let mut codes: Vec<u8> = Vec::new();
+        let mut rtpmaps: Vec<(u8, String, u32)> = Vec::new();
+        let mut rtcp = None;
+        let mut ptime = None;
+        let mut in_audio = false;
+        let mut seen_media = false;
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (kind, value) = line.split_once('=').ok_or_else(|| SdpError::Malformed(line.into()))?;
+            let malformed = || SdpError::Malformed(line.to_string());
+            match kind {
+                "o" => {
+                    let fields: Vec<&str> = value.split_whitespace().collect();
+                    let id = fields.get(1).and_then(|v| v.parse().ok()).ok_or_else(malformed)?;
+                    let version = fields.get(2).and_then(|v| v.parse().ok()).ok_or_else(malformed)?;
+                    origin = Some((id, version));
+                }
+                "c" => {
+                    let ip = value
+                        .split_whitespace()
+                        .nth(2)
+                        .and_then(|a| a.split('/').next())
+                        .and_then(|a| a.parse::<IpAddr>().ok())
+                        .ok_or_else(malformed)?;
+                    match (seen_media, in_audio) {
+                        (false, _) => session_ip = Some(ip),
+                        (true, true) => media_ip = Some(ip),
+                        (true, false) => {}
+                    }
+                }
+                "m" => {
+                    // only the first audio stream is used
+                    if port.is_some() {
+                        in_audio = false;
+                        seen_media = true;
+                        continue;
+                    }
+                    seen_media = true;
+                    let fields: Vec<&str> = value.split_whitespace().collect();
+                    in_audio = fields.first() == Some(&"audio") && fields.get(2) == Some(&"RTP/AVP");
+                    if in_audio {
+                        port = Some(fields.get(1).and_then(|p| p.parse::<u16>().ok()).ok_or_else(malformed)?);
+                        codes = fields[3..].iter().map(|c| c.parse()).collect::<Result<_, _>>().map_err(|_| malformed())?;
+                    }
+                }
+                "a" if in_audio => {
+                    let (name, attr) = value.split_once(':').unwrap_or((value, ""));
+                    match name {
+                        "rtpmap" => {
+                            let (code, encoding) = attr.split_once(' ').ok_or_else(malformed)?;
+                            let mut parts = encoding.split('/');
+                            let encoding = parts.next().unwrap_or_default().to_string();
+                            let rate = parts.next().and_then(|r| r.parse().ok()).ok_or_else(malformed)?;
+                            rtpmaps.push((code.parse().map_err(|_| malformed())?, encoding, rate));
+                        }
+                        "rtcp" => {
+                            let mut fields = attr.split_whitespace();
+                            let port: u16 = fields.next().and_then(|p| p.parse().ok()).ok_or_else(malformed)?;
+                            let ip = fields.nth(2).and_then(|a| a.parse::<IpAddr>().ok());
+                            rtcp = Some((port, ip));
+                        }
+                        "ptime" => ptime = Some(attr.parse().map_err(|_| malformed())?),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (session_id, session_version) = origin.ok_or(SdpError::Missing("o="))?;
+        let port = port.ok_or(SdpError::NoAudio)?;
+        let ip = media_ip.or(session_ip).ok_or(SdpError::Missing("c="))?;
+        let address = NetworkAddress::new(ip, port).ok_or(SdpError::NoAudio)?;
+        let control_address = rtcp.and_then(|(port, rtcp_ip)| NetworkAddress::new(rtcp_ip.unwrap_or(ip), port));
+
+        let mut payload_types = Vec::new();
+        let mut rfc2833_payload_type = None;
+        for code in codes {
+            let rtpmap = rtpmaps
+                .iter()
+                .find(|(c, ..)| *c == code)
+                .map(|(_, encoding, rate)| (encoding.as_str(), *rate));
+            match rtpmap {
+                Some((encoding, _)) if encoding.eq_ignore_ascii_case(TELEPHONE_EVENT) => {
+                    rfc2833_payload_type = Some(code)
+                }
+                _ => payload_types.extend(payload_type_for(code, rtpmap).map(|pt| (code, pt))),
+            }
+        }
+
+        Ok(SessionDescription {
+            session_id,
+            session_version,
+            address,
+            control_address,
+            payload_types,
+            rfc2833_payload_type,
+            ptime,
+        })
+    }
+}
+
+impl fmt::Display for SessionDescription {
+    /// Render the description as SDP, with CRLF line endings.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ip = self.address.ip();
+        let family = if ip.is_ipv4() { "IP4" } else { "IP6" };
+        write!(f, "v=0\r\n")?;
+        write!(f, "o=- {} {} IN {} {}\r\n", self.session_id, self.session_version, family, ip)?;
+        write!(f, "s=-\r\n")?;
+        write!(f, "c=IN {} {}\r\n", family, ip)?;
+        write!(f, "t=0 0\r\n")?;
+        write!(f, "m=audio {} RTP/AVP", self.address.port())?;
+        for (code, _) in &self.payload_types {
+            write!(f, " {}", code)?;
+        }
+        if let Some(code) = self.rfc2833_payload_type {
+            write!(f, " {}", code)?;
+        }
+        write!(f, "\r\n")?;
+        for (code, pt) in &self.payload_types {
+            write!(f, "a=rtpmap:{} {}/{}\r\n", code, encoding_name(pt), pt.sample_rate)?;
+        }
+        if let Some(code) = self.rfc2833_payload_type {
+            write!(f, "a=rtpmap:{} {}/{}\r\n", code, TELEPHONE_EVENT, TELEPHONE_EVENT_RATE)?;
+            write!(f, "a=fmtp:{} 0-15\r\n", code)?;
+        }
+        if let Some(control) = self.control_address {
+            let control_ip = control.ip();
+            let control_family = if control_ip.is_ipv4() { "IP4" } else { "IP6" };
+            write!(f, "a=rtcp:{} IN {} {}\r\n", control.port(), control_family, control_ip)?;
+        }
+        if let Some(ptime) = self.ptime {
+            write!(f, "a=ptime:{}\r\n", ptime)?;
+        }
+        write!(f, "a=sendrecv\r\n")
+    }
+}
+
+impl FromStr for SessionDescription {
+    type Err = SdpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SessionDescription::parse(s)
+    }
+}
+
+#[pymethods]
+impl SessionDescription {
+    /// An SDP offer for media received at `address`; codecs are listed in order of preference.
+    /// Codecs without a static RTP code take theirs from `payload_type_map`, usually the
+    /// session's `payload_types`.
+    #[staticmethod]
+    #[pyo3(name = "offer", signature = (address, payload_types, rfc2833_payload_type=None, control_address=None, ptime=Some(20), payload_type_map=None))]
+    fn py_offer(
+        address: NetworkAddress,
+        payload_types: Vec<PayloadType>,
+        rfc2833_payload_type: Option<u8>,
+        control_address: Option<NetworkAddress>,
+        ptime: Option<u32>,
+        payload_type_map: Option<PayloadTypeMap>,
+    ) -> PyResult<Self> {
+        Ok(SessionDescription::offer(
+            address,
+            control_address,
+            &payload_types,
+            &payload_type_map.unwrap_or_default(),
+            rfc2833_payload_type,
+            ptime,
+        )?)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "parse")]
+    fn py_parse(text: &str) -> PyResult<Self> {
+        Ok(SessionDescription::parse(text)?)
+    }
+
+    /// An answer to this offer, choosing the first offered codec found in `supported`.
+    #[pyo3(signature = (address, supported, control_address=None))]
+    fn answer(
+        &self,
+        address: NetworkAddress,
+        supported: Vec<PayloadType>,
+        control_address: Option<NetworkAddress>,
+    ) -> PyResult<Self> {
+        Ok(self.answer_with(address, control_address, &supported)?)
+    }
+
+    /// Dynamic payload codes of this description, to register with the session.
+    pub fn payload_type_map(&self) -> PayloadTypeMap {
+        let mut map = PayloadTypeMap::default();
+        for (code, pt) in &self.payload_types {
+            // static codes are rejected by the map and need no registration
+            let _ = map.insert(*code, *pt);
+        }
+        map
+    }
+
+    #[getter]
+    fn payload_types(&self) -> Vec<PayloadType> {
+        self.payload_types.iter().map(|(_, pt)| *pt).collect()
+    }
+
+    /// `RtpChannelStartSending` that sends to the media address of this (remote) description
+    /// with its preferred codec, announcing the codec's RTP code where it differs from
+    /// Gridborg's own numbering, as for dynamic codes and G.711.
+    pub fn start_sending_command(&self, resource_id: ResourceId) -> PyResult<Command> {
+        let (code, payload_type) = self.payload_types.first().ok_or(SdpError::NoCommonCodec)?;
+        let command = Command::rtp_channel_start_sending(
+            resource_id,
+            self.address,
+            self.control_address,
+            None,
+            None,
+            Some(*payload_type),
+            self.rfc2833_payload_type,
+            None,
+        );
+        Ok(match payload_type.type_code == Some(*code) {
+            true => command,
+            false => command.with_rtp_payload_type(*code),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{
+        PayloadType_G711_ALAW_64K, PayloadType_G711_ULAW_64K, PayloadType_G726_32K, PayloadType_G729, PayloadType_GSM_0610,
+        PayloadType_ILBC_13K3, PayloadType_SPEEX_8K,
+    };
+
+    fn address(text: &str) -> NetworkAddress {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn offer_lists_codecs_and_telephone_events() {
+        let mut offer = SessionDescription::offer(
+            address("192.0.2.10:40000"),
+            None,
+            &[PayloadType_G711_ALAW_64K, PayloadType_G711_ULAW_64K, PayloadType_G729],
+            &PayloadTypeMap::default(),
+            Some(101),
+            Some(20),
+        )
+        .unwrap();
+        offer.session_id = 42;
+        offer.session_version = 1;
+        assert_eq!(
+            offer.to_string(),
+            "v=0\r\n\
+             o=- 42 1 IN IP4 192.0.2.10\r\n\
+             s=-\r\n\
+             c=IN IP4 192.0.2.10\r\n\
+             t=0 0\r\n\
+             m=audio 40000 RTP/AVP 8 0 18 101\r\n\
+             a=rtpmap:8 PCMA/8000\r\n\
+             a=rtpmap:0 PCMU/8000\r\n\
+             a=rtpmap:18 G729/8000\r\n\
+             a=rtpmap:101 telephone-event/8000\r\n\
+             a=fmtp:101 0-15\r\n\
+             a=ptime:20\r\n\
+             a=sendrecv\r\n"
+        );
+
+        assert_eq!(
+            SessionDescription::offer(
+                address("192.0.2.10:40000"),
+                None,
+                &[PayloadType_SPEEX_8K],
+                &PayloadTypeMap::default(),
+                None,
+                None
+            ),
+            Err(SdpError::NoPayloadCode("SpeexIETFNarrow-8k"))
+        );
+    }
+
+    #[test]
+    fn offer_round_trips_through_parse() {
+        let offer = SessionDescription::offer(
+            address("[2001:db8::5]:30000"),
+            Some(address("[2001:db8::5]:30011")),
+            &[PayloadType_GSM_0610, PayloadType_G711_ULAW_64K],
+            &PayloadTypeMap::default(),
+            Some(96),
+            None,
+        )
+        .unwrap();
+        assert_eq!(SessionDescription::parse(&offer.to_string()).unwrap(), offer);
+    }
+
+    #[test]
+    fn parse_remote_offer_and_build_commands() {
+        let remote = "v=0\n\
+                      o=proxy 2890844526 2890842807 IN IP4 198.51.100.1\n\
+                      s=call\n\
+                      c=IN IP4 198.51.100.1\n\
+                      t=0 0\n\
+                      m=audio 49170 RTP/AVP 0 97 8 101\n\
+                      c=IN IP4 198.51.100.7\n\
+                      a=rtpmap:97 iLBC/8000\n\
+                      a=rtpmap:101 telephone-event/8000\n\
+                      a=fmtp:101 0-16\n\
+                      a=rtcp:49175\n\
+                      m=video 51372 RTP/AVP 31\n\
+                      c=IN IP4 198.51.100.9\n";
+        let offer: SessionDescription = remote.parse().unwrap();
+        assert_eq!(offer.address, address("198.51.100.7:49170"));
+        assert_eq!(offer.control_address, Some(address("198.51.100.7:49175")));
+        assert_eq!(offer.rfc2833_payload_type, Some(101));
+        // bare static codes follow RFC 3551, iLBC is matched through its rtpmap
+        assert_eq!(
+            offer.payload_types(),
+            [PayloadType_G711_ULAW_64K, PayloadType_ILBC_13K3, PayloadType_G711_ALAW_64K]
+        );
+        assert_eq!(offer.payload_type_map().lookup(97), Some(PayloadType_ILBC_13K3));
+
+        // Gridborg numbers μ-law 8, so the RFC code is announced
+        assert_eq!(
+            offer.start_sending_command(4).unwrap().to_string(),
+            "RtpChannelStartSending 4 198.51.100.7:49170 ReceiverControlAddress=198.51.100.7:49175 \
+             PayloadType=G.711-uLaw-64k RtpPayloadType=0 RFC2833PayloadType=101"
+        );
+
+        let answer = offer
+            .answer_with(address("192.0.2.10:40000"), None, &[PayloadType_G711_ULAW_64K])
+            .unwrap();
+        assert_eq!(answer.payload_types(), [PayloadType_G711_ULAW_64K]);
+        assert!(answer.to_string().contains("m=audio 40000 RTP/AVP 0 101\r\na=rtpmap:0 PCMU/8000\r\n"));
+        assert_eq!(
+            offer.answer_with(address("192.0.2.10:40000"), None, &[PayloadType_G729]),
+            Err(SdpError::NoCommonCodec)
+        );
+    }
+
+    #[test]
+    fn offer_and_start_dynamic_codec() {
+        let mut map = PayloadTypeMap::default();
+        map.insert(98, PayloadType_G726_32K).unwrap();
+        let offer = SessionDescription::offer(
+            address("192.0.2.10:40000"),
+            None,
+            &[PayloadType_G726_32K, PayloadType_G711_ALAW_64K],
+            &map,
+            None,
+            None,
+        )
+        .unwrap();
+        let text = offer.to_string();
+        assert!(text.contains("m=audio 40000 RTP/AVP 98 8\r\n"));
+        assert!(text.contains("a=rtpmap:98 G726-32/8000\r\n"));
+
+        // the remote side parses the offer and starts sending with the negotiated code
+        let remote = SessionDescription::parse(&text).unwrap();
+        assert_eq!(remote.payload_types()[0], PayloadType_G726_32K);
+        assert_eq!(remote.payload_type_map().dynamic_code_for(&PayloadType_G726_32K), Some(98));
+        assert_eq!(
+            remote.start_sending_command(3).unwrap().to_string(),
+            "RtpChannelStartSending 3 192.0.2.10:40000 PayloadType=G.726-32k RtpPayloadType=98"
+        );
+    }
+
+    #[test]
+    fn parse_rejects_incomplete_sdp() {
+        assert_eq!(SessionDescription::parse("v=0\r\no=- 1 1 IN IP4 192.0.2.1\r\n"), Err(SdpError::NoAudio));
+        assert_eq!(
+            SessionDescription::parse("v=0\r\no=- 1 1 IN IP4 192.0.2.1\r\nm=audio 4000 RTP/AVP 0\r\n"),
+            Err(SdpError::Missing("c="))
+        );
+        assert!(matches!(SessionDescription::parse("garbage"), Err(SdpError::Malformed(_))));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::primitives::ResourceId;
+use pyo3::exceptions::{PyTimeoutError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "statistics")?;
+
+    child_module.add_class::<RtpStatistics>()?;
+    child_module.add_class::<RtpThresholds>()?;
+    child_module.add_class::<RtpAlert>()?;
+    child_module.add_class::<RtpMonitor>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum StatisticsError {
+    #[error("bad value for {key}: '{value}'")]
+    BadValue { key: String, value: String },
+    #[error("reply carries no RTP statistics: '{0}'")]
+    NoStatistics(String),
+    #[error("reply carries RTP statistics of another resource than {expected}: '{line}'")]
+    OtherResource { expected: ResourceId, line: String },
+    #[error("no reply to GetRtpStatistics within {0} ms")]
+    Timeout(u64),
+}
+
+impl From<StatisticsError> for PyErr {
+    fn from(err: StatisticsError) -> Self {
+        match err {
+            StatisticsError::Timeout(_) => PyTimeoutError::new_err(err.to_string()),
+            other => PyValueError::new_err(other.to_string()),
+        }
+    }
+}
+
+/// Counters of an `RtpChannel` resource, as reported in reply to `GetRtpStatistics`.
+///
+/// Jitter and round-trip time are in milliseconds. Keys the parser does not know
+/// are kept, as reported, in `extra`.
+#[pyclass]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RtpStatistics {
+    #[pyo3(get)]
+    resource_id: ResourceId,
+    #[pyo3(get)]
+    packets_sent: u64,
+    #[pyo3(get)]
+    octets_sent: u64,
+    #[pyo3(get)]
+    packets_received: u64,
+    #[pyo3(get)]
+    octets_received: u64,
+    #[pyo3(get)]
+    packets_lost: u64,
+    #[pyo3(get)]
+    jitter: Option<u32>,
+    #[pyo3(get)]
+    round_trip_time: Option<u32>,
+    #[pyo3(get)]
+    codec: Option<String>,
+    #[pyo3(get)]
+    extra: HashMap<String, String>,
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, StatisticsError> {
+    value.parse().map_err(|_| StatisticsError::BadValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+impl RtpStatistics {
+    /// Parse a reply line of the form `RtpStatistics <resource_id> Key=Value ... [COMMANDTAG=n]`.
+    ///
+    /// The verb and keys are matched case-insensitively, as in event options. Any other
+    /// reply, such as an `Error` carrying keys, is refused, as is one for another resource.
+    pub fn parse(resource_id: ResourceId, line: &str) -> Result<Self, StatisticsError> {
+        let mut tokens = line.split_whitespace();
+        if !tokens.next().is_some_and(|verb| verb.eq_ignore_ascii_case("RtpStatistics")) {
+            return Err(StatisticsError::NoStatistics(line.to_string()));
+        }
+        match tokens.next().map(str::parse::<ResourceId>) {
+            Some(Ok(id)) if id == resource_id => {}
+            Some(Ok(_)) => {
+                return Err(StatisticsError::OtherResource {
+                    expected: resource_id,
+                    line: line.to_string(),
+                })
+            }
+            _ => return Err(StatisticsError::NoStatistics(line.to_string())),
+        }
+        let mut stats = RtpStatistics {
+            resource_id,
+            ..Default::default()
+        };
+        let mut found = false;
+        for (key, value) in tokens.filter_map(|t| t.split_once('=')) {
+            let lower = key.to_ascii_lowercase();
+            if lower == "commandtag" {
+                continue;
+            }
+            found = true;
+            match lower.as_str() {
+                "packetssent" => stats.packets_sent = parse_value(key, value)?,
+                "octetssent" | "bytessent" => stats.octets_sent = parse_value(key, value)?,
+                "packetsreceived" => stats.packets_received = parse_value(key, value)?,
+                "octetsreceived" | "bytesreceived" => stats.octets_received = parse_value(key, value)?,
+                "packetslost" => stats.packets_lost = parse_value(key, value)?,
+                "jitter" => stats.jitter = Some(parse_value(key, value)?),
+                "roundtriptime" | "rtt" => stats.round_trip_time = Some(parse_value(key, value)?),
+                "codec" | "payloadtype" => stats.codec = Some(value.to_string()),
+                _ => {
+                    stats.extra.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        if !found {
+            return Err(StatisticsError::NoStatistics(line.to_string()));
+        }
+        Ok(stats)
+    }
+}
+
+#[pymethods]
+impl RtpStatistics {
+    /// Share of expected packets that were lost, in percent.
+    pub fn loss_percent(&self) -> f64 {
+        loss_percent(self.packets_received, self.packets_lost)
+    }
+}
+
+fn loss_percent(received: u64, lost: u64) -> f64 {
+    match received + lost {
+        0 => 0.0,
+        expected => lost as f64 * 100.0 / expected as f64,
+    }
+}
+
+/// Limits checked by `RtpMonitor`; a limit left as `None` is not checked.
+#[pyclass]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RtpThresholds {
+    #[pyo3(get, set)]
+    max_loss_percent: Option<f64>,
+    #[pyo3(get, set)]
+    max_jitter: Option<u32>,
+    #[pyo3(get, set)]
+    max_round_trip_time: Option<u32>,
+}
+
+#[pymethods]
+impl RtpThresholds {
+    #[new]
+    #[pyo3(signature = (max_loss_percent=None, max_jitter=None, max_round_trip_time=None))]
+    pub fn new(max_loss_percent: Option<f64>, max_jitter: Option<u32>, max_round_trip_time: Option<u32>) -> Self {
+        RtpThresholds {
+            max_loss_percent,
+            max_jitter,
+            max_round_trip_time,
+        }
+    }
+}
+
+/// A threshold exceeded by an RTP channel; `metric` is `"loss_percent"`, `"jitter"`
+/// or `"round_trip_time"`.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RtpAlert {
+    #[pyo3(get)]
+    resource_id: ResourceId,
+    #[pyo3(get)]
+    metric: &'static str,
+    #[pyo3(get)]
+    value: f64,
+    #[pyo3(get)]
+    threshold: f64,
+}
+
+/// Checks periodic `RtpStatistics` of several RTP channels against `RtpThresholds`.
+///
+/// Packet loss is measured over the interval since the previous sample of the same
+/// channel, so an early burst of loss does not keep raising alerts.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct RtpMonitor {
+    #[pyo3(get, set)]
+    thresholds: RtpThresholds,
+    #[pyo3(get, set)]
+    interval_ms: u64,
+    previous: HashMap<ResourceId, RtpStatistics>,
+}
+
+impl RtpMonitor {
+    pub fn new(thresholds: RtpThresholds, interval_ms: u64) -> Self {
+        RtpMonitor {
+            thresholds,
+            interval_ms,
+            previous: HashMap::new(),
+        }
+    }
+
+    pub fn interval_ms(&self) -> u64 {
+        self.interval_ms
+    }
+
+    /// Record a new sample and return the thresholds it exceeds.
+    pub fn update(&mut self, stats: RtpStatistics) -> Vec<RtpAlert> {
+        let mut alerts = Vec::new();
+        let mut alert = |metric, value: f64, threshold: f64| {
+            if value > threshold {
+                alerts.push(RtpAlert {
+                    resource_id: stats.resource_id,
+                    metric,
+                    value,
+                    threshold,
+                });
+            }
+        };
+
+        if let Some(max) = self.thresholds.max_loss_percent {
+            let (received, lost) = match self.previous.get(&stats.resource_id) {
+                Some(prev) => (
+                    stats.packets_received.saturating_sub(prev.packets_received),
+                    stats.packets_lost.saturating_sub(prev.packets_lost),
+                ),
+                None => (stats.packets_received, stats.packets_lost),
+            };
+            alert("loss_percent", loss_percent(received, lost), max);
+        }
+        if let (Some(max), Some(jitter)) = (self.thresholds.max_jitter, stats.jitter) {
+            alert("jitter", f64::from(jitter), f64::from(max));
+        }
+        if let (Some(max), Some(rtt)) = (self.thresholds.max_round_trip_time, stats.round_trip_time) {
+            alert("round_trip_time", f64::from(rtt), f64::from(max));
+        }
+
+        self.previous.insert(stats.resource_id, stats);
+        alerts
+    }
+}
+
+#[pymethods]
+impl RtpMonitor {
+    #[new]
+    #[pyo3(signature = (thresholds, interval_ms=5000))]
+    fn py_new(thresholds: RtpThresholds, interval_ms: u64) -> Self {
+        RtpMonitor::new(thresholds, interval_ms)
+    }
+
+    #[pyo3(name = "update")]
+    fn py_update(&mut self, stats: RtpStatistics) -> Vec<RtpAlert> {
+        self.update(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_statistics_reply() {
+        let line = "RtpStatistics 4 PacketsSent=1500 OctetsSent=240000 PacketsReceived=1480 \
+                    OctetsReceived=236800 PacketsLost=20 Jitter=12 RTT=85 Codec=G.711-ALaw-64k \
+                    MosLq=4.1 COMMANDTAG=9";
+        let stats = RtpStatistics::parse(4, line).unwrap();
+        assert_eq!(stats.resource_id, 4);
+        assert_eq!(stats.packets_sent, 1500);
+        assert_eq!(stats.octets_received, 236800);
+        assert_eq!(stats.packets_lost, 20);
+        assert_eq!(stats.jitter, Some(12));
+        assert_eq!(stats.round_trip_time, Some(85));
+        assert_eq!(stats.codec.as_deref(), Some("G.711-ALaw-64k"));
+        assert_eq!(stats.extra.get("MosLq").map(String::as_str), Some("4.1"));
+        assert!(!stats.extra.contains_key("COMMANDTAG"));
+        assert!((stats.loss_percent() - 20.0 * 100.0 / 1500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parse_rejects_bad_replies() {
+        assert_eq!(
+            RtpStatistics::parse(1, "RtpStatistics 1 PacketsLost=many"),
+            Err(StatisticsError::BadValue {
+                key: "PacketsLost".into(),
+                value: "many".into()
+            })
+        );
+        assert!(matches!(
+            RtpStatistics::parse(1, "Error 1 COMMANDTAG=3"),
+            Err(StatisticsError::NoStatistics(_))
+        ));
+        assert!(matches!(
+            RtpStatistics::parse(1, "Error 1 PacketsLost=0 Reason=busy COMMANDTAG=3"),
+            Err(StatisticsError::NoStatistics(_))
+        ));
+        assert!(matches!(
+            RtpStatistics::parse(1, "RtpStatistics PacketsLost=0"),
+            Err(StatisticsError::NoStatistics(_))
+        ));
+        assert_eq!(
+            RtpStatistics::parse(1, "rtpstatistics 2 PacketsLost=0"),
+            Err(StatisticsError::OtherResource {
+                expected: 1,
+                line: "rtpstatistics 2 PacketsLost=0".into()
+            })
+        );
+    }
+
+    #[test]
+    fn monitor_alerts_on_interval_loss() {
+        let mut monitor = RtpMonitor::new(RtpThresholds::new(Some(5.0), Some(30), None), 1000);
+        let sample = |received, lost, jitter| {
+            RtpStatistics::parse(2, &format!("RtpStatistics 2 PacketsReceived={received} PacketsLost={lost} Jitter={jitter}")).unwrap()
+        };
+
+        // 10% loss in the first interval, jitter within limits
+        let alerts = monitor.update(sample(90, 10, 20));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].metric, "loss_percent");
+        assert_eq!(alerts[0].resource_id, 2);
+
+        // no new loss, jitter too high
+        let alerts = monitor.update(sample(190, 10, 45));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!((alerts[0].metric, alerts[0].value), ("jitter", 45.0));
+
+        assert!(monitor.update(sample(290, 12, 10)).is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::constants::{AudioFormatType, ToneType};
+use crate::g711;
+use crate::primitives::{Channels, SampleRate};
+use crate::wav::{self, WavHeader};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::borrow::Cow;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "tones")?;
+
+    child_module.add_class::<ToneGenerator>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+/// Peak amplitude of a full-scale sine, which G.711 defines as +3.14 dBm0.
+#[allow(clippy::approx_constant)]
+const FULL_SCALE_DBM0: f64 = 3.14;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ToneError {
+    #[error("'{0}' is not a DTMF key")]
+    InvalidDtmfKey(char),
+    #[error("cadence of {on_ms} ms on and {off_ms} ms off is shorter than one sample")]
+    EmptyCadence { on_ms: u16, off_ms: u16 },
+    #[error("tones can only be encoded as PCM16, ALAW or MULAW, not {0}")]
+    UnsupportedEncoding(&'static str),
+    #[error("{0} bytes of audio do not fit in a WAV file")]
+    TooLong(usize),
+    #[error("cannot write WAV file: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl From<ToneError> for PyErr {
+    fn from(err: ToneError) -> Self {
+        match err {
+            ToneError::Io(e) => PyIOError::new_err(e.to_string()),
+            other => PyValueError::new_err(other.to_string()),
+        }
+    }
+}
+
+/// Software synthesizer for single and dual-frequency tones with on/off cadences.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct ToneGenerator {
+    #[pyo3(get)]
+    sample_rate: SampleRate,
+    /// Level of each frequency component in dBm0.
+    #[pyo3(get, set)]
+    level_dbm0: f64,
+}
+
+impl ToneGenerator {
+    pub fn new(sample_rate: SampleRate, level_dbm0: f64) -> Self {
+        ToneGenerator {
+            sample_rate,
+            level_dbm0,
+        }
+    }
+
+    fn samples_for(&self, ms: u32) -> usize {
+        (u64::from(ms) * u64::from(self.sample_rate) / 1000) as usize
+    }
+
+    fn push_burst(&self, out: &mut Vec<i16>, f1: u16, f2: u16, samples: usize) {
+        let amplitude = 32767.0 * 10f64.powf((self.level_dbm0 - FULL_SCALE_DBM0) / 20.0);
+        let rate = f64::from(self.sample_rate);
+        out.extend((0..samples).map(|n| {
+            let t = n as f64 / rate;
+            let mut value = 0.0;
+            for f in [f1, f2].into_iter().filter(|&f| f != 0) {
+                value += amplitude * (2.0 * PI * f64::from(f) * t).sin();
+            }
+            value.round().clamp(-32768.0, 32767.0) as i16
+        }));
+    }
+
+    /// Synthesize `duration_ms` of a tone that is on for `on_ms` and off for `off_ms`,
+    /// repeating; without a cadence the tone plays continuously. `f2` of 0 gives a single tone.
+    /// A cadence whose period is shorter than one sample is refused.
+    pub fn cadence(
+        &self,
+        f1: u16,
+        f2: u16,
+        on_ms: Option<u16>,
+        off_ms: Option<u16>,
+        duration_ms: u32,
+    ) -> Result<Vec<i16>, ToneError> {
+        let total = self.samples_for(duration_ms);
+        let mut out = Vec::with_capacity(total);
+        match on_ms {
+            Some(on_ms) if on_ms > 0 => {
+                let off_ms = off_ms.unwrap_or(0);
+                let on = self.samples_for(u32::from(on_ms));
+                let off = self.samples_for(u32::from(off_ms));
+                if on + off == 0 {
+                    return Err(ToneError::EmptyCadence { on_ms, off_ms });
+                }
+                while out.len() < total {
+                    let burst = on.min(total - out.len());
+                    self.push_burst(&mut out, f1, f2, burst);
+                    let gap = off.min(total - out.len());
+                    out.resize(out.len() + gap, 0);
+                }
+            }
+            _ => self.push_burst(&mut out, f1, f2, total),
+        }
+        Ok(out)
+    }
+
+    /// Synthesize `duration_ms` of one of the `PlayTone_*` tones, following its cadence.
+    pub fn tone(&self, tone: &ToneType, duration_ms: u32) -> Result<Vec<i16>, ToneError> {
+        self.cadence(tone.f1, tone.f2, tone.on_ms, tone.off_ms, duration_ms)
+    }
+
+    /// Synthesize a DTMF string the way `CallSendDTMF` plays it: each key sounds for
+    /// `duration_ms`, keys are separated by `delay_ms` and a `,` inserts `pause_ms` of silence.
+    pub fn dtmf(
+        &self,
+        digits: &str,
+        duration_ms: u32,
+        delay_ms: u32,
+        pause_ms: u32,
+    ) -> Result<Vec<i16>, ToneError> {
+        let mut out = Vec::new();
+        for (i, key) in digits.chars().enumerate() {
+            if i > 0 {
+                out.resize(out.len() + self.samples_for(delay_ms), 0);
+            }
+            if key == ',' {
+                out.resize(out.len() + self.samples_for(pause_ms), 0);
+                continue;
+            }
+            let tone = ToneType::from_dtmf_key(key).ok_or(ToneError::InvalidDtmfKey(key))?;
+            self.push_burst(&mut out, tone.f1, tone.f2, self.samples_for(duration_ms));
+        }
+        Ok(out)
+    }
+}
+
+/// Encode linear samples as PCM16 (little endian), A-law or µ-law, following `audio_type`'s codec.
+pub fn encode(samples: &[i16], audio_type: &AudioFormatType) -> Result<Vec<u8>, ToneError> {
+    match audio_type.codec() {
+        "PCM16" => Ok(samples.iter().flat_map(|s| s.to_le_bytes()).collect()),
+        "ALAW" => Ok(samples.iter().map(|&s| g711::linear_to_alaw(s)).collect()),
+        "MULAW" => Ok(samples.iter().map(|&s| g711::linear_to_ulaw(s)).collect()),
+        _ => Err(ToneError::UnsupportedEncoding(audio_type.name)),
+    }
+}
+
+/// Decode PCM16 (little endian), A-law or µ-law bytes into linear samples.
+pub fn decode(data: &[u8], audio_type: &AudioFormatType) -> Result<Vec<i16>, ToneError> {
+    match audio_type.codec() {
+        "PCM16" => Ok(data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect()),
+        "ALAW" => Ok(data.iter().map(|&b| g711::alaw_to_linear(b)).collect()),
+        "MULAW" => Ok(data.iter().map(|&b| g711::ulaw_to_linear(b)).collect()),
+        _ => Err(ToneError::UnsupportedEncoding(audio_type.name)),
+    }
+}
+
+/// The `data` chunk length of `len` bytes of audio; the RIFF size, 36 bytes more, must
+/// fit in 32 bits as well.
+fn wav_data_len(len: usize) -> Result<u32, ToneError> {
+    u32::try_from(len)
+        .ok()
+        .filter(|&len| len <= u32::MAX - 36)
+        .ok_or(ToneError::TooLong(len))
+}
+
+/// Write mono `samples` as a WAV file in the codec of `audio_type`.
+pub fn write_wav(
+    path: &Path,
+    samples: &[i16],
+    sample_rate: SampleRate,
+    audio_type: &AudioFormatType,
+) -> Result<(), ToneError> {
+    let data = encode(samples, audio_type)?;
+    let (format_tag, bits_per_sample) = match audio_type.codec() {
+        "PCM16" => (wav::WAVE_FORMAT_PCM, 16),
+        "ALAW" => (wav::WAVE_FORMAT_ALAW, 8),
+        _ => (wav::WAVE_FORMAT_MULAW, 8),
+    };
+    let header = WavHeader {
+        format_tag,
+        channels: Channels::Mono as u16,
+        sample_rate: u32::from(sample_rate),
+        block_align: bits_per_sample / 8,
+        bits_per_sample,
+        data_len: wav_data_len(data.len())?,
+    };
+    let mut writer = BufWriter::new(File::create(path)?);
+    wav::write_header(&mut writer, &header)?;
+    writer.write_all(&data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[pymethods]
+impl ToneGenerator {
+    #[new]
+    #[pyo3(signature = (sample_rate=8000, level_dbm0=-10.0))]
+    fn py_new(sample_rate: SampleRate, level_dbm0: f64) -> Self {
+        ToneGenerator::new(sample_rate, level_dbm0)
+    }
+
+    /// Encoded bytes of a `PlayTone_*` tone, e.g. for streaming through a transport channel.
+    #[pyo3(name = "tone")]
+    fn py_tone(
+        &self,
+        tone: ToneType,
+        duration_ms: u32,
+        audio_type: AudioFormatType,
+    ) -> PyResult<Cow<'static, [u8]>> {
+        let data = encode(&self.tone(&tone, duration_ms)?, &audio_type)?;
+        Ok(Cow::Owned(data))
+    }
+
+    /// Encoded bytes of an arbitrary single or dual-frequency cadence.
+    #[pyo3(name = "cadence", signature = (f1, f2, duration_ms, audio_type, on_ms=None, off_ms=None))]
+    fn py_cadence(
+        &self,
+        f1: u16,
+        f2: u16,
+        duration_ms: u32,
+        audio_type: AudioFormatType,
+        on_ms: Option<u16>,
+        off_ms: Option<u16>,
+    ) -> PyResult<Cow<'static, [u8]>> {
+        let data = encode(&self.cadence(f1, f2, on_ms, off_ms, duration_ms)?, &audio_type)?;
+        Ok(Cow::Owned(data))
+    }
+
+    /// Encoded bytes of a DTMF string, with the same defaults as `CallSendDTMF`.
+    #[pyo3(name = "dtmf", signature = (digits, audio_type, duration=300, delay=200, pause_duration=2000))]
+    fn py_dtmf(
+        &self,
+        digits: &str,
+        audio_type: AudioFormatType,
+        duration: u32,
+        delay: u32,
+        pause_duration: u32,
+    ) -> PyResult<Cow<'static, [u8]>> {
+        let data = encode(&self.dtmf(digits, duration, delay, pause_duration)?, &audio_type)?;
+        Ok(Cow::Owned(data))
+    }
+
+    /// Write a `PlayTone_*` tone to a WAV file, e.g. as a test fixture.
+    #[pyo3(name = "write_tone_wav")]
+    fn py_write_tone_wav(
+        &self,
+        path: String,
+        tone: ToneType,
+        duration_ms: u32,
+        audio_type: AudioFormatType,
+    ) -> PyResult<()> {
+        let samples = self.tone(&tone, duration_ms)?;
+        Ok(write_wav(Path::new(&path), &samples, self.sample_rate, &audio_type)?)
+    }
+
+    /// Write a DTMF string to a WAV file, with the same defaults as `CallSendDTMF`.
+    #[pyo3(name = "write_dtmf_wav", signature = (path, digits, audio_type, duration=300, delay=200, pause_duration=2000))]
+    fn py_write_dtmf_wav(
+        &self,
+        path: String,
+        digits: &str,
+        audio_type: AudioFormatType,
+        duration: u32,
+        delay: u32,
+        pause_duration: u32,
+    ) -> PyResult<()> {
+        let samples = self.dtmf(digits, duration, delay, pause_duration)?;
+        Ok(write_wav(Path::new(&path), &samples, self.sample_rate, &audio_type)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{PlayTone_BusyTone, PlayTone_DialTone, RAW_ALAW, RAW_G729, WAV_MULAW};
+
+    #[test]
+    fn cadence_follows_on_off_pattern() {
+        let generator = ToneGenerator::new(8000, -10.0);
+        let samples = generator.tone(&PlayTone_BusyTone, 2000).unwrap();
+        assert_eq!(samples.len(), 16000);
+        // 500 ms on, 500 ms off
+        assert!(samples[..4000].iter().any(|&s| s != 0));
+        assert!(samples[4000..8000].iter().all(|&s| s == 0));
+        assert!(samples[8000..12000].iter().any(|&s| s != 0));
+    }
+
+    #[test]
+    fn cadence_shorter_than_a_sample_is_refused() {
+        // at 500 Hz one sample lasts 2 ms
+        let generator = ToneGenerator::new(500, -10.0);
+        assert!(matches!(
+            generator.cadence(400, 0, Some(1), Some(1), 1000),
+            Err(ToneError::EmptyCadence { on_ms: 1, off_ms: 1 })
+        ));
+        assert!(matches!(
+            ToneGenerator::new(0, -10.0).tone(&PlayTone_BusyTone, 1000),
+            Err(ToneError::EmptyCadence { .. })
+        ));
+    }
+
+    #[test]
+    fn continuous_tone_has_expected_level() {
+        let generator = ToneGenerator::new(8000, FULL_SCALE_DBM0);
+        let samples = generator.cadence(1000, 0, None, None, 100).unwrap();
+        let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!(peak > 32000, "peak {peak}");
+
+        let generator = ToneGenerator::new(8000, -10.0);
+        let samples = generator.tone(&PlayTone_DialTone, 100).unwrap();
+        assert!(samples.iter().all(|&s| s.unsigned_abs() < 16384));
+    }
+
+    #[test]
+    fn dtmf_string_timing() {
+        let generator = ToneGenerator::new(8000, -10.0);
+        let samples = generator.dtmf("1,2", 100, 50, 400).unwrap();
+        // 100 tone + 50 delay + 400 pause + 50 delay + 100 tone
+        assert_eq!(samples.len(), 8 * 700);
+        assert!(matches!(
+            generator.dtmf("1X", 100, 50, 400),
+            Err(ToneError::InvalidDtmfKey('X'))
+        ));
+    }
+
+    #[test]
+    fn encode_and_write_wav() {
+        let samples = [0i16, 1000, -1000];
+        assert_eq!(encode(&samples, &RAW_ALAW).unwrap().len(), 3);
+        assert!(matches!(
+            encode(&samples, &RAW_G729),
+            Err(ToneError::UnsupportedEncoding("RAW_G729"))
+        ));
+
+        let path = std::env::temp_dir().join(format!("gridborg-tones-{}.wav", std::process::id()));
+        write_wav(&path, &samples, 8000, &WAV_MULAW).unwrap();
+        let header = wav::read_header(&mut File::open(&path).unwrap()).unwrap();
+        assert_eq!(header.format_tag, wav::WAVE_FORMAT_MULAW);
+        assert_eq!(header.data_len, 3);
+
+        assert_eq!(wav_data_len(u32::MAX as usize - 36).unwrap(), u32::MAX - 36);
+        assert!(matches!(wav_data_len(u32::MAX as usize - 35), Err(ToneError::TooLong(_))));
+        assert!(matches!(wav_data_len(u32::MAX as usize + 1), Err(ToneError::TooLong(_))));
+    }
+}
@@ -0,0 +1,513 @@
+use crate::commands::Command;
+use crate::constants::PayloadTypeMap;
+use crate::events::{self, Event};
+use crate::mock::{split_tag, tagged};
+use crate::primitives::unix_time;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "trace")?;
+
+    child_module.add_class::<TraceEntry>()?;
+    child_module.add_class::<SessionTrace>()?;
+    child_module.add_class::<ReplayServer>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TraceError {
+    #[error("cannot access trace: {0}")]
+    Io(#[from] io::Error),
+    #[error("trace is corrupt at line {0}: {1}")]
+    Corrupt(usize, &'static str),
+}
+
+impl From<TraceError> for PyErr {
+    fn from(err: TraceError) -> Self {
+        match err {
+            TraceError::Io(e) => PyIOError::new_err(e.to_string()),
+            other => PyValueError::new_err(other.to_string()),
+        }
+    }
+}
+
+const HEADER: &str = "# gridborg trace 1";
+const SENT: &str = ">";
+const RECEIVED: &str = "<";
+/// Stands in for the password of a recorded `Login`.
+const REDACTED: &str = "***";
+/// How often `ReplayServer::wait` checks whether the replay has ended.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// One line of a control-channel session: a command the client sent (tag included) or
+/// a line it received, `at_ms` milliseconds after recording started.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    #[pyo3(get)]
+    pub at_ms: u64,
+    #[pyo3(get)]
+    pub sent: bool,
+    #[pyo3(get)]
+    pub line: String,
+}
+
+/// Writes the lines of a session to a trace file as they are sent and received.
+///
+/// After a header, each line of the file is `<at_ms>\t<direction>\t<line>`, the direction
+/// being `>` for a sent command and `<` for a received line. Lines are written through, so
+/// the trace survives a crash of the process. The password of a `Login` is not recorded.
+pub struct TraceRecorder {
+    file: LineWriter<File>,
+    started: Instant,
+}
+
+impl TraceRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "# started {}", unix_time())?;
+        Ok(TraceRecorder {
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, sent: bool, line: &str) -> io::Result<()> {
+        let (direction, line) = if sent { (SENT, redact(line)) } else { (RECEIVED, Cow::Borrowed(line)) };
+        writeln!(self.file, "{}\t{}\t{}", self.started.elapsed().as_millis(), direction, line)
+    }
+}
+
+/// `line` with the password replaced by `REDACTED` if it is a `Login` command. A `Login`
+/// that does not parse keeps only its name, as the password cannot be told apart.
+fn redact(line: &str) -> Cow<'_, str> {
+    let (command, tag) = split_tag(line);
+    if command.split_whitespace().next() != Some("Login") {
+        return Cow::Borrowed(line);
+    }
+    match command.parse() {
+        Ok(Command::Login(mut login)) => {
+            login.password = REDACTED.to_string();
+            Cow::Owned(tagged(Command::Login(login).to_string(), tag))
+        }
+        _ => Cow::Owned(tagged(format!("Login {}", REDACTED), tag)),
+    }
+}
+
+/// A recorded session, loaded from a trace file written by `GridborgClient.record_trace`.
+#[pyclass]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionTrace {
+    #[pyo3(get)]
+    pub entries: Vec<TraceEntry>,
+}
+
+impl SessionTrace {
+    pub fn load(path: &Path) -> Result<Self, TraceError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, TraceError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(TraceError::Corrupt(1, "missing header"));
+        }
+        let mut entries = Vec::new();
+        for (number, line) in lines {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(3, '\t');
+            let (Some(at_ms), Some(direction), Some(line)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(TraceError::Corrupt(number, "expected three fields"));
+            };
+            let at_ms = at_ms.parse().map_err(|_| TraceError::Corrupt(number, "bad time"))?;
+            let sent = match direction {
+                SENT => true,
+                RECEIVED => false,
+                _ => return Err(TraceError::Corrupt(number, "bad direction")),
+            };
+            entries.push(TraceEntry {
+                at_ms,
+                sent,
+                line: line.to_string(),
+            });
+        }
+        Ok(SessionTrace { entries })
+    }
+
+    fn lines(&self, sent: bool) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|e| e.sent == sent)
+            .map(|e| e.line.clone())
+            .collect()
+    }
+
+    /// The received lines that are events, in order, for feeding to event handlers such as
+    /// `FaxSendJob.handle_event`; replies to commands are left out.
+    pub fn events(&self, payload_types: &PayloadTypeMap) -> Vec<Event> {
+        self.entries
+            .iter()
+            .filter(|e| !e.sent)
+            .filter_map(|e| events::parse_event_with(&e.line, payload_types).ok())
+            .collect()
+    }
+}
+
+#[pymethods]
+impl SessionTrace {
+    #[staticmethod]
+    #[pyo3(name = "load")]
+    fn py_load(path: &str) -> PyResult<Self> {
+        Ok(SessionTrace::load(Path::new(path))?)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "parse")]
+    fn py_parse(text: &str) -> PyResult<Self> {
+        Ok(SessionTrace::parse(text)?)
+    }
+
+    fn sent(&self) -> Vec<String> {
+        self.lines(true)
+    }
+
+    fn received(&self) -> Vec<String> {
+        self.lines(false)
+    }
+
+    #[pyo3(name = "events", signature = (payload_types=None))]
+    fn py_events(&self, payload_types: Option<PayloadTypeMap>) -> Vec<Event> {
+        self.events(&payload_types.unwrap_or_default())
+    }
+
+    fn __len__(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReplayOutcome {
+    replayed: usize,
+    finished: bool,
+    mismatch: Option<(usize, String, String)>,
+}
+
+fn lock(outcome: &Mutex<ReplayOutcome>) -> MutexGuard<'_, ReplayOutcome> {
+    outcome.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Play the server side of `entries` to one client, checking what it sends against the trace.
+fn replay(entries: &[TraceEntry], stream: TcpStream, realtime: bool, outcome: &Mutex<ReplayOutcome>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+    let mut previous_ms = entries.first().map_or(0, |e| e.at_ms);
+    // recorded command tags and the tags the client used in their place
+    let mut tags = HashMap::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.sent {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let actual = line.trim_end();
+            let (expected_command, expected_tag) = split_tag(&entry.line);
+            let (actual_command, actual_tag) = split_tag(actual);
+            if redact(actual_command) != expected_command {
+                lock(outcome).mismatch = Some((index, entry.line.clone(), actual.to_string()));
+                return writer.shutdown(Shutdown::Both);
+            }
+            if let (Some(expected_tag), Some(actual_tag)) = (expected_tag, actual_tag) {
+                tags.insert(expected_tag, actual_tag);
+            }
+        } else {
+            if realtime {
+                thread::sleep(Duration::from_millis(entry.at_ms.saturating_sub(previous_ms)));
+            }
+            let (reply, tag) = split_tag(&entry.line);
+            let reply = tagged(reply.to_string(), tag.map(|tag| tags.get(&tag).copied().unwrap_or(tag)));
+            writer.write_all(format!("{}\n", reply).as_bytes())?;
+        }
+        previous_ms = entry.at_ms;
+        lock(outcome).replayed = index + 1;
+    }
+    lock(outcome).finished = true;
+
+    // anything the client sends after the end of the trace is a divergence too
+    line.clear();
+    while reader.read_line(&mut line)? > 0 {
+        if !line.trim().is_empty() {
+            lock(outcome).mismatch = Some((entries.len(), String::new(), line.trim_end().to_string()));
+            break;
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+/// A fake server that plays a recorded session back to a client.
+///
+/// The first client to connect gets the received lines of the trace in order, each one
+/// once the commands recorded before it have arrived. The commands must match the trace
+/// apart from their `COMMANDTAG`, and replies carry the tag of the command they answer as
+/// the client sent it; the recorded `Login` matches any password. The first command that
+/// does not match, or any command after the end of the trace, is kept in `mismatch` as `(index, expected, actual)` and ends the replay;
+/// `actual` is empty if the client disconnected. By default lines are written as soon as
+/// they are due, and with `realtime` the recorded gaps between them are kept.
+#[pyclass]
+pub struct ReplayServer {
+    outcome: Arc<Mutex<ReplayOutcome>>,
+    stopped: Arc<AtomicBool>,
+    client: Arc<Mutex<Option<TcpStream>>>,
+    #[pyo3(get)]
+    port: u16,
+}
+
+impl ReplayServer {
+    pub fn start(trace: &SessionTrace, port: u16, realtime: bool) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let port = listener.local_addr()?.port();
+        let outcome = Arc::new(Mutex::new(ReplayOutcome::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let client = Arc::new(Mutex::new(None));
+
+        let entries = trace.entries.clone();
+        let (replay_outcome, replay_stopped, replay_client) = (outcome.clone(), stopped.clone(), client.clone());
+        thread::spawn(move || {
+            let Ok((stream, _)) = listener.accept() else {
+                return;
+            };
+            if replay_stopped.load(Ordering::SeqCst) {
+                return;
+            }
+            *replay_client.lock().unwrap_or_else(|e| e.into_inner()) = stream.try_clone().ok();
+            if replay(&entries, stream, realtime, &replay_outcome).is_err() {
+                let mut outcome = lock(&replay_outcome);
+                if !outcome.finished && outcome.mismatch.is_none() {
+                    let index = outcome.replayed;
+                    outcome.mismatch = Some((index, entries[index].line.clone(), String::new()));
+                }
+            }
+        });
+        Ok(ReplayServer {
+            outcome,
+            stopped,
+            client,
+            port,
+        })
+    }
+
+    /// Entries of the trace played so far.
+    pub fn replayed(&self) -> usize {
+        lock(&self.outcome).replayed
+    }
+
+    /// The whole trace has been played.
+    pub fn is_finished(&self) -> bool {
+        lock(&self.outcome).finished
+    }
+
+    pub fn mismatch(&self) -> Option<(usize, String, String)> {
+        lock(&self.outcome).mismatch.clone()
+    }
+
+    /// Wait up to `timeout` for the replay to finish or diverge; true if it finished.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let outcome = lock(&self.outcome);
+            if outcome.mismatch.is_some() || outcome.finished || Instant::now() >= deadline {
+                return outcome.finished;
+            }
+            drop(outcome);
+            thread::sleep(WAIT_POLL_INTERVAL);
+        }
+    }
+
+    pub fn stop(&self) {
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // wake the accept call if no client came
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        if let Some(client) = self.client.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for ReplayServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[pymethods]
+impl ReplayServer {
+    #[new]
+    #[pyo3(signature = (trace, port=0, realtime=false))]
+    fn py_new(trace: &SessionTrace, port: u16, realtime: bool) -> PyResult<Self> {
+        Ok(ReplayServer::start(trace, port, realtime)?)
+    }
+
+    #[getter(replayed)]
+    fn py_replayed(&self) -> usize {
+        self.replayed()
+    }
+
+    #[getter(finished)]
+    fn py_finished(&self) -> bool {
+        self.is_finished()
+    }
+
+    #[getter(mismatch)]
+    fn py_mismatch(&self) -> Option<(usize, String, String)> {
+        self.mismatch()
+    }
+
+    #[pyo3(name = "wait", signature = (timeout_ms=5000))]
+    fn py_wait(&self, py: Python<'_>, timeout_ms: u64) -> bool {
+        py.allow_threads(|| self.wait(Duration::from_millis(timeout_ms)))
+    }
+
+    #[pyo3(name = "stop")]
+    fn py_stop(&self) {
+        self.stop()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(&self, _exc_type: PyObject, _exc_value: PyObject, _traceback: PyObject) {
+        self.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "# gridborg trace 1\n\
+                         # started 1700000000\n\
+                         0\t>\tResourceCreateFrontEnd COMMANDTAG=0\n\
+                         3\t<\tResourceCreated 1 COMMANDTAG=0\n\
+                         3\t<\tEResourceCreated 1 1\n\
+                         9\t>\tCallMake 1 200 COMMANDTAG=1\n\
+                         12\t<\tECallConnectionEstablished 1 1\n";
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("gridborg-trace-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn records_and_loads_entries() {
+        let path = temp_path("record");
+        let mut recorder = TraceRecorder::create(&path).unwrap();
+        recorder.record(true, "Login admin \"pass word\" 2 3 COMMANDTAG=0").unwrap();
+        recorder.record(true, "CallAnswer 1 COMMANDTAG=1").unwrap();
+        recorder.record(false, "ECallCleared 1 1 \"hung\tup\"").unwrap();
+        let trace = SessionTrace::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(trace.lines(true), ["Login admin *** 2 3 COMMANDTAG=0", "CallAnswer 1 COMMANDTAG=1"]);
+        assert_eq!(trace.lines(false), ["ECallCleared 1 1 \"hung\tup\""]);
+        assert!(trace.entries[0].at_ms <= trace.entries[1].at_ms);
+
+        let trace = SessionTrace::parse(TRACE).unwrap();
+        assert_eq!(trace.entries.len(), 5);
+        let events = trace.events(&PayloadTypeMap::default());
+        assert_eq!(events.iter().map(|e| e.to_string()).collect::<Vec<_>>(), ["EResourceCreated 1 1", "ECallConnectionEstablished 1 1"]);
+
+        assert!(matches!(SessionTrace::parse("0\t>\tQuit"), Err(TraceError::Corrupt(1, _))));
+        assert!(matches!(SessionTrace::parse(&format!("{}\n0\t?\tQuit", HEADER)), Err(TraceError::Corrupt(2, _))));
+    }
+
+    #[test]
+    fn redacts_logins_that_do_not_parse() {
+        assert_eq!(redact("Login admin \"pass word COMMANDTAG=0"), "Login *** COMMANDTAG=0");
+        assert_eq!(redact("Login admin secret"), "Login ***");
+        assert_eq!(redact("Login admin secret 2 3 COMMANDTAG=4"), "Login admin *** 2 3 COMMANDTAG=4");
+        assert_eq!(redact("LoginStatus 1"), "LoginStatus 1");
+    }
+
+    fn exchange(stream: &mut TcpStream, reader: &mut BufReader<TcpStream>, command: &str, replies: usize) -> Vec<String> {
+        stream.write_all(format!("{}\n", command).as_bytes()).unwrap();
+        (0..replies)
+            .map(|_| {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replays_server_side_of_trace() {
+        let server = ReplayServer::start(&SessionTrace::parse(TRACE).unwrap(), 0, false).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        assert_eq!(
+            exchange(&mut stream, &mut reader, "ResourceCreateFrontEnd COMMANDTAG=0", 2),
+            ["ResourceCreated 1 COMMANDTAG=0", "EResourceCreated 1 1"]
+        );
+        assert_eq!(
+            exchange(&mut stream, &mut reader, "CallMake 1 200 COMMANDTAG=1", 1),
+            ["ECallConnectionEstablished 1 1"]
+        );
+        assert!(server.wait(Duration::from_secs(5)));
+        assert_eq!((server.replayed(), server.mismatch()), (5, None));
+    }
+
+    #[test]
+    fn replay_renumbers_tags_and_ignores_password() {
+        let trace = "# gridborg trace 1\n\
+                     0\t>\tLogin admin *** 2 3 COMMANDTAG=0\n\
+                     1\t<\tESessionCreated 1\n\
+                     2\t>\tResourceCreateFrontEnd COMMANDTAG=1\n\
+                     3\t<\tResourceCreated 1 COMMANDTAG=1\n\
+                     3\t<\tEResourceCreated 1 1\n";
+        let server = ReplayServer::start(&SessionTrace::parse(trace).unwrap(), 0, false).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        assert_eq!(exchange(&mut stream, &mut reader, "Login admin secret 2 3 COMMANDTAG=7", 1), ["ESessionCreated 1"]);
+        assert_eq!(
+            exchange(&mut stream, &mut reader, "ResourceCreateFrontEnd COMMANDTAG=8", 2),
+            ["ResourceCreated 1 COMMANDTAG=8", "EResourceCreated 1 1"]
+        );
+        assert!(server.wait(Duration::from_secs(5)));
+        assert_eq!(server.mismatch(), None);
+    }
+
+    #[test]
+    fn reports_divergence_from_trace() {
+        let server = ReplayServer::start(&SessionTrace::parse(TRACE).unwrap(), 0, false).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        exchange(&mut stream, &mut reader, "ResourceCreateFrontEnd COMMANDTAG=0", 2);
+        assert_eq!(exchange(&mut stream, &mut reader, "CallMake 1 300 COMMANDTAG=1", 1), [""]);
+        assert!(!server.wait(Duration::from_secs(5)));
+        assert_eq!(
+            server.mismatch(),
+            Some((3, "CallMake 1 200 COMMANDTAG=1".to_string(), "CallMake 1 300 COMMANDTAG=1".to_string()))
+        );
+        assert_eq!(server.replayed(), 3);
+    }
+}
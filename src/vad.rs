@@ -0,0 +1,316 @@
+use crate::commands::Command;
+use crate::constants::AudioFormatType;
+use crate::primitives::{ResourceId, SampleRate};
+use crate::tones;
+use pyo3::prelude::*;
+use std::collections::VecDeque;
+
+pub fn init(parent_module: &Bound<'_, PyModule>) -> PyResult<()> {
+    let child_module = PyModule::new(parent_module.py(), "vad")?;
+
+    child_module.add_class::<AudioLevelSettings>()?;
+    child_module.add_class::<AudioLevelMeter>()?;
+    child_module.add_class::<AudioLevel>()?;
+
+    parent_module.add_submodule(&child_module)
+}
+
+const DEFAULT_RESOLUTION: u16 = 100;
+const DEFAULT_VOICE_DEAD_BAND: u16 = 20;
+const DEFAULT_SILENCE_DEAD_BAND: u16 = 10;
+const DEFAULT_ADAPTIVE_PERIOD: u16 = 10_000;
+const DEFAULT_VOICE_TIMER: u16 = 200;
+const DEFAULT_SILENCE_TIMER: u16 = 1000;
+
+/// Parameters of `AudioLevelNotificationSend`, shared by the local `AudioLevelMeter`.
+///
+/// * `resolution` - length of each measurement window in ms
+/// * `voice_dead_band` - energy above the noise floor that counts as voice
+/// * `silence_dead_band` - energy above the noise floor below which audio counts as silence
+/// * `adaptive_period` - ms over which the noise floor is tracked; 0 fixes the floor at 0
+/// * `voice_timer` - ms of voice needed before reporting talk
+/// * `silence_timer` - ms of silence needed before reporting the end of talk
+///
+/// Parameters left as `None` are omitted from the command and use local defaults in the meter.
+#[pyclass]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AudioLevelSettings {
+    #[pyo3(get, set)]
+    resolution: Option<u16>,
+    #[pyo3(get, set)]
+    voice_dead_band: Option<u16>,
+    #[pyo3(get, set)]
+    silence_dead_band: Option<u16>,
+    #[pyo3(get, set)]
+    adaptive_period: Option<u16>,
+    #[pyo3(get, set)]
+    voice_timer: Option<u16>,
+    #[pyo3(get, set)]
+    silence_timer: Option<u16>,
+}
+
+#[pymethods]
+impl AudioLevelSettings {
+    #[new]
+    #[pyo3(signature = (resolution=None, voice_dead_band=None, silence_dead_band=None, adaptive_period=None, voice_timer=None, silence_timer=None))]
+    pub fn new(
+        resolution: Option<u16>,
+        voice_dead_band: Option<u16>,
+        silence_dead_band: Option<u16>,
+        adaptive_period: Option<u16>,
+        voice_timer: Option<u16>,
+        silence_timer: Option<u16>,
+    ) -> Self {
+        AudioLevelSettings {
+            resolution,
+            voice_dead_band,
+            silence_dead_band,
+            adaptive_period,
+            voice_timer,
+            silence_timer,
+        }
+    }
+
+    /// The `AudioLevelNotificationSend` command applying these settings on the server.
+    pub fn command(&self, resource_id: ResourceId) -> Command {
+        Command::audio_level_notification_send(
+            resource_id,
+            self.resolution,
+            self.voice_dead_band,
+            self.silence_dead_band,
+            self.adaptive_period,
+            self.voice_timer,
+            self.silence_timer,
+        )
+    }
+}
+
+/// One measurement window, shaped like `AudioLevelNotification`.
+///
+/// `energy_level` is the window RMS in dB above -100 dBov, from 0 (silence)
+/// to 100 (full scale). `timestamp_ms` is the start of the window.
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioLevel {
+    #[pyo3(get)]
+    resource_id: ResourceId,
+    #[pyo3(get)]
+    in_talk: bool,
+    #[pyo3(get)]
+    energy_level: u8,
+    #[pyo3(get)]
+    timestamp_ms: u64,
+}
+
+/// Local voice activity detector and energy meter for streamed linear PCM.
+#[pyclass]
+pub struct AudioLevelMeter {
+    #[pyo3(get)]
+    resource_id: ResourceId,
+    #[pyo3(get)]
+    sample_rate: SampleRate,
+    #[pyo3(get)]
+    in_talk: bool,
+    resolution: u16,
+    voice_dead_band: u8,
+    silence_dead_band: u8,
+    voice_timer: u16,
+    silence_timer: u16,
+    window_len: usize,
+    history_len: usize,
+    window: Vec<i16>,
+    history: VecDeque<u8>,
+    windows_seen: u64,
+    /// ms spent on the other side of the current state's threshold
+    pending_ms: u32,
+}
+
+impl AudioLevelMeter {
+    pub fn new(resource_id: ResourceId, sample_rate: SampleRate, settings: &AudioLevelSettings) -> Self {
+        let resolution = settings.resolution.unwrap_or(DEFAULT_RESOLUTION).max(1);
+        let adaptive_period = settings.adaptive_period.unwrap_or(DEFAULT_ADAPTIVE_PERIOD);
+        let window_len = (u64::from(sample_rate) * u64::from(resolution) / 1000).max(1) as usize;
+        AudioLevelMeter {
+            resource_id,
+            sample_rate,
+            in_talk: false,
+            resolution,
+            voice_dead_band: dead_band(settings.voice_dead_band, DEFAULT_VOICE_DEAD_BAND),
+            silence_dead_band: dead_band(settings.silence_dead_band, DEFAULT_SILENCE_DEAD_BAND),
+            voice_timer: settings.voice_timer.unwrap_or(DEFAULT_VOICE_TIMER),
+            silence_timer: settings.silence_timer.unwrap_or(DEFAULT_SILENCE_TIMER),
+            window_len,
+            history_len: usize::from(adaptive_period / resolution),
+            window: Vec::with_capacity(window_len),
+            history: VecDeque::new(),
+            windows_seen: 0,
+            pending_ms: 0,
+        }
+    }
+
+    /// Analyse a frame of linear samples, returning a level for every window completed within it.
+    pub fn process(&mut self, samples: &[i16]) -> Vec<AudioLevel> {
+        let mut levels = Vec::new();
+        for &sample in samples {
+            self.window.push(sample);
+            if self.window.len() == self.window_len {
+                let energy_level = energy_level(&self.window);
+                self.window.clear();
+                levels.push(self.update(energy_level));
+            }
+        }
+        levels
+    }
+
+    /// Lowest energy over the adaptive period, including the current window.
+    pub fn noise_floor(&self) -> u8 {
+        self.history.iter().copied().min().unwrap_or(0)
+    }
+
+    fn update(&mut self, energy_level: u8) -> AudioLevel {
+        if self.history_len > 0 {
+            if self.history.len() == self.history_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(energy_level);
+        }
+        let floor = self.noise_floor();
+
+        let (crossed, timer) = if self.in_talk {
+            (energy_level < floor.saturating_add(self.silence_dead_band), self.silence_timer)
+        } else {
+            (energy_level >= floor.saturating_add(self.voice_dead_band), self.voice_timer)
+        };
+        if crossed {
+            self.pending_ms += u32::from(self.resolution);
+            if self.pending_ms >= u32::from(timer) {
+                self.in_talk = !self.in_talk;
+                self.pending_ms = 0;
+            }
+        } else {
+            self.pending_ms = 0;
+        }
+
+        let level = AudioLevel {
+            resource_id: self.resource_id,
+            in_talk: self.in_talk,
+            energy_level,
+            timestamp_ms: self.windows_seen * u64::from(self.resolution),
+        };
+        self.windows_seen += 1;
+        level
+    }
+}
+
+fn dead_band(value: Option<u16>, default: u16) -> u8 {
+    value.unwrap_or(default).min(100) as u8
+}
+
+fn energy_level(window: &[i16]) -> u8 {
+    let mean_square = window.iter().map(|&s| f64::from(s).powi(2)).sum::<f64>() / window.len() as f64;
+    if mean_square == 0.0 {
+        return 0;
+    }
+    let dbov = 10.0 * (mean_square / 32768f64.powi(2)).log10();
+    (dbov + 100.0).round().clamp(0.0, 100.0) as u8
+}
+
+#[pymethods]
+impl AudioLevelMeter {
+    #[new]
+    #[pyo3(signature = (settings=AudioLevelSettings::default(), resource_id=0, sample_rate=8000))]
+    fn py_new(settings: AudioLevelSettings, resource_id: ResourceId, sample_rate: SampleRate) -> Self {
+        AudioLevelMeter::new(resource_id, sample_rate, &settings)
+    }
+
+    /// Analyse a frame of PCM16, A-law or µ-law audio.
+    #[pyo3(name = "process")]
+    fn py_process(&mut self, data: &[u8], audio_type: AudioFormatType) -> PyResult<Vec<AudioLevel>> {
+        let samples = tones::decode(data, &audio_type)?;
+        Ok(self.process(&samples))
+    }
+
+    #[pyo3(name = "noise_floor")]
+    fn py_noise_floor(&self) -> u8 {
+        self.noise_floor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tones::ToneGenerator;
+
+    fn speech_like(generator: &ToneGenerator, ms: u32) -> Vec<i16> {
+        generator.cadence(300, 1200, None, None, ms).unwrap()
+    }
+
+    fn noise(ms: u32) -> Vec<i16> {
+        // low-level deterministic pseudo-noise around -70 dBov
+        (0..ms * 8).map(|n| ((n * 7919 % 21) as i16) - 10).collect()
+    }
+
+    #[test]
+    fn energy_level_scale() {
+        assert_eq!(energy_level(&[0; 80]), 0);
+        assert_eq!(energy_level(&[i16::MIN; 80]), 100);
+        let generator = ToneGenerator::new(8000, -20.0);
+        // two components at -20 dBm0 each, where 0 dBm0 is 3.14 dB below full scale
+        let level = energy_level(&speech_like(&generator, 100));
+        assert!((76..=78).contains(&level), "{level}");
+    }
+
+    #[test]
+    fn voice_and_silence_timers() {
+        let settings = AudioLevelSettings::new(Some(50), Some(20), Some(10), Some(5000), Some(150), Some(400));
+        let mut meter = AudioLevelMeter::new(4, 8000, &settings);
+        let generator = ToneGenerator::new(8000, -20.0);
+
+        let mut samples = noise(500);
+        samples.extend(speech_like(&generator, 1000));
+        samples.extend(noise(1000));
+        let levels = meter.process(&samples);
+        assert_eq!(levels.len(), 50);
+        assert!(levels.iter().all(|l| l.resource_id == 4));
+
+        let talk_start = levels.iter().find(|l| l.in_talk).unwrap();
+        // speech starts at 500 ms, voice timer of 150 ms is reached in the third window
+        assert_eq!(talk_start.timestamp_ms, 600);
+        let talk_end = levels.iter().skip_while(|l| !l.in_talk).find(|l| !l.in_talk).unwrap();
+        assert_eq!(talk_end.timestamp_ms, 1500 + 350);
+    }
+
+    #[test]
+    fn short_bursts_do_not_trigger_talk() {
+        let settings = AudioLevelSettings::new(Some(50), None, None, None, Some(300), None);
+        let mut meter = AudioLevelMeter::new(0, 8000, &settings);
+        let generator = ToneGenerator::new(8000, -20.0);
+        let mut samples = noise(300);
+        samples.extend(speech_like(&generator, 200));
+        samples.extend(noise(300));
+        assert!(meter.process(&samples).iter().all(|l| !l.in_talk));
+    }
+
+    #[test]
+    fn adaptive_floor_tracks_background_level() {
+        let settings = AudioLevelSettings::new(Some(100), Some(10), Some(5), Some(1000), Some(100), Some(100));
+        let mut meter = AudioLevelMeter::new(0, 8000, &settings);
+        let loud = ToneGenerator::new(8000, -20.0);
+        // a constant tone becomes background once it fills the adaptive period
+        let mut samples = noise(500);
+        samples.extend(speech_like(&loud, 3000));
+        let levels = meter.process(&samples);
+        assert!(levels[5].in_talk);
+        assert!(!levels.last().unwrap().in_talk);
+        assert_eq!(meter.noise_floor(), levels.last().unwrap().energy_level);
+    }
+
+    #[test]
+    fn settings_build_command() {
+        let settings = AudioLevelSettings::new(Some(100), Some(20), None, None, Some(200), None);
+        assert_eq!(
+            settings.command(3).to_string(),
+            "AudioLevelNotificationSend 3 Resolution=100 VoiceDeadBand=20 VoiceTimer=200"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Minimal RIFF/WAVE header handling for prompt files.
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+pub const WAVE_FORMAT_PCM: u16 = 0x0001;
+pub const WAVE_FORMAT_MS_ADPCM: u16 = 0x0002;
+pub const WAVE_FORMAT_ALAW: u16 = 0x0006;
+pub const WAVE_FORMAT_MULAW: u16 = 0x0007;
+pub const WAVE_FORMAT_IMA_ADPCM: u16 = 0x0011;
+pub const WAVE_FORMAT_DIALOGIC_OKI_ADPCM: u16 = 0x0017;
+pub const WAVE_FORMAT_GSM610: u16 = 0x0031;
+pub const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Largest `fmt ` chunk accepted; the biggest standard one, MS ADPCM with its
+/// coefficient table, is 50 bytes.
+const MAX_FMT_CHUNK: u32 = 256;
+
+/// The parts of the `fmt ` and `data` chunks needed to describe a prompt.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WavHeader {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    pub data_len: u32,
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+/// Size of a chunk body including the pad byte that follows an odd sized one.
+fn padded(size: u32) -> u64 {
+    u64::from(size) + u64::from(size & 1)
+}
+
+/// Read the RIFF header up to the start of the `data` chunk.
+///
+/// For `WAVE_FORMAT_EXTENSIBLE` files the format tag of the sub-format GUID is returned.
+/// Chunks other than `fmt ` are seeked past without being read.
+pub fn read_header<R: Read + Seek>(reader: &mut R) -> io::Result<WavHeader> {
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF/WAVE file"));
+    }
+
+    let mut header: Option<WavHeader> = None;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = u32_at(&chunk, 4);
+
+        match &chunk[0..4] {
+            b"fmt " => {
+                if size < 16 {
+                    return Err(invalid("fmt chunk too short"));
+                }
+                if size > MAX_FMT_CHUNK {
+                    return Err(invalid("fmt chunk too long"));
+                }
+                let mut fmt = vec![0u8; padded(size) as usize];
+                reader.read_exact(&mut fmt)?;
+                let mut format_tag = u16_at(&fmt, 0);
+                if format_tag == WAVE_FORMAT_EXTENSIBLE && size >= 26 {
+                    format_tag = u16_at(&fmt, 24);
+                }
+                header = Some(WavHeader {
+                    format_tag,
+                    channels: u16_at(&fmt, 2),
+                    sample_rate: u32_at(&fmt, 4),
+                    block_align: u16_at(&fmt, 12),
+                    bits_per_sample: u16_at(&fmt, 14),
+                    data_len: 0,
+                });
+            }
+            b"data" => {
+                let mut header = header.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                header.data_len = size;
+                return Ok(header);
+            }
+            _ => {
+                // a chunk running past the end shows as a failed read of the next header
+                reader.seek(SeekFrom::Current(padded(size) as i64))?;
+            }
+        }
+    }
+}
+
+/// Write a canonical 44 byte header; `header.data_len` bytes of sample data must follow.
+pub fn write_header<W: Write>(writer: &mut W, header: &WavHeader) -> io::Result<()> {
+    writer.write_all(&header_bytes(header))
+}
+
+pub(crate) fn header_bytes(header: &WavHeader) -> Vec<u8> {
+    let mut out = Vec::with_capacity(44);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + header.data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&header.format_tag.to_le_bytes());
+    out.extend_from_slice(&header.channels.to_le_bytes());
+    out.extend_from_slice(&header.sample_rate.to_le_bytes());
+    let byte_rate = header.sample_rate * u32::from(header.block_align);
+    out.extend_from_slice(&byte_rate.to_le_bytes());
+    out.extend_from_slice(&header.block_align.to_le_bytes());
+    out.extend_from_slice(&header.bits_per_sample.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&header.data_len.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_header_skips_unknown_chunks() {
+        let header = WavHeader {
+            format_tag: WAVE_FORMAT_ALAW,
+            channels: 1,
+            sample_rate: 8000,
+            block_align: 1,
+            bits_per_sample: 8,
+            data_len: 160,
+        };
+        let mut bytes = header_bytes(&header);
+        // insert a LIST chunk with an odd size between fmt and data
+        let list = [b"LIST".as_slice(), &3u32.to_le_bytes(), b"abc\0"].concat();
+        bytes.splice(36..36, list);
+
+        assert_eq!(read_header(&mut io::Cursor::new(bytes)).unwrap(), header);
+    }
+
+    #[test]
+    fn read_header_rejects_non_wave() {
+        let err = read_header(&mut io::Cursor::new(b"RIFF\0\0\0\0AVI LIST")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_header_rejects_oversized_chunks() {
+        let mut bytes = header_bytes(&WavHeader {
+            format_tag: WAVE_FORMAT_PCM,
+            channels: 1,
+            sample_rate: 8000,
+            block_align: 2,
+            bits_per_sample: 16,
+            data_len: 0,
+        });
+
+        let mut huge_fmt = bytes.clone();
+        huge_fmt[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = read_header(&mut io::Cursor::new(huge_fmt)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // a chunk claiming u32::MAX bytes is skipped to past the end of the file
+        let list = [b"LIST".as_slice(), &u32::MAX.to_le_bytes()].concat();
+        bytes.splice(36..36, list);
+        let err = read_header(&mut io::Cursor::new(bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,5 +1,6 @@
 use std::sync::Once;
 use pyo3::prelude::*;
+use pyo3::types::IntoPyDict;
 use gridborg_rs::gridborg_rs as gridborg;
 
 static PY_INIT: Once = Once::new();
@@ -14,6 +15,9 @@ pub fn init_python() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{BufRead, BufReader, Lines, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
 
     #[test]
     fn test_sum_as_string() {
@@ -75,4 +79,578 @@ mod tests {
             assert_eq!(command_tag, 0);
         });
     }
+
+    #[test]
+    fn test_rtp_command_network_addresses() {
+        init_python();
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py
+                .import("gridborg_rs")
+                .expect("import gridborg failed");
+            let command_class = gridborg_rs
+                .getattr("commands")
+                .and_then(|m| m.getattr("Command"))
+                .expect("getattr Command failed");
+
+            // addresses can be given as "ip:port" strings or (ip, port) tuples
+            let command = command_class
+                .call_method1(
+                    "rtp_channel_start_sending",
+                    (
+                        3u32,
+                        ("10.0.0.1", 5004u16),
+                        "10.0.0.1:5005",
+                        py.None(),
+                        py.None(),
+                        py.None(),
+                        py.None(),
+                        py.None(),
+                    ),
+                )
+                .expect("rtp_channel_start_sending failed");
+            assert_eq!(
+                command.str().unwrap().to_string(),
+                "RtpChannelStartSending 3 10.0.0.1:5004 ReceiverControlAddress=10.0.0.1:5005"
+            );
+
+            for bad in ["10.0.0.1", "10.0.0.1:0"] {
+                let err = command_class
+                    .call_method1(
+                        "rtp_channel_start_sending",
+                        (3u32, bad, py.None(), py.None(), py.None(), py.None(), py.None(), py.None()),
+                    )
+                    .expect_err("invalid address accepted");
+                assert!(err.to_string().contains("Invalid network address"), "{err}");
+            }
+        });
+    }
+
+    /// One client connection to a fake server, driven line by line from its thread.
+    struct FakeConnection {
+        writer: TcpStream,
+        lines: Lines<BufReader<TcpStream>>,
+    }
+
+    impl FakeConnection {
+        /// Read the next line, check that it starts with `prefix` and write `answer`.
+        fn expect(&mut self, prefix: &str, answer: &str) {
+            let line = self.lines.next().unwrap().unwrap();
+            assert!(line.starts_with(prefix), "{line}");
+            self.writer.write_all(answer.as_bytes()).unwrap();
+        }
+
+        /// The next `count` lines; the client keeps a reading clone of its socket,
+        /// so only the expected lines are read.
+        fn take(self, count: usize) -> Vec<String> {
+            self.lines.take(count).map(|line| line.unwrap()).collect()
+        }
+    }
+
+    /// Accept one client on a free port and run `script` on its connection.
+    fn fake_server<T, F>(script: F) -> (u16, thread::JoinHandle<T>)
+    where
+        T: Send + 'static,
+        F: FnOnce(FakeConnection) -> T + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            script(FakeConnection {
+                writer: stream.try_clone().unwrap(),
+                lines: BufReader::new(stream).lines(),
+            })
+        });
+        (port, server)
+    }
+
+    /// A `MockServer` on a free port, with `credentials` or the default ones.
+    fn mock_server<'py>(gridborg_rs: &Bound<'py, PyModule>, credentials: Option<(&str, &str)>) -> Bound<'py, PyAny> {
+        let class = gridborg_rs.getattr("mock").and_then(|m| m.getattr("MockServer")).unwrap();
+        match credentials {
+            Some((username, password)) => class.call1((0u16, username, password)),
+            None => class.call0(),
+        }
+        .expect("failed to start MockServer")
+    }
+
+    /// A `GridborgClient` connected to the server on `port`.
+    fn connected_client<'py>(gridborg_rs: &Bound<'py, PyModule>, port: u16) -> Bound<'py, PyAny> {
+        let py = gridborg_rs.py();
+        let client = gridborg_rs
+            .getattr("client")
+            .and_then(|m| m.getattr("GridborgClient"))
+            .and_then(|c| c.call1(("127.0.0.1", port, py.None(), py.None(), py.None())))
+            .expect("failed to create GridborgClient");
+        client.call_method0("connect").expect("connect failed");
+        client
+    }
+
+    fn fax_send_options<'py>(gridborg_rs: &Bound<'py, PyModule>) -> Bound<'py, PyAny> {
+        gridborg_rs
+            .getattr("fax")
+            .and_then(|m| m.getattr("FaxSendOptions"))
+            .and_then(|c| c.call0())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_get_rtp_statistics_reply() {
+        init_python();
+
+        let (port, server) = fake_server(|mut conn| {
+            // an unrelated event arrives before the reply
+            conn.expect(
+                "GetRtpStatistics 7 COMMANDTAG=0",
+                "ERtpChannelStopped 1 9\nRtpStatistics 7 PacketsReceived=95 PacketsLost=5 Jitter=40 COMMANDTAG=0\n",
+            );
+        });
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let client = connected_client(&gridborg_rs, port);
+
+            let stats = client
+                .call_method1("get_rtp_statistics", (7u32,))
+                .expect("get_rtp_statistics failed");
+            let lost: u64 = stats.getattr("packets_lost").unwrap().extract().unwrap();
+            let jitter: Option<u32> = stats.getattr("jitter").unwrap().extract().unwrap();
+            let loss: f64 = stats.call_method0("loss_percent").unwrap().extract().unwrap();
+            assert_eq!((lost, jitter), (5, Some(40)));
+            assert!((loss - 5.0).abs() < 1e-9);
+            let unhandled: Vec<String> = client.call_method0("take_unhandled").unwrap().extract().unwrap();
+            assert_eq!(unhandled, ["ERtpChannelStopped 1 9"]);
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_audio_routes_follow_commands() {
+        init_python();
+
+        let (port, server) = fake_server(|conn| conn.take(6));
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let client = connected_client(&gridborg_rs, port);
+
+            let none = py.None();
+            let audio_send = |source: u32, sink: u32| {
+                client.call_method1(
+                    "audio_send",
+                    (source, sink, &none, &none, -3i16, &none, &none, &none, &none, &none),
+                )
+            };
+            audio_send(1, 2).expect("audio_send failed");
+            audio_send(2, 1).expect("audio_send failed");
+            let err = audio_send(1, 2).expect_err("duplicate route accepted");
+            assert!(err.to_string().contains("already routed"), "{err}");
+
+            client.call_method1("reroute", (1u32, 2u32, 3u32)).expect("reroute failed");
+            client.call_method1("resource_delete", (2u32,)).expect("resource_delete failed");
+
+            let routes = client.getattr("routes").unwrap();
+            let links: Vec<(u32, u32)> = routes.call_method0("links").unwrap().extract().unwrap();
+            assert_eq!(links, [(1, 3)]);
+            let json: String = routes.call_method0("to_json").unwrap().extract().unwrap();
+            assert_eq!(json, "[{\"source\":1,\"sink\":3,\"volume\":-3}]");
+        });
+
+        let lines = server.join().unwrap();
+        assert_eq!(
+            lines,
+            [
+                "AudioSend 1 2 Volume=-3 COMMANDTAG=0",
+                "AudioSend 2 1 Volume=-3 COMMANDTAG=1",
+                "AudioSend 1 3 Volume=-3 COMMANDTAG=2",
+                "AudioCancel 1 2 COMMANDTAG=3",
+                "AudioCancel 2 1 COMMANDTAG=4",
+                "ResourceDelete 2 COMMANDTAG=5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_call_creates_and_routes_recorder() {
+        init_python();
+
+        let (port, server) = fake_server(|mut conn| {
+            conn.expect("ResourceCreateRecorder COMMANDTAG=0", "ResourceCreated 7 COMMANDTAG=0\n");
+            conn.expect("ResourceCreatePlayer COMMANDTAG=1", "ResourceCreated 8 COMMANDTAG=1\n");
+            conn.take(6)
+        });
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let client = connected_client(&gridborg_rs, port);
+
+            let recording = client
+                .call_method1("record_call", (1u32, 2u32, "call.wav", true))
+                .expect("record_call failed");
+            let recorder: u32 = recording.getattr("recorder").unwrap().extract().unwrap();
+            assert_eq!(recorder, 7);
+
+            let event = client.call_method1("parse_event", ("ECallCleared 1 2 Normal",)).unwrap();
+            let commands = recording.call_method1("handle_event", (event,)).unwrap();
+            let stop: Vec<String> = commands
+                .try_iter()
+                .unwrap()
+                .map(|c| c.unwrap().str().unwrap().to_string())
+                .collect();
+            assert_eq!(stop, ["RecorderStop 7"]);
+            let cleared_by: Option<u32> = recording
+                .getattr("metadata")
+                .and_then(|m| m.getattr("cleared_by"))
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(cleared_by, Some(2));
+        });
+
+        let lines = server.join().unwrap();
+        assert_eq!(lines[0], "AudioSend 1 7 SinkChannel=1 COMMANDTAG=2");
+        assert_eq!(lines[2], "RecorderStartToFile 7 call.wav Channels=2 COMMANDTAG=4");
+        assert!(lines[5].starts_with("PlayTone 8 Tone="), "{}", lines[5]);
+    }
+
+    #[test]
+    fn test_record_call_deletes_recorder_after_errors() {
+        init_python();
+
+        let (port, server) = fake_server(|mut conn| {
+            conn.expect("ResourceCreateRecorder COMMANDTAG=0", "ResourceCreated 7 COMMANDTAG=0\n");
+            conn.expect("ResourceCreatePlayer COMMANDTAG=1", "Error \"no player licence\" COMMANDTAG=1\n");
+            conn.take(1)
+        });
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let client = connected_client(&gridborg_rs, port);
+
+            client
+                .call_method1("record_call", (1u32, 2u32, "call.wav", true))
+                .expect_err("missing player ignored");
+        });
+
+        assert_eq!(server.join().unwrap(), ["ResourceDelete 7 COMMANDTAG=2"]);
+    }
+
+    #[test]
+    fn test_unanswered_resource_create_times_out() {
+        init_python();
+
+        // the connection stays open until the client has given up
+        let (done, wait) = std::sync::mpsc::channel::<()>();
+        let (port, server) = fake_server(move |mut conn| {
+            let line = conn.lines.next().unwrap().unwrap();
+            let _ = wait.recv();
+            line
+        });
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let client = connected_client(&gridborg_rs, port);
+
+            let err = client
+                .call_method1("record_call", (1u32, 2u32, "call.wav", false))
+                .expect_err("missing reply ignored");
+            assert!(err.is_instance_of::<pyo3::exceptions::PyTimeoutError>(py), "{err}");
+            assert!(err.to_string().contains("No reply to ResourceCreateRecorder (command 0)"), "{err}");
+        });
+
+        done.send(()).unwrap();
+        assert_eq!(server.join().unwrap(), "ResourceCreateRecorder COMMANDTAG=0");
+    }
+
+    #[test]
+    fn test_send_fax_runs_job_to_report() {
+        init_python();
+
+        let (port, server) = fake_server(|mut conn| {
+            conn.expect("ResourceCreateFrontEnd", "ResourceCreated 1 COMMANDTAG=0\n");
+            conn.expect("ResourceCreateFax", "ResourceCreated 2 COMMANDTAG=1\n");
+            conn.expect("ResourceCreateDocument", "ResourceCreated 3 COMMANDTAG=2\n");
+            conn.expect("DocumentAddFile 3 page.tif", "");
+            conn.expect("DocumentPrepare 3", "EDocumentPrepared 1 3\n");
+            conn.expect("CallMake 1 5550100", "ECallConnectionEstablished 1 1\n");
+            conn.expect(
+                "FaxSend 2 1 3",
+                "EFaxOperationsStarted 1 2\nEFacsimilePageStarted 1 2 V29At9600 A4 Low 0\n\
+                 EFacsimilePageSent 1 2\nEFaxOperationFinished 1 2\n",
+            );
+            conn.take(4)
+        });
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let client = connected_client(&gridborg_rs, port);
+
+            let progress = pyo3::types::PyList::empty(py);
+            let report = client
+                .call_method1(
+                    "send_fax",
+                    (
+                        "5550100",
+                        vec!["page.tif"],
+                        fax_send_options(&gridborg_rs),
+                        progress.getattr("append").unwrap(),
+                    ),
+                )
+                .expect("send_fax failed");
+            let success: bool = report.getattr("success").unwrap().extract().unwrap();
+            let pages_sent: u32 = report.getattr("pages_sent").unwrap().extract().unwrap();
+            assert!(success);
+            assert_eq!(pages_sent, 1);
+
+            let states: Vec<String> = progress
+                .iter()
+                .map(|p| p.getattr("state").unwrap().extract().unwrap())
+                .collect();
+            assert_eq!(states.first().map(String::as_str), Some("preparing"));
+            assert_eq!(states.last().map(String::as_str), Some("completed"));
+        });
+
+        let cleanup = server.join().unwrap();
+        assert_eq!(cleanup[0], "CallClear 1 COMMANDTAG=7");
+        assert_eq!(cleanup[3], "ResourceDelete 1 COMMANDTAG=10");
+    }
+
+    #[test]
+    fn test_serve_fax_saves_received_document() {
+        init_python();
+
+        let (port, server) = fake_server(|mut conn| {
+            conn.expect("ResourceCreateFrontEnd Accepting=1", "ResourceCreated 4 COMMANDTAG=0\n");
+            conn.expect("ResourceCreateFax", "ResourceCreated 5 COMMANDTAG=1\n");
+            conn.expect(
+                "ResourceCreateDocument",
+                "ResourceCreated 6 COMMANDTAG=2\nECallIncoming 1 4 c1 ANI=5550100 DNIS=42\n",
+            );
+            conn.expect("CallAnswer 4", "EFaxIncoming 1 5\n");
+            conn.expect(
+                "FaxReceive 5 4 6",
+                "EFacsimilePageReceived 1 5\nEFaxOperationFinished 1 5\n",
+            );
+            conn.expect("DocumentSave 6 inbox/", "EDocumentSaved 1 6\n");
+            conn.take(5)
+        });
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let client = connected_client(&gridborg_rs, port);
+
+            let options = gridborg_rs
+                .getattr("fax")
+                .and_then(|m| m.getattr("FaxReceiveOptions"))
+                .and_then(|c| c.call1(("inbox",)))
+                .unwrap();
+            let saved = pyo3::types::PyList::empty(py);
+            let globals = [("saved", &saved)].into_py_dict(py).unwrap();
+            let callback = py
+                .eval(
+                    pyo3::ffi::c_str!("lambda path, fax: saved.append((path, len(fax.pages)))"),
+                    Some(&globals),
+                    None,
+                )
+                .unwrap();
+            let faxes = client
+                .call_method1("serve_fax", (options, callback, 1, 1, 5000))
+                .expect("serve_fax failed");
+            assert_eq!(faxes.len().unwrap(), 1);
+
+            let (path, pages): (String, usize) = saved.get_item(0).unwrap().extract().unwrap();
+            assert!(path.starts_with("inbox/") && path.ends_with("_5550100_42.tif"), "{path}");
+            assert_eq!(pages, 1);
+        });
+
+        let rest = server.join().unwrap();
+        assert_eq!(rest[0], "CallClear 4 COMMANDTAG=6");
+        assert_eq!(rest[1], "DocumentClear 6 COMMANDTAG=7");
+        assert_eq!(rest[4], "ResourceDelete 4 COMMANDTAG=10");
+    }
+
+    #[test]
+    fn test_send_fax_against_mock_server() {
+        init_python();
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let server = mock_server(&gridborg_rs, Some(("user1", "abc")));
+            let client = connected_client(&gridborg_rs, server.getattr("port").unwrap().extract().unwrap());
+            client.call_method0("login").expect("login failed");
+
+            // a call on a line the application created itself is not the fax job's; the server
+            // registers the connection on its own thread, so it may not reach the client at once
+            let call = "ECallIncoming 1 99 c9 ANI=1 DNIS=2";
+            while server.call_method1("emit", (call,)).unwrap().extract::<usize>().unwrap() == 0 {
+                thread::sleep(std::time::Duration::from_millis(10));
+            }
+            let options = fax_send_options(&gridborg_rs);
+            let report = client
+                .call_method1("send_fax", ("5550100", vec!["a.tif", "b.tif"], &options, py.None()))
+                .expect("send_fax failed");
+            let unhandled: Vec<String> = client.call_method0("take_unhandled").unwrap().extract().unwrap();
+            assert!(unhandled.iter().any(|line| line == call), "{unhandled:?}");
+            assert!(!unhandled.iter().any(|line| line.starts_with("EFax")), "{unhandled:?}");
+            let success: bool = report.getattr("success").unwrap().extract().unwrap();
+            let pages_sent: u32 = report.getattr("pages_sent").unwrap().extract().unwrap();
+            assert!(success);
+            assert_eq!(pages_sent, 2);
+
+            server.call_method1("fail", ("CallMake", "EndedByRemoteBusy")).unwrap();
+            let report = client
+                .call_method1("send_fax", ("5550100", vec!["a.tif"], &options, py.None()))
+                .expect("send_fax failed");
+            let success: bool = report.getattr("success").unwrap().extract().unwrap();
+            let failure: Option<String> = report.getattr("failure").unwrap().extract().unwrap();
+            assert!(!success);
+            assert!(failure.unwrap().contains("EndedByRemoteBusy"));
+
+            server.call_method1("fail", ("DocumentAddFile", "file not found")).unwrap();
+            let report = client
+                .call_method1("send_fax", ("5550100", vec!["missing.tif"], &options, py.None()))
+                .expect("send_fax failed");
+            let success: bool = report.getattr("success").unwrap().extract().unwrap();
+            let failure: Option<String> = report.getattr("failure").unwrap().extract().unwrap();
+            assert!(!success);
+            assert!(failure.unwrap().contains("file not found"));
+
+            // the resources of the job refused a file are deleted, after send_fax has returned
+            let deleted = |received: &[String]| received.iter().filter(|line| line.starts_with("ResourceDelete ")).count();
+            let mut received: Vec<String> = Vec::new();
+            for _ in 0..100 {
+                received = server.getattr("received").unwrap().extract().unwrap();
+                if deleted(&received) == 9 {
+                    break;
+                }
+                thread::sleep(std::time::Duration::from_millis(20));
+            }
+            assert_eq!(deleted(&received), 9, "{received:?}");
+            assert_eq!(received[0], "Login user1 abc 2 3");
+            assert!(received.iter().any(|line| line.starts_with("FaxSend ")), "{received:?}");
+            server.call_method0("stop").unwrap();
+        });
+    }
+
+    #[test]
+    fn test_fax_send_options_refuse_bad_header() {
+        init_python();
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let class = gridborg_rs.getattr("fax").and_then(|m| m.getattr("FaxSendOptions")).unwrap();
+
+            let kwargs = [("header", "{date} p{page}")].into_py_dict(py).unwrap();
+            let options = class.call((), Some(&kwargs)).expect("valid header refused");
+            let template: String = options.getattr("header").and_then(|h| h.getattr("template")).unwrap().extract().unwrap();
+            assert_eq!(template, "{date} p{page}");
+
+            let kwargs = [("header", "{fax}")].into_py_dict(py).unwrap();
+            let err = class.call((), Some(&kwargs)).expect_err("unknown placeholder accepted");
+            assert!(err.is_instance_of::<pyo3::exceptions::PyValueError>(py), "{err}");
+        });
+    }
+
+    #[test]
+    fn test_send_fax_cleans_up_after_errors() {
+        init_python();
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let server = mock_server(&gridborg_rs, None);
+            let client = connected_client(&gridborg_rs, server.getattr("port").unwrap().extract().unwrap());
+            let options = fax_send_options(&gridborg_rs);
+            // the server handles the cleanup after send_fax has returned
+            let wait_for = |line: &str| {
+                for _ in 0..100 {
+                    let received: Vec<String> = server.getattr("received").unwrap().extract().unwrap();
+                    if received.iter().any(|r| r == line) {
+                        return received;
+                    }
+                    thread::sleep(std::time::Duration::from_millis(20));
+                }
+                panic!("{line} not received");
+            };
+
+            // the progress callback raises once the call is up
+            let callback = py
+                .eval(
+                    pyo3::ffi::c_str!("lambda p: 1 / 0 if p.state == 'connected' else None"),
+                    None,
+                    None,
+                )
+                .unwrap();
+            let err = client
+                .call_method1("send_fax", ("5550100", vec!["a.tif"], &options, callback))
+                .expect_err("callback error swallowed");
+            assert!(err.is_instance_of::<pyo3::exceptions::PyZeroDivisionError>(py), "{err}");
+            let received = wait_for("ResourceDelete 1");
+            let cleanup: Vec<&str> = received.iter().map(String::as_str).skip_while(|l| !l.starts_with("CallClear")).collect();
+            assert_eq!(cleanup, ["CallClear 1", "ResourceDelete 3", "ResourceDelete 2", "ResourceDelete 1"]);
+
+            // the front-end is deleted when the fax resource cannot be created
+            server.call_method1("fail", ("ResourceCreateFax", "no fax licence")).unwrap();
+            let err = client
+                .call_method1("send_fax", ("5550100", vec!["a.tif"], &options, py.None()))
+                .expect_err("missing fax resource ignored");
+            assert!(err.to_string().contains("no fax licence"), "{err}");
+            let received = wait_for("ResourceDelete 4");
+            assert_eq!(received.last().map(String::as_str), Some("ResourceDelete 4"));
+            server.call_method0("stop").unwrap();
+        });
+    }
+
+    #[test]
+    fn test_replay_recorded_session() {
+        init_python();
+
+        Python::with_gil(|py| {
+            let gridborg_rs = py.import("gridborg_rs").expect("import gridborg failed");
+            let path = std::env::temp_dir().join(format!("gridborg-session-{}.trace", std::process::id()));
+            let path = path.to_str().unwrap();
+
+            let send_fax = |port: u16| {
+                let client = connected_client(&gridborg_rs, port);
+                client.call_method1("record_trace", (path,)).expect("record_trace failed");
+                client.call_method0("login").expect("login failed");
+                let report = client
+                    .call_method1("send_fax", ("5550100", vec!["a.tif"], fax_send_options(&gridborg_rs), py.None()))
+                    .expect("send_fax failed");
+                client.call_method0("stop_trace").unwrap();
+                let success: bool = report.getattr("success").unwrap().extract().unwrap();
+                let pages_sent: u32 = report.getattr("pages_sent").unwrap().extract().unwrap();
+                (success, pages_sent)
+            };
+
+            let server = mock_server(&gridborg_rs, Some(("user1", "abc")));
+            let recorded = send_fax(server.getattr("port").unwrap().extract().unwrap());
+            server.call_method0("stop").unwrap();
+            assert_eq!(recorded, (true, 1));
+
+            let trace_module = gridborg_rs.getattr("trace").unwrap();
+            let trace = trace_module
+                .getattr("SessionTrace")
+                .and_then(|c| c.call_method1("load", (path,)))
+                .expect("failed to load trace");
+            let sent: Vec<String> = trace.call_method0("sent").unwrap().extract().unwrap();
+            assert_eq!(sent[0], "Login user1 *** 2 3 COMMANDTAG=0");
+            let events = trace.call_method0("events").unwrap();
+            assert!(events.len().unwrap() > 0);
+
+            let replay = trace_module
+                .getattr("ReplayServer")
+                .and_then(|c| c.call1((&trace,)))
+                .expect("failed to start ReplayServer");
+            let replayed = send_fax(replay.getattr("port").unwrap().extract().unwrap());
+            assert_eq!(replayed, recorded);
+            let finished: bool = replay.call_method0("wait").unwrap().extract().unwrap();
+            let mismatch: Option<(usize, String, String)> = replay.getattr("mismatch").unwrap().extract().unwrap();
+            assert_eq!(mismatch, None);
+            assert!(finished);
+            replay.call_method0("stop").unwrap();
+            std::fs::remove_file(path).unwrap();
+        });
+    }
 }
+